@@ -1,11 +1,15 @@
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
 
-pub const BASE_COLOR: Color = Color::DARK_GREEN;
 pub const BASE_RADIUS: f32 = 30.;
-pub const BASE_INITIAL_HEALTH: f32 = 1000.;
+// number of cells around the origin covered by the base sprite
+pub const BASE_FOOTPRINT_CELLS: i32 = 1;
 
-#[derive(Component)]
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
 pub struct Base {
-    pub health: f32
+    pub health: f32,
+    // games saved before there was a maximum get it back from the difficulty when they are loaded
+    #[serde(default)]
+    pub max_health: f32
 }
\ No newline at end of file
@@ -0,0 +1,300 @@
+use bevy::{prelude::*, sprite::Mesh2dHandle};
+use serde::{Deserialize, Serialize};
+
+use super::{GameState, despawn_with_component};
+use crate::config::GameConfig;
+use crate::controls::Action;
+use crate::game::fall_off_damage_curve;
+use crate::tower::{PlacementPreview, Relocating, SelectedTowerKind, TowerStats};
+
+// Putting a tower down damages everything around it. How much depends on the blast profile of
+// the tower's kind, set in the game config. This plugin draws the reach of the blast around the
+// placement preview while the debug overlay is on.
+pub struct BlastPlugin;
+
+impl Plugin for BlastPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BlastOverlay>()
+        .add_system(toggle_blast_overlay.run_if(in_state(GameState::Game)))
+        .add_system(show_blast_overlay)
+        .add_system(despawn_with_component::<BlastRing>.in_schedule(OnExit(GameState::Game)));
+    }
+}
+
+// Share of the full damage where a blast without a maximum radius is drawn to end
+const OVERLAY_MIN_SHARE: f32 = 0.05;
+// Unbounded blasts are searched for their reach up to this distance
+const OVERLAY_MAX_DISTANCE: f32 = 10_000.;
+const OUTER_RING_COLOR: Color = Color::rgba(1., 0.5, 0., 0.15);
+const INNER_RING_COLOR: Color = Color::rgba(1., 0.1, 0., 0.25);
+
+// How the damage of a blast decreases with the distance from the tower
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Falloff {
+    // full damage up to `min_distance`, then falling off more slowly with a larger `steepness`
+    Logistic { min_distance: f32, steepness: f32 },
+    // full damage up to `min_distance`, then down to nothing at `zero_distance`
+    Linear { min_distance: f32, zero_distance: f32 },
+    // full damage up to `min_distance`, then divided by the square of the distance
+    InverseSquare { min_distance: f32 },
+    // rings of (outer distance, share of the damage), from the innermost out. Nothing is
+    // damaged beyond the last ring.
+    Step(Vec<(f32, f32)>),
+}
+
+impl Falloff {
+    // Falloffs whose damage would not decrease with the distance are rejected when the config is loaded
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Falloff::Linear { min_distance, zero_distance } if zero_distance <= min_distance =>
+                Err(format!("linear falloff ends at {} before it starts at {}", zero_distance, min_distance)),
+            Falloff::InverseSquare { min_distance } if *min_distance <= 0. =>
+                Err(format!("inverse-square falloff starts at {}, it has to start past the center", min_distance)),
+            Falloff::Step(rings) if rings.windows(2).any(|pair| pair[1].0 <= pair[0].0) =>
+                Err("step falloff rings are not sorted by distance".to_string()),
+            Falloff::Step(rings) if rings.iter().any(|(_, share)| !(0. ..=1.).contains(share)) =>
+                Err("step falloff shares have to be between 0 and 1".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    fn apply(&self, distance: f32, damage: f32) -> f32 {
+        match self {
+            Falloff::Logistic { min_distance, steepness } => fall_off_damage_curve(distance, damage, *min_distance, *steepness),
+            // a falloff ending where it starts drops straight to nothing instead of dividing by zero
+            Falloff::Linear { min_distance, zero_distance } => {
+                if distance <= *min_distance {
+                    damage
+                } else if zero_distance <= min_distance {
+                    0.
+                } else {
+                    damage * (1. - (distance - min_distance) / (zero_distance - min_distance)).clamp(0., 1.)
+                }
+            }
+            // the center gets the full damage even when the falloff starts right at it
+            Falloff::InverseSquare { min_distance } => {
+                if distance <= *min_distance {
+                    damage
+                } else {
+                    damage * (min_distance / distance).powi(2)
+                }
+            }
+            Falloff::Step(rings) => rings.iter()
+                .find(|(outer_distance, _)| distance < *outer_distance)
+                .map_or(0., |(_, share)| damage * share),
+        }
+    }
+}
+
+// Scales the damage of a blast for each kind of thing it hits
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TargetMultipliers {
+    pub tower: f32,
+    pub enemy: f32,
+    pub base: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlastTarget {
+    Tower,
+    Enemy,
+    Base,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlastProfile {
+    // damage at the center of the blast, before the target multipliers
+    pub damage: f32,
+    pub falloff: Falloff,
+    // nothing further than this is damaged, blasts without one reach across the whole map
+    #[serde(default)]
+    pub max_radius: Option<f32>,
+    pub multipliers: TargetMultipliers,
+}
+
+impl BlastProfile {
+    // Damage done to a target `distance` away from where the tower is put down
+    pub fn damage(&self, distance: f32, target: BlastTarget) -> f32 {
+        if self.max_radius.is_some_and(|max_radius| distance > max_radius) {
+            return 0.;
+        }
+        let multiplier = match target {
+            BlastTarget::Tower => self.multipliers.tower,
+            BlastTarget::Enemy => self.multipliers.enemy,
+            BlastTarget::Base => self.multipliers.base,
+        };
+        self.falloff.apply(distance, self.damage * multiplier)
+    }
+
+    // Share of the full damage done `distance` away, regardless of the target
+    fn share(&self, distance: f32) -> f32 {
+        if self.max_radius.is_some_and(|max_radius| distance > max_radius) {
+            0.
+        } else {
+            self.falloff.apply(distance, 1.)
+        }
+    }
+
+    // Distance at which the damage falls to `share` of the full damage. Every falloff only
+    // decreases with the distance, so it can be searched by bisection.
+    pub fn radius_at(&self, share: f32) -> f32 {
+        let (mut inside, mut outside) = (0., self.max_radius.unwrap_or(OVERLAY_MAX_DISTANCE));
+        if self.share(outside) >= share {
+            return outside;
+        }
+        for _ in 0..32 {
+            let middle = (inside + outside) / 2.;
+            if self.share(middle) >= share {
+                inside = middle;
+            } else {
+                outside = middle;
+            }
+        }
+        inside
+    }
+}
+
+#[derive(Resource, Default)]
+struct BlastOverlay(bool);
+
+// Disc drawn around the placement preview, the outer one where the blast ends and the inner one
+// where it does half its damage
+#[derive(Component)]
+struct BlastRing {
+    share: f32,
+}
+
+fn toggle_blast_overlay(actions: Res<Input<Action>>, mut overlay: ResMut<BlastOverlay>) {
+    if actions.just_pressed(Action::ToggleBlastOverlay) {
+        overlay.0 = !overlay.0;
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn show_blast_overlay(
+    mut commands: Commands,
+    overlay: Res<BlastOverlay>,
+    config: Res<GameConfig>,
+    selected_kind: Res<SelectedTowerKind>,
+    preview_query: Query<&Transform, (With<PlacementPreview>, Without<BlastRing>)>,
+    relocating_query: Query<&TowerStats, With<Relocating>>,
+    mut ring_query: Query<(Entity, &BlastRing, &mut Transform)>,
+    mut ring_assets: Local<Option<(Mesh2dHandle, Handle<ColorMaterial>, Handle<ColorMaterial>)>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>
+) {
+    let Some(preview_transform) = preview_query.get_single().ok().filter(|_| overlay.0) else {
+        for (ring_entity, _, _) in ring_query.iter() {
+            commands.entity(ring_entity).despawn();
+        }
+        return;
+    };
+
+    // a picked up tower lands with the blast of its own kind
+    let kind = relocating_query.get_single().map_or(selected_kind.0, |tower_stat| tower_stat.kind);
+    let profile = config.blasts.profile(kind);
+    let Vec2 { x, y } = preview_transform.translation.truncate();
+
+    if ring_query.is_empty() {
+        // the rings share a circle of radius 1 that is scaled to the size of the blast
+        let (mesh, outer_material, inner_material) = ring_assets.get_or_insert_with(|| (
+            meshes.add(shape::Circle::new(1.).into()).into(),
+            materials.add(ColorMaterial::from(OUTER_RING_COLOR)),
+            materials.add(ColorMaterial::from(INNER_RING_COLOR))
+        ));
+        for (share, material, z) in [(OVERLAY_MIN_SHARE, outer_material, 2.6), (0.5, inner_material, 2.7)] {
+            let radius = profile.radius_at(share);
+            commands.spawn((BlastRing { share },
+                ColorMesh2dBundle {
+                    mesh: mesh.clone(),
+                    material: material.clone(),
+                    transform: Transform::from_xyz(x, y, z).with_scale(Vec3::splat(radius)),
+                    ..default()
+                }));
+        }
+        return;
+    }
+
+    for (_, ring, mut ring_transform) in ring_query.iter_mut() {
+        ring_transform.translation.x = x;
+        ring_transform.translation.y = y;
+        ring_transform.scale = Vec3::splat(profile.radius_at(ring.share));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BlastProfile, BlastTarget, Falloff, TargetMultipliers};
+
+    fn profile(falloff: Falloff, max_radius: Option<f32>) -> BlastProfile {
+        BlastProfile {
+            damage: 100.,
+            falloff,
+            max_radius,
+            multipliers: TargetMultipliers { tower: 1., enemy: 1., base: 1. },
+        }
+    }
+
+    #[test]
+    fn check_logistic_falloff() {
+        let blast = profile(Falloff::Logistic { min_distance: 10., steepness: 4. }, None);
+        assert_eq!(blast.damage(5., BlastTarget::Tower), 100.);
+        assert!(blast.damage(50., BlastTarget::Tower) < blast.damage(20., BlastTarget::Tower));
+        assert!(blast.damage(1000., BlastTarget::Tower) > 0.);
+    }
+
+    #[test]
+    fn check_larger_steepness_falls_off_more_slowly() {
+        let shallow = profile(Falloff::Logistic { min_distance: 10., steepness: 6. }, None);
+        let steep = profile(Falloff::Logistic { min_distance: 10., steepness: 2. }, None);
+        assert!(shallow.damage(50., BlastTarget::Tower) > steep.damage(50., BlastTarget::Tower));
+    }
+
+    #[test]
+    fn check_linear_falloff() {
+        let blast = profile(Falloff::Linear { min_distance: 10., zero_distance: 30. }, None);
+        assert_eq!(blast.damage(10., BlastTarget::Tower), 100.);
+        assert_eq!(blast.damage(20., BlastTarget::Tower), 50.);
+        assert_eq!(blast.damage(40., BlastTarget::Tower), 0.);
+
+        let blast = profile(Falloff::Linear { min_distance: 10., zero_distance: 10. }, None);
+        assert_eq!(blast.damage(10., BlastTarget::Tower), 100.);
+        assert_eq!(blast.damage(10.5, BlastTarget::Tower), 0.);
+    }
+
+    #[test]
+    fn check_inverse_square_falloff() {
+        let blast = profile(Falloff::InverseSquare { min_distance: 30. }, None);
+        assert_eq!(blast.damage(30., BlastTarget::Tower), 100.);
+        assert_eq!(blast.damage(60., BlastTarget::Tower), 25.);
+
+        let blast = profile(Falloff::InverseSquare { min_distance: 0. }, None);
+        assert_eq!(blast.damage(0., BlastTarget::Tower), 100.);
+        assert_eq!(blast.damage(10., BlastTarget::Tower), 0.);
+    }
+
+    #[test]
+    fn check_step_falloff() {
+        let blast = profile(Falloff::Step(vec![(45., 1.), (90., 0.4)]), None);
+        assert_eq!(blast.damage(0., BlastTarget::Tower), 100.);
+        assert_eq!(blast.damage(60., BlastTarget::Tower), 40.);
+        assert_eq!(blast.damage(90., BlastTarget::Tower), 0.);
+    }
+
+    #[test]
+    fn check_max_radius_ends_the_blast() {
+        let blast = profile(Falloff::Logistic { min_distance: 10., steepness: 4. }, Some(50.));
+        assert!(blast.damage(50., BlastTarget::Tower) > 0.);
+        assert_eq!(blast.damage(51., BlastTarget::Tower), 0.);
+        assert_eq!(blast.radius_at(0.), 50.);
+    }
+
+    #[test]
+    fn check_multipliers_scale_each_target() {
+        let mut blast = profile(Falloff::Linear { min_distance: 10., zero_distance: 30. }, None);
+        blast.multipliers = TargetMultipliers { tower: 0.5, enemy: 2., base: 0. };
+        assert_eq!(blast.damage(20., BlastTarget::Tower), 25.);
+        assert_eq!(blast.damage(20., BlastTarget::Enemy), 100.);
+        assert_eq!(blast.damage(20., BlastTarget::Base), 0.);
+    }
+}
@@ -0,0 +1,278 @@
+use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use super::{GameState, despawn_with_component};
+use crate::base::Base;
+use crate::config::GameConfig;
+use crate::difficulty::MatchDifficulty;
+use crate::enemy::{EnemyBundle, EnemyStats};
+use crate::game::euclidean_distance;
+use crate::map::{Map, CELL_SIZE};
+use crate::season::{SeasonSchedule, SEASON_BAR_HEIGHT};
+use crate::simulation::{MatchStats, SimulationEvent, SimulationRng};
+use crate::tower::{TowerStats, withstands};
+
+// A match whose season schedule ends with a boss is only won once the boss is defeated. The boss
+// is simulated by the `SimulationPlugin`, this plugin draws it and its health bar.
+pub struct BossPlugin;
+
+impl Plugin for BossPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(add_boss_sprite)
+        .add_system(show_boss_health_bar)
+        .add_system(despawn_with_component::<BossHealthBar>.in_schedule(OnEnter(GameState::Menu)));
+    }
+}
+
+const BOSS_SPRITE: &str = "textures/rpg/mobs/boss_bee.png";
+// the sprite is 34 pixels wide
+pub const BOSS_SCALE: f32 = 3.;
+const HEALTH_BAR_HEIGHT: f32 = 16.;
+const HEALTH_BAR_BACKGROUND: Color = Color::rgba(0., 0., 0., 0.6);
+const HEALTH_BAR_COLOR: Color = Color::rgb(0.85, 0.15, 0.15);
+const PHASE_MARKER_COLOR: Color = Color::WHITE;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BossConfig {
+    pub health: f32,
+    pub speed: f32,
+    // the boss moves on to its next phase as its health drops below each of these shares
+    pub phases: Vec<f32>,
+    // enemies spawned around the boss when a phase breaks
+    pub minions_per_phase: u32,
+    // the boss speeds up by this factor with each phase
+    pub phase_speed_multiplier: f32,
+    // every `sting_interval_seconds` the boss damages the towers and the base within `sting_radius`
+    pub sting_interval_seconds: f32,
+    pub sting_radius: f32,
+    pub sting_damage: f32,
+}
+
+// Where the match is with its boss
+#[derive(Resource, Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BossStage {
+    #[default]
+    Waiting,
+    // the last season is over, the boss appears on the next step
+    Due,
+    Fighting,
+}
+
+#[derive(Component, Debug, Clone, Serialize, Deserialize)]
+pub struct Boss {
+    pub max_health: f32,
+    // number of phases already broken
+    pub phase: usize,
+    pub sting_cooldown: f32,
+}
+
+#[derive(Component)]
+struct BossHealthBar;
+
+#[derive(Component)]
+struct BossHealthFill;
+
+// The boss comes from the reachable cell furthest from the base
+pub fn spawn_boss(
+    mut commands: Commands,
+    mut boss_stage: ResMut<BossStage>,
+    match_difficulty: Res<MatchDifficulty>,
+    config: Res<GameConfig>,
+    map_query: Query<&Map>
+) {
+    if *boss_stage != BossStage::Due {
+        return;
+    }
+    let (Some(boss_config), Ok(map)) = (&config.boss, map_query.get_single()) else {
+        return;
+    };
+
+    // ties are broken by the coordinates, the cells of the map are not kept in a fixed order
+    let Some((&cell, &destination)) = map.came_from.iter()
+        .filter(|(cell, _)| !map.has_wall(cell))
+        .max_by(|(cell1, _), (cell2, _)| cell1.to_position().length().total_cmp(&cell2.to_position().length())
+            .then(cell1.x.cmp(&cell2.x))
+            .then(cell1.y.cmp(&cell2.y))) else {
+        return;
+    };
+
+    let difficulty = match_difficulty.0;
+    let modifiers = difficulty.modifiers(&config);
+    let Vec2 { x, y } = cell.to_position();
+    let health = boss_config.health * modifiers.enemy_count;
+    let mut enemy_bundle = EnemyBundle::new(x, y, destination.to_position(), difficulty, &config);
+    enemy_bundle.stats.health = health;
+    enemy_bundle.stats.speed = boss_config.speed * modifiers.enemy_speed;
+
+    info!("the boss appeared");
+    commands.spawn((enemy_bundle,
+        Boss { max_health: health, phase: 0, sting_cooldown: boss_config.sting_interval_seconds },
+        TransformBundle::from_transform(Transform::from_xyz(x, y, 1.5).with_scale(Vec3::splat(BOSS_SCALE)))));
+    *boss_stage = BossStage::Fighting;
+}
+
+// Breaks the phases of the boss as it loses health and stings what is around it
+#[allow(clippy::too_many_arguments)]
+pub fn update_boss(
+    mut commands: Commands,
+    fixed_time: Res<FixedTime>,
+    config: Res<GameConfig>,
+    match_difficulty: Res<MatchDifficulty>,
+    mut simulation_rng: ResMut<SimulationRng>,
+    mut match_stats: ResMut<MatchStats>,
+    mut boss_query: Query<(&mut Boss, &mut EnemyStats, &Transform)>,
+    mut towers_query: Query<(Entity, &mut TowerStats, &Transform)>,
+    mut base_query: Query<(&mut Base, &Transform)>,
+    mut simulation_events: EventWriter<SimulationEvent>
+) {
+    let Some(boss_config) = &config.boss else {
+        return;
+    };
+    let difficulty = match_difficulty.0;
+
+    for (mut boss, mut boss_stat, boss_transform) in boss_query.iter_mut() {
+        // shot down, the match is won at the end of the step
+        if boss_stat.health <= 0. {
+            continue;
+        }
+        let position = boss_transform.translation.truncate();
+
+        while boss.phase < boss_config.phases.len() && boss_stat.health <= boss_config.phases[boss.phase] * boss.max_health {
+            boss.phase += 1;
+            boss_stat.speed *= boss_config.phase_speed_multiplier;
+            simulation_events.send(SimulationEvent::BossPhaseBroken { position });
+
+            // minions follow the path of the boss, starting around it
+            let minions = (boss_config.minions_per_phase as f32 * difficulty.modifiers(&config).enemy_count).round() as u32;
+            for _ in 0..minions {
+                let x = position.x + simulation_rng.gen_range((-CELL_SIZE)..CELL_SIZE);
+                let y = position.y + simulation_rng.gen_range((-CELL_SIZE)..CELL_SIZE);
+                commands.spawn((
+                    EnemyBundle::new(x, y, boss_stat.destination, difficulty, &config),
+                    TransformBundle::from_transform(Transform::from_xyz(x, y, 1.).with_scale(Vec3::splat(0.075)))));
+            }
+        }
+
+        boss.sting_cooldown -= fixed_time.period.as_secs_f32();
+        if boss.sting_cooldown > 0. {
+            continue;
+        }
+        boss.sting_cooldown = boss_config.sting_interval_seconds;
+        simulation_events.send(SimulationEvent::BossSting { position });
+
+        let damage = boss_config.sting_damage * difficulty.modifiers(&config).enemy_damage;
+        for (tower_entity, mut tower_stat, tower_transform) in towers_query.iter_mut() {
+            let distance = euclidean_distance(position.x, position.y, tower_transform.translation.x, tower_transform.translation.y);
+            if distance >= boss_config.sting_radius {
+                continue;
+            }
+            if withstands(tower_stat.health, damage) {
+                tower_stat.health -= damage;
+            } else {
+                info!("tower despawned");
+                match_stats.towers_lost += 1;
+                simulation_events.send(SimulationEvent::TowerDestroyed { position: tower_transform.translation.truncate() });
+                commands.entity(tower_entity).despawn();
+            }
+        }
+
+        for (mut base, base_transform) in base_query.iter_mut() {
+            let distance = euclidean_distance(position.x, position.y, base_transform.translation.x, base_transform.translation.y);
+            if distance < boss_config.sting_radius {
+                base.health -= damage;
+            }
+        }
+    }
+}
+
+pub fn check_boss_defeated(
+    boss_stage: Res<BossStage>,
+    boss_query: Query<&EnemyStats, With<Boss>>,
+    mut season_schedule: ResMut<SeasonSchedule>,
+    mut game_state: ResMut<NextState<GameState>>
+) {
+    if *boss_stage == BossStage::Fighting && boss_query.iter().any(|boss_stat| boss_stat.health <= 0.) {
+        info!("THE BOSS HAS BEEN DEFEATED!");
+        season_schedule.finish();
+        game_state.set(GameState::GameWon);
+    }
+}
+
+fn add_boss_sprite(mut commands: Commands,
+    boss_query: Query<Entity, Added<Boss>>,
+    asset_server: Res<AssetServer>) {
+
+    for boss_entity in boss_query.iter() {
+        commands.entity(boss_entity).insert((
+            asset_server.load::<Image, _>(BOSS_SPRITE),
+            Sprite::default(),
+            VisibilityBundle::default()));
+    }
+}
+
+// The bar runs across the top of the window, under the season bar, with a mark where each phase breaks
+fn show_boss_health_bar(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    boss_query: Query<(&Boss, &EnemyStats)>,
+    bar_query: Query<Entity, With<BossHealthBar>>,
+    mut fill_query: Query<&mut Style, With<BossHealthFill>>
+) {
+    let Ok((boss, boss_stat)) = boss_query.get_single() else {
+        for bar_entity in bar_query.iter() {
+            commands.entity(bar_entity).despawn_recursive();
+        }
+        return;
+    };
+
+    let share = (boss_stat.health / boss.max_health).clamp(0., 1.) * 100.;
+    if !bar_query.is_empty() {
+        for mut style in fill_query.iter_mut() {
+            style.size.width = Val::Percent(share);
+        }
+        return;
+    }
+
+    let phases = config.boss.as_ref().map_or(&[][..], |boss_config| &boss_config.phases[..]);
+    // like the season bar, it keeps the clicks on it from reaching the map
+    commands.spawn((NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Percent(10.),
+                top: Val::Px(SEASON_BAR_HEIGHT * 2.),
+                ..default()
+            },
+            size: Size::new(Val::Percent(80.), Val::Px(HEALTH_BAR_HEIGHT)),
+            ..default()
+        },
+        background_color: HEALTH_BAR_BACKGROUND.into(),
+        ..default()
+    }, Interaction::default(), BossHealthBar)).with_children(|parent| {
+        parent.spawn((NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(share), Val::Percent(100.)),
+                ..default()
+            },
+            background_color: HEALTH_BAR_COLOR.into(),
+            ..default()
+        }, BossHealthFill));
+
+        for &phase in phases {
+            parent.spawn(NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        left: Val::Percent(phase * 100.),
+                        ..default()
+                    },
+                    size: Size::new(Val::Px(2.), Val::Percent(100.)),
+                    ..default()
+                },
+                background_color: PHASE_MARKER_COLOR.into(),
+                ..default()
+            });
+        }
+    });
+}
@@ -1,21 +1,22 @@
-use bevy::{
-    prelude::*,
-    sprite::MaterialMesh2dBundle,
-    input::mouse::{MouseButtonInput, MouseMotion, MouseWheel}, window::PrimaryWindow
-};
+use bevy::{prelude::*, sprite::Mesh2dHandle};
 use crate::enemy::EnemyStats;
+use crate::tower::TowerState;
+use crate::simulation::{MatchStats, SimulationEvent};
 
+// Bullets are simulated by the `SimulationPlugin`, this plugin only draws them
 pub struct BulletPlugin;
 
 impl Plugin for BulletPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(move_bullets);
+        app.add_system(add_bullet_mesh);
     }
 }
 
 #[derive(Component)]
 pub struct Bullet {
     pub target: Entity,
+    // tower that fired the bullet, credited with its damage and kills
+    pub source: Entity,
     pub damage: f32,
     pub speed: f32
 }
@@ -26,30 +27,25 @@ pub const BULLET_COLOR: Color = Color::WHITE;
 
 pub fn move_bullets(
     mut commands: Commands,
-    time: Res<Time>, 
+    fixed_time: Res<FixedTime>, 
     mut bullet_query: Query<(Entity, &Bullet, &mut Transform)>, 
-    mut enemy_query: Query<(Entity, &mut EnemyStats, &Transform), Without<Bullet>>) {
+    enemy_query: Query<&Transform, (With<EnemyStats>, Without<Bullet>)>) {
 
     for (bullet_entity, bullet, mut transform) in bullet_query.iter_mut() {
-        if let Ok((target_entity, mut target_stats, target_transform)) = enemy_query.get_mut(bullet.target) {
+        if let Ok(target_transform) = enemy_query.get(bullet.target) {
             let dist = transform
             .translation
             .truncate()
             .distance(target_transform.translation.truncate());
 
             if dist > BULLET_RADIUS {
-                let delta = time.delta_seconds();
-                let step = bullet.speed * delta;
+                let delta = fixed_time.period.as_secs_f32();
+                // never step past the target, a fast bullet would otherwise overshoot and miss it
+                let step = (bullet.speed * delta).min(dist);
                 transform.translation.x +=
                         step / dist * (target_transform.translation.x - transform.translation.x);
                 transform.translation.y +=
                         step / dist * (target_transform.translation.y - transform.translation.y);
-            } else {
-                target_stats.health -= bullet.damage;
-                if target_stats.health <= 0. {
-                    commands.entity(target_entity).despawn();
-                }
-                commands.entity(bullet_entity).despawn();
             }
 
         } else {
@@ -57,3 +53,64 @@ pub fn move_bullets(
         }
     }
 }
+
+pub fn resolve_bullet_hits(
+    mut commands: Commands,
+    bullet_query: Query<(Entity, &Bullet, &Transform)>, 
+    mut enemy_query: Query<(Entity, &mut EnemyStats, &Transform), Without<Bullet>>,
+    mut tower_query: Query<&mut TowerState>,
+    mut match_stats: ResMut<MatchStats>,
+    mut simulation_events: EventWriter<SimulationEvent>) {
+
+    for (bullet_entity, bullet, transform) in bullet_query.iter() {
+        let Ok((target_entity, mut target_stats, target_transform)) = enemy_query.get_mut(bullet.target) else {
+            continue;
+        };
+
+        let dist = transform
+            .translation
+            .truncate()
+            .distance(target_transform.translation.truncate());
+
+        if dist <= BULLET_RADIUS {
+            // several bullets can land on the same enemy before it is despawned
+            let was_alive = target_stats.health > 0.;
+            let dealt = bullet.damage.min(target_stats.health.max(0.));
+            target_stats.health -= bullet.damage;
+            let killed = was_alive && target_stats.health <= 0.;
+            if let Ok(mut tower_state) = tower_query.get_mut(bullet.source) {
+                tower_state.damage_dealt += dealt;
+                if killed {
+                    tower_state.kills += 1;
+                }
+            }
+            if !killed {
+                simulation_events.send(SimulationEvent::EnemyHit { position: target_transform.translation.truncate() });
+            }
+            if killed {
+                match_stats.kills += 1;
+                simulation_events.send(SimulationEvent::EnemyKilled { position: target_transform.translation.truncate() });
+                commands.entity(target_entity).despawn();
+            }
+            commands.entity(bullet_entity).despawn();
+        }
+    }
+}
+
+// All bullets share one mesh and material
+fn add_bullet_mesh(
+    mut commands: Commands,
+    bullet_query: Query<Entity, Added<Bullet>>,
+    mut bullet_assets: Local<Option<(Mesh2dHandle, Handle<ColorMaterial>)>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>) {
+
+    let (mesh, material) = bullet_assets.get_or_insert_with(|| (
+        meshes.add(shape::Circle::new(BULLET_RADIUS).into()).into(),
+        materials.add(ColorMaterial::from(BULLET_COLOR))
+    ));
+
+    for bullet_entity in bullet_query.iter() {
+        commands.entity(bullet_entity).insert((mesh.clone(), material.clone(), VisibilityBundle::default()));
+    }
+}
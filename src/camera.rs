@@ -0,0 +1,155 @@
+use bevy::{
+    prelude::*,
+    input::mouse::{MouseScrollUnit, MouseWheel},
+    render::camera::{CameraProjection, ScalingMode},
+    window::PrimaryWindow,
+};
+
+use super::{GameState, new_game_schedule};
+use crate::controls::{Action, Pointer};
+use crate::map::{Map, WORLD_HEIGHT, WORLD_WIDTH};
+
+// This plugin owns the camera. The world has a fixed logical size and the camera scales it to
+// fit the window whatever its size or shape, the leftover space on the longer side staying
+// empty like a letterbox. The window can be resized at any time, even in the middle of a game.
+// Maps larger than the world are scrolled with the pan actions or by pointing at the edges of
+// the window, and the mouse wheel zooms. The camera never leaves the map.
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(spawn_camera)
+        .add_system(reset_camera.in_schedule(new_game_schedule()))
+        .add_systems((pan_camera, zoom_camera)
+            .distributive_run_if(in_state(GameState::Game))
+            .before(clamp_camera_to_map))
+        // a resized window can show more of the map at any time
+        .add_system(clamp_camera_to_map);
+    }
+}
+
+// Speed of the camera at the default zoom, in world units per second
+const PAN_SPEED: f32 = 800.;
+// Distance from the edges of the window at which the pointer scrolls the map, in pixels
+const EDGE_SCROLL_MARGIN: f32 = 10.;
+// Smallest projection scale, the largest one shows the whole map
+const MIN_ZOOM: f32 = 0.5;
+// Zoom change for every line of the mouse wheel
+const ZOOM_STEP: f32 = 0.1;
+// Pixels scrolled by a touchpad that count as one line of a mouse wheel
+const PIXELS_PER_LINE: f32 = 100.;
+
+// The camera showing the world, the only one in the app
+#[derive(Component)]
+pub struct MainCamera;
+
+fn spawn_camera(mut commands: Commands) {
+    let mut camera = Camera2dBundle::default();
+    camera.projection.scaling_mode = ScalingMode::AutoMin {
+        min_width: WORLD_WIDTH,
+        min_height: WORLD_HEIGHT,
+    };
+    commands.spawn((camera, MainCamera));
+}
+
+// Every match starts centered on the base at the default zoom
+fn reset_camera(mut camera_query: Query<(&mut Transform, &mut OrthographicProjection), With<MainCamera>>) {
+    for (mut transform, mut projection) in camera_query.iter_mut() {
+        transform.translation.x = 0.;
+        transform.translation.y = 0.;
+        projection.scale = 1.;
+    }
+}
+
+fn pan_camera(
+    time: Res<Time>,
+    actions: Res<Input<Action>>,
+    pointer: Res<Pointer>,
+    primary_window_query: Query<&Window, With<PrimaryWindow>>,
+    mut camera_query: Query<(&mut Transform, &OrthographicProjection), With<MainCamera>>
+) {
+    let mut direction = Vec2::ZERO;
+    for (action, step) in [
+        (Action::PanUp, Vec2::Y),
+        (Action::PanDown, Vec2::NEG_Y),
+        (Action::PanLeft, Vec2::NEG_X),
+        (Action::PanRight, Vec2::X),
+    ] {
+        if actions.pressed(action) {
+            direction += step;
+        }
+    }
+
+    if let (Ok(window), Some(position)) = (primary_window_query.get_single(), pointer.position) {
+        if position.x < EDGE_SCROLL_MARGIN {
+            direction.x -= 1.;
+        } else if position.x > window.width() - EDGE_SCROLL_MARGIN {
+            direction.x += 1.;
+        }
+        if position.y < EDGE_SCROLL_MARGIN {
+            direction.y -= 1.;
+        } else if position.y > window.height() - EDGE_SCROLL_MARGIN {
+            direction.y += 1.;
+        }
+    }
+
+    if direction == Vec2::ZERO {
+        return;
+    }
+    // the camera moves as fast on screen at any zoom and game speed
+    for (mut transform, projection) in camera_query.iter_mut() {
+        let offset = direction.clamp_length_max(1.) * PAN_SPEED * projection.scale * time.raw_delta_seconds();
+        transform.translation += offset.extend(0.);
+    }
+}
+
+fn zoom_camera(
+    mut mouse_wheel_events: EventReader<MouseWheel>,
+    map_query: Query<&Map>,
+    mut camera_query: Query<&mut OrthographicProjection, With<MainCamera>>
+) {
+    let lines: f32 = mouse_wheel_events.iter()
+        .map(|event| match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / PIXELS_PER_LINE,
+        })
+        .sum();
+    if lines == 0. {
+        return;
+    }
+    let Ok(map) = map_query.get_single() else {
+        return;
+    };
+
+    let map_size = map.world_rect().size();
+    let max_zoom = (map_size.x / WORLD_WIDTH).max(map_size.y / WORLD_HEIGHT).max(1.);
+    for mut projection in camera_query.iter_mut() {
+        // scrolling up zooms in
+        let scale = projection.scale * (1. - ZOOM_STEP).powf(lines);
+        projection.scale = scale.clamp(MIN_ZOOM, max_zoom);
+    }
+}
+
+// Keep the view inside the map, centered on it along the sides where the whole map fits
+pub fn clamp_camera_to_map(
+    map_query: Query<&Map>,
+    primary_window_query: Query<&Window, With<PrimaryWindow>>,
+    mut camera_query: Query<(&mut Transform, &mut OrthographicProjection), With<MainCamera>>
+) {
+    let (Ok(map), Ok(window)) = (map_query.get_single(), primary_window_query.get_single()) else {
+        return;
+    };
+
+    let map_rect = map.world_rect();
+    for (mut transform, mut projection) in camera_query.iter_mut() {
+        // the area of the projection only follows the window and the zoom at the end of the frame
+        projection.update(window.width(), window.height());
+        let half_view = projection.area.half_size();
+        let slack = (map_rect.half_size() - half_view).max(Vec2::ZERO);
+        let center = map_rect.center();
+        let position = transform.translation.truncate().clamp(center - slack, center + slack);
+        if position != transform.translation.truncate() {
+            transform.translation = position.extend(transform.translation.z);
+        }
+    }
+}
@@ -0,0 +1,279 @@
+use std::fs;
+
+use bevy::{
+    prelude::*,
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::blast::BlastProfile;
+use crate::boss::BossConfig;
+use crate::difficulty::DifficultyModifiers;
+use crate::heal::HealConsequence;
+use crate::records::ScoreConfig;
+use crate::replay::ReplayPlayback;
+use crate::season::Season;
+use crate::synergy::SynergyRule;
+use crate::tower::TowerKind;
+
+// This plugin loads the balance of the game from `assets/config/game.config.ron` and reloads it
+// whenever the file changes. The simulation reads the `GameConfig` resource, which holds the
+// latest version of the file that could be parsed.
+pub struct ConfigPlugin;
+
+impl Plugin for ConfigPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<GameConfig>()
+        .init_asset_loader::<GameConfigLoader>()
+        .add_startup_system(load_game_config)
+        .add_system(apply_game_config);
+    }
+}
+
+const CONFIG_ASSET: &str = "config/game.config.ron";
+// Where headless runs read the config from, they have no asset server
+const CONFIG_FILE: &str = "assets/config/game.config.ron";
+// Built into the game, for when the file is missing or broken at startup
+const DEFAULT_CONFIG: &str = include_str!("../assets/config/game.config.ron");
+
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize, TypeUuid)]
+#[uuid = "5f3c8a6e-2b1d-4c7a-9e0f-8d4b6a2c1e73"]
+pub struct GameConfig {
+    pub tower: TowerConfig,
+    pub enemy: EnemyConfig,
+    pub base: BaseConfig,
+    pub heal: HealConfig,
+    pub blasts: BlastProfiles,
+    pub synergies: Vec<SynergyRule>,
+    pub seasons: SeasonConfig,
+    pub difficulties: DifficultyModifiers,
+    pub score: ScoreConfig,
+    // without a boss the match is won when the last season ends
+    #[serde(default)]
+    pub boss: Option<BossConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TowerConfig {
+    // nominal worth of a tower, selling one gives back a share of it
+    pub value: u32,
+    pub health: f32,
+    pub range: f32,
+    pub damage: f32,
+    pub bullet_speed: f32,
+    pub cooldown_seconds: f32,
+    pub upgrade_price: u32,
+    pub sell_refund: f32,
+    // seasons in which towers can be picked up and moved
+    pub relocation_seasons: Vec<Season>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnemyConfig {
+    pub health: f32,
+    pub speed: f32,
+    pub damage: f32,
+    pub spawn_interval_seconds: f32,
+    pub spawn_per_interval: u32,
+    pub spawn_min_distance: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BaseConfig {
+    pub health: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealConfig {
+    pub amount: f32,
+    // heals never take health above the starting health times this
+    pub max_health: f32,
+    pub charges_per_season: u32,
+    // a click heals everything within this distance, or only what is under it without one
+    #[serde(default)]
+    pub radius: Option<f32>,
+    pub consequence: HealConsequence,
+}
+
+// Blast of each kind of tower when it is put down
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlastProfiles {
+    pub turret: BlastProfile,
+    pub mortar: BlastProfile,
+    pub bunker: BlastProfile,
+    pub amplifier: BlastProfile,
+    pub supply: BlastProfile,
+}
+
+impl BlastProfiles {
+    fn all(&self) -> [(TowerKind, &BlastProfile); 5] {
+        [
+            (TowerKind::Turret, &self.turret),
+            (TowerKind::Mortar, &self.mortar),
+            (TowerKind::Bunker, &self.bunker),
+            (TowerKind::Amplifier, &self.amplifier),
+            (TowerKind::Supply, &self.supply),
+        ]
+    }
+
+    pub fn profile(&self, kind: TowerKind) -> &BlastProfile {
+        match kind {
+            TowerKind::Turret => &self.turret,
+            TowerKind::Mortar => &self.mortar,
+            TowerKind::Bunker => &self.bunker,
+            TowerKind::Amplifier => &self.amplifier,
+            TowerKind::Supply => &self.supply,
+        }
+    }
+}
+
+// Lengths are whole seconds, ranges are inclusive
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SeasonConfig {
+    pub first_build_seconds: u32,
+    pub pairs: (u32, u32),
+    pub build_seconds: (u32, u32),
+    pub heal_seconds: (u32, u32),
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        ron::from_str(DEFAULT_CONFIG).expect("the built-in game config is valid")
+    }
+}
+
+impl GameConfig {
+    // The config file as it is on disk, or the built-in one when there is no file
+    pub fn read_file() -> Result<GameConfig, String> {
+        let Ok(contents) = fs::read_to_string(CONFIG_FILE) else {
+            return Ok(GameConfig::default());
+        };
+        let config: GameConfig = ron::from_str(&contents)
+            .map_err(|error| format!("invalid game config {}: {}", CONFIG_FILE, error))?;
+        config.validate().map_err(|error| format!("invalid game config {}: {}", CONFIG_FILE, error))?;
+        Ok(config)
+    }
+
+    // Values that parse but would break the simulation
+    pub fn validate(&self) -> Result<(), String> {
+        if self.enemy.spawn_interval_seconds <= 0. {
+            return Err(format!("the enemy spawn interval {} is not positive", self.enemy.spawn_interval_seconds));
+        }
+        if self.enemy.speed < 0. {
+            return Err(format!("the enemy speed {} is negative", self.enemy.speed));
+        }
+        if self.tower.cooldown_seconds <= 0. {
+            return Err(format!("the tower cooldown {} is not positive", self.tower.cooldown_seconds));
+        }
+        if self.heal.max_health <= 0. {
+            return Err(format!("the maximum health {} is not positive", self.heal.max_health));
+        }
+        for (kind, profile) in self.blasts.all() {
+            profile.falloff.validate().map_err(|error| format!("{:?} blast: {}", kind, error))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct GameConfigLoader;
+
+impl AssetLoader for GameConfigLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let config: GameConfig = ron::de::from_bytes(bytes)?;
+            config.validate().map_err(bevy::asset::Error::msg)?;
+            load_context.set_default_asset(LoadedAsset::new(config));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["config.ron"]
+    }
+}
+
+// Kept so that the config stays loaded and is watched for changes
+#[derive(Resource)]
+struct GameConfigHandle(Handle<GameConfig>);
+
+fn load_game_config(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(GameConfigHandle(asset_server.load(CONFIG_ASSET)));
+}
+
+// A file that fails to parse or to validate is reported by the asset server and the previous
+// config stays. A replay plays back with the configs it recorded, the file is applied again once
+// it is over.
+fn apply_game_config(
+    mut config_events: EventReader<AssetEvent<GameConfig>>,
+    configs: Res<Assets<GameConfig>>,
+    config_handle: Res<GameConfigHandle>,
+    playback: Res<ReplayPlayback>,
+    mut game_config: ResMut<GameConfig>
+) {
+    let file_changed = config_events.iter().any(|event| matches!(event,
+        AssetEvent::Created { handle } | AssetEvent::Modified { handle } if *handle == config_handle.0));
+    let playback_ended = playback.is_changed() && !playback.is_active();
+    if !(file_changed || playback_ended) || playback.is_active() {
+        return;
+    }
+    if let Some(config) = configs.get(&config_handle.0) {
+        if *game_config != *config {
+            info!("game config loaded");
+            *game_config = config.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn check_configs_that_would_break_the_simulation_are_rejected() {
+        use super::GameConfig;
+        use crate::blast::Falloff;
+
+        assert_eq!(GameConfig::default().validate(), Ok(()));
+
+        let mut config = GameConfig::default();
+        config.enemy.spawn_interval_seconds = 0.;
+        assert!(config.validate().is_err());
+
+        let mut config = GameConfig::default();
+        config.blasts.amplifier.falloff = Falloff::Linear { min_distance: 5., zero_distance: 5. };
+        assert!(config.validate().is_err());
+
+        let mut config = GameConfig::default();
+        config.blasts.mortar.falloff = Falloff::InverseSquare { min_distance: 0. };
+        assert!(config.validate().is_err());
+
+        let mut config = GameConfig::default();
+        config.blasts.bunker.falloff = Falloff::Step(vec![(3., 1.), (2., 0.5)]);
+        assert!(config.validate().is_err());
+
+        let mut config = GameConfig::default();
+        config.blasts.bunker.falloff = Falloff::Step(vec![(2., 1.5), (3., 0.5)]);
+        assert!(config.validate().is_err());
+
+        let mut config = GameConfig::default();
+        config.blasts.bunker.falloff = Falloff::Step(vec![(2., 1.), (3., -0.5)]);
+        assert!(config.validate().is_err());
+
+        let mut config = GameConfig::default();
+        config.enemy.speed = -1.;
+        assert!(config.validate().is_err());
+
+        let mut config = GameConfig::default();
+        config.tower.cooldown_seconds = 0.;
+        assert!(config.validate().is_err());
+
+        let mut config = GameConfig::default();
+        config.heal.max_health = 0.;
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,477 @@
+use std::collections::BTreeMap;
+
+use bevy::{
+    prelude::*,
+    input::{InputSystem, gamepad::GamepadButtonType},
+    ui::UiSystem,
+    window::{CursorMoved, PrimaryWindow},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::camera::MainCamera;
+use crate::storage;
+
+// This plugin turns the raw keyboard, mouse and gamepad input into the player's actions, read
+// through `Res<Input<Action>>`. Every action can be rebound from the controls screen, and the
+// bindings are kept between launches. With a gamepad, the left stick moves a virtual cursor
+// that stands in for the mouse, also on the menu buttons.
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(load_bindings())
+        .init_resource::<Input<Action>>()
+        .init_resource::<Pointer>()
+        .init_resource::<Rebinding>()
+        .add_startup_system(spawn_virtual_cursor)
+        .add_systems((update_actions, update_pointer)
+            .in_base_set(CoreSet::PreUpdate)
+            .after(InputSystem))
+        .add_system(press_buttons_with_pointer
+            .in_base_set(CoreSet::PreUpdate)
+            .after(UiSystem::Focus)
+            .after(update_actions)
+            .after(update_pointer))
+        .add_system(capture_binding)
+        .add_system(save_bindings)
+        .add_system(show_virtual_cursor);
+    }
+}
+
+const BINDINGS_FILE: &str = "bindings.json";
+const STICK_DEAD_ZONE: f32 = 0.15;
+// Speed of the virtual cursor with the stick fully tilted, in pixels per second
+const VIRTUAL_CURSOR_SPEED: f32 = 600.;
+const VIRTUAL_CURSOR_SIZE: f32 = 12.;
+// Cancels a rebinding in progress
+const CANCEL_KEY: KeyCode = KeyCode::Escape;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    Place,
+    Heal,
+    Select,
+    Sell,
+    Relocate,
+    Pause,
+    SpeedUp,
+    CycleTowerType,
+    ToggleBlastOverlay,
+    PanUp,
+    PanDown,
+    PanLeft,
+    PanRight,
+}
+
+impl Action {
+    pub const ALL: [Action; 13] = [
+        Action::Place,
+        Action::Heal,
+        Action::Select,
+        Action::Sell,
+        Action::Relocate,
+        Action::Pause,
+        Action::SpeedUp,
+        Action::CycleTowerType,
+        Action::ToggleBlastOverlay,
+        Action::PanUp,
+        Action::PanDown,
+        Action::PanLeft,
+        Action::PanRight,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::Place => "Place",
+            Action::Heal => "Heal",
+            Action::Select => "Select",
+            Action::Sell => "Sell",
+            Action::Relocate => "Relocate",
+            Action::Pause => "Pause",
+            Action::SpeedUp => "Speed Up",
+            Action::CycleTowerType => "Tower Type",
+            Action::ToggleBlastOverlay => "Blast Overlay",
+            Action::PanUp => "Pan Up",
+            Action::PanDown => "Pan Down",
+            Action::PanLeft => "Pan Left",
+            Action::PanRight => "Pan Right",
+        }
+    }
+
+    // Placing and healing happen in different seasons, they can share a binding. Any other two
+    // actions bound together would both trigger.
+    fn shares_binding_with(self, other: Action) -> bool {
+        matches!((self, other), (Action::Place, Action::Heal) | (Action::Heal, Action::Place))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+// Every action can be bound to a key or a mouse button, and to a gamepad button
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActionBinding {
+    pub binding: Option<Binding>,
+    pub gamepad: Option<GamepadButtonType>,
+}
+
+impl ActionBinding {
+    pub fn label(&self, slot: BindingSlot) -> String {
+        let label = match slot {
+            BindingSlot::KeyboardMouse => self.binding.map(|binding| match binding {
+                Binding::Key(key) => format!("{:?}", key),
+                Binding::Mouse(button) => format!("Mouse {:?}", button),
+            }),
+            BindingSlot::Gamepad => self.gamepad.map(|button| format!("{:?}", button)),
+        };
+        label.unwrap_or_else(|| "-".to_string())
+    }
+
+    // Whether both are bound to the same key or button in `slot`
+    fn clashes_with(&self, other: &ActionBinding, slot: BindingSlot) -> bool {
+        match slot {
+            BindingSlot::KeyboardMouse => self.binding.is_some() && self.binding == other.binding,
+            BindingSlot::Gamepad => self.gamepad.is_some() && self.gamepad == other.gamepad,
+        }
+    }
+
+    // This binding with `slot` taken from `other`
+    fn with_slot(self, other: ActionBinding, slot: BindingSlot) -> Self {
+        match slot {
+            BindingSlot::KeyboardMouse => ActionBinding { binding: other.binding, ..self },
+            BindingSlot::Gamepad => ActionBinding { gamepad: other.gamepad, ..self },
+        }
+    }
+}
+
+#[derive(Resource, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bindings(pub BTreeMap<Action, ActionBinding>);
+
+impl Default for Bindings {
+    fn default() -> Self {
+        use GamepadButtonType::*;
+
+        let bind = |binding, gamepad| ActionBinding { binding: Some(binding), gamepad: Some(gamepad) };
+        Bindings(BTreeMap::from([
+            // placing and healing happen in different seasons, they share the main button
+            (Action::Place, bind(Binding::Mouse(MouseButton::Left), South)),
+            (Action::Heal, bind(Binding::Mouse(MouseButton::Left), South)),
+            (Action::Select, bind(Binding::Mouse(MouseButton::Right), West)),
+            // S pans the camera
+            (Action::Sell, bind(Binding::Key(KeyCode::X), North)),
+            (Action::Relocate, bind(Binding::Key(KeyCode::R), East)),
+            (Action::Pause, bind(Binding::Key(KeyCode::Escape), Start)),
+            (Action::SpeedUp, bind(Binding::Key(KeyCode::Tab), RightTrigger)),
+            (Action::CycleTowerType, bind(Binding::Key(KeyCode::T), LeftTrigger)),
+            (Action::ToggleBlastOverlay, bind(Binding::Key(KeyCode::F3), Select)),
+            (Action::PanUp, bind(Binding::Key(KeyCode::W), DPadUp)),
+            (Action::PanDown, bind(Binding::Key(KeyCode::S), DPadDown)),
+            (Action::PanLeft, bind(Binding::Key(KeyCode::A), DPadLeft)),
+            (Action::PanRight, bind(Binding::Key(KeyCode::D), DPadRight)),
+        ]))
+    }
+}
+
+impl Bindings {
+    pub fn get(&self, action: Action) -> ActionBinding {
+        self.0.get(&action).copied().unwrap_or_default()
+    }
+
+    // Actions other than `action` bound to the same key or button in `slot`
+    fn clashing(&self, action: Action, action_binding: ActionBinding, slot: BindingSlot) -> Vec<Action> {
+        Action::ALL.into_iter()
+            .filter(|&other| other != action && !action.shares_binding_with(other))
+            .filter(|&other| action_binding.clashes_with(&self.get(other), slot))
+            .collect()
+    }
+
+    // Bindings saved before a default changed can clash with it, like selling on S from before S
+    // panned the camera. An action whose binding is not its default goes back to the default, or
+    // is left unbound when that clashes too.
+    fn resolve_clashes(&mut self) {
+        let defaults = Bindings::default();
+        for slot in [BindingSlot::KeyboardMouse, BindingSlot::Gamepad] {
+            for action in Action::ALL {
+                let action_binding = self.get(action);
+                let default = defaults.get(action);
+                if action_binding.with_slot(default, slot) == action_binding
+                    || self.clashing(action, action_binding, slot).is_empty() {
+                    continue;
+                }
+                let mut resolved = action_binding.with_slot(default, slot);
+                if !self.clashing(action, resolved, slot).is_empty() {
+                    resolved = resolved.with_slot(ActionBinding::default(), slot);
+                }
+                warn!("{} was bound like another action, it is now bound to {}", action.label(), resolved.label(slot));
+                self.0.insert(action, resolved);
+            }
+        }
+    }
+}
+
+// The two bindings of an action
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingSlot {
+    KeyboardMouse,
+    Gamepad,
+}
+
+// Binding waiting for the player to press the new key or button
+#[derive(Resource, Default)]
+pub struct Rebinding(pub Option<(Action, BindingSlot)>);
+
+// Where the player points, in window coordinates with the origin at the bottom left like
+// `Window::cursor_position`, and the matching world position under the camera. It follows the
+// mouse, or the virtual cursor once a gamepad is used.
+#[derive(Resource, Default)]
+pub struct Pointer {
+    pub position: Option<Vec2>,
+    pub world_position: Option<Vec2>,
+    pub gamepad: bool,
+}
+
+#[derive(Component)]
+struct VirtualCursor;
+
+// Bindings saved by a previous launch on top of the defaults, so that actions added since
+// then get their default binding
+fn load_bindings() -> Bindings {
+    let mut bindings = Bindings::default();
+    if let Some(contents) = storage::read(BINDINGS_FILE) {
+        match serde_json::from_str::<Bindings>(&contents) {
+            Ok(saved) => {
+                bindings.0.extend(saved.0);
+                bindings.resolve_clashes();
+            }
+            Err(error) => warn!("invalid bindings, using the defaults: {}", error),
+        }
+    }
+    bindings
+}
+
+fn save_bindings(bindings: Res<Bindings>) {
+    if !bindings.is_changed() || bindings.is_added() {
+        return;
+    }
+    let result = serde_json::to_string_pretty(&*bindings)
+        .map_err(|error| error.to_string())
+        .and_then(|contents| storage::write(BINDINGS_FILE, &contents));
+    if let Err(error) = result {
+        warn!("could not save the bindings: {}", error);
+    }
+}
+
+fn update_actions(
+    bindings: Res<Bindings>,
+    rebinding: Res<Rebinding>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_button_input: Res<Input<MouseButton>>,
+    gamepad_button_input: Res<Input<GamepadButton>>,
+    gamepads: Res<Gamepads>,
+    mut actions: ResMut<Input<Action>>
+) {
+    actions.clear();
+    for action in Action::ALL {
+        let action_binding = bindings.get(action);
+        let pressed = match action_binding.binding {
+            Some(Binding::Key(key)) => keyboard_input.pressed(key),
+            Some(Binding::Mouse(button)) => mouse_button_input.pressed(button),
+            None => false,
+        } || action_binding.gamepad.is_some_and(|button_type| gamepads.iter()
+            .any(|gamepad| gamepad_button_input.pressed(GamepadButton::new(gamepad, button_type))));
+
+        if pressed {
+            actions.press(action);
+            // the key being bound must not also trigger its old action, now or once it is bound
+            if rebinding.0.is_some() {
+                actions.clear_just_pressed(action);
+            }
+        } else {
+            actions.release(action);
+        }
+    }
+}
+
+fn update_pointer(
+    time: Res<Time>,
+    mut cursor_moved_events: EventReader<CursorMoved>,
+    gamepads: Res<Gamepads>,
+    axes: Res<Axis<GamepadAxis>>,
+    primary_window_query: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    mut pointer: ResMut<Pointer>
+) {
+    let Ok(window) = primary_window_query.get_single() else {
+        return;
+    };
+
+    if cursor_moved_events.iter().count() > 0 {
+        pointer.gamepad = false;
+    }
+
+    let stick = gamepads.iter()
+        .map(|gamepad| Vec2::new(
+            axes.get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX)).unwrap_or(0.),
+            axes.get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickY)).unwrap_or(0.)))
+        .find(|stick| stick.length() > STICK_DEAD_ZONE);
+    if let Some(stick) = stick {
+        if !pointer.gamepad {
+            pointer.gamepad = true;
+            // the virtual cursor takes over where the mouse was
+            pointer.position = pointer.position.or(Some(Vec2::new(window.width(), window.height()) / 2.));
+        }
+        let position = pointer.position.unwrap_or_default() + stick * VIRTUAL_CURSOR_SPEED * time.raw_delta_seconds();
+        pointer.position = Some(position.clamp(Vec2::ZERO, Vec2::new(window.width(), window.height())));
+    }
+
+    if !pointer.gamepad {
+        pointer.position = window.cursor_position();
+    }
+
+    pointer.world_position = match (pointer.position, camera_query.get_single()) {
+        (Some(position), Ok((camera, camera_transform))) => camera.viewport_to_world(camera_transform, position)
+            .map(|ray| ray.origin.truncate()),
+        _ => None,
+    };
+}
+
+// Bevy only lets the mouse press buttons, with a gamepad the button or other HUD node under the
+// virtual cursor is hovered and pressed by the place action instead
+fn press_buttons_with_pointer(
+    pointer: Res<Pointer>,
+    actions: Res<Input<Action>>,
+    primary_window_query: Query<&Window, With<PrimaryWindow>>,
+    mut button_query: Query<(&Node, &GlobalTransform, &ComputedVisibility, &mut Interaction)>
+) {
+    let (true, Some(position)) = (pointer.gamepad, pointer.position) else {
+        return;
+    };
+    let Ok(window) = primary_window_query.get_single() else {
+        return;
+    };
+
+    // UI coordinates start at the top left
+    let ui_position = Vec2::new(position.x, window.height() - position.y);
+    for (node, global_transform, visibility, mut interaction) in button_query.iter_mut() {
+        let center = global_transform.translation().truncate();
+        let extents = node.size() / 2.;
+        let hovered = visibility.is_visible()
+            && ui_position.cmpge(center - extents).all()
+            && ui_position.cmple(center + extents).all();
+        let wanted = match (hovered, actions.just_pressed(Action::Place)) {
+            (true, true) => Interaction::Clicked,
+            (true, false) => Interaction::Hovered,
+            (false, _) => Interaction::None,
+        };
+        interaction.set_if_neq(wanted);
+    }
+}
+
+fn capture_binding(
+    mut rebinding: ResMut<Rebinding>,
+    mut bindings: ResMut<Bindings>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_button_input: Res<Input<MouseButton>>,
+    gamepad_button_input: Res<Input<GamepadButton>>
+) {
+    // the click that started the rebinding must not become the new binding
+    if rebinding.is_changed() {
+        return;
+    }
+    let Some((action, slot)) = rebinding.0 else {
+        return;
+    };
+
+    if keyboard_input.just_pressed(CANCEL_KEY) {
+        rebinding.0 = None;
+        return;
+    }
+
+    let mut action_binding = bindings.get(action);
+    match slot {
+        BindingSlot::KeyboardMouse => {
+            let pressed = keyboard_input.get_just_pressed().next().map(|&key| Binding::Key(key))
+                .or_else(|| mouse_button_input.get_just_pressed().next().map(|&button| Binding::Mouse(button)));
+            let Some(binding) = pressed else {
+                return;
+            };
+            action_binding.binding = Some(binding);
+        }
+        BindingSlot::Gamepad => {
+            let Some(button) = gamepad_button_input.get_just_pressed().next() else {
+                return;
+            };
+            action_binding.gamepad = Some(button.button_type);
+        }
+    }
+    // a key or button only does one thing, the action it was bound to is left unbound
+    for other in bindings.clashing(action, action_binding, slot) {
+        let cleared = bindings.get(other).with_slot(ActionBinding::default(), slot);
+        bindings.0.insert(other, cleared);
+    }
+    bindings.0.insert(action, action_binding);
+    rebinding.0 = None;
+}
+
+fn spawn_virtual_cursor(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Px(VIRTUAL_CURSOR_SIZE), Val::Px(VIRTUAL_CURSOR_SIZE)),
+                ..default()
+            },
+            background_color: Color::WHITE.into(),
+            // drawn above everything else
+            z_index: ZIndex::Global(i32::MAX),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        VirtualCursor,
+    ));
+}
+
+fn show_virtual_cursor(
+    pointer: Res<Pointer>,
+    primary_window_query: Query<&Window, With<PrimaryWindow>>,
+    mut cursor_query: Query<(&mut Style, &mut Visibility), With<VirtualCursor>>
+) {
+    let Ok(window) = primary_window_query.get_single() else {
+        return;
+    };
+    for (mut style, mut visibility) in cursor_query.iter_mut() {
+        let Some(position) = pointer.position.filter(|_| pointer.gamepad) else {
+            visibility.set_if_neq(Visibility::Hidden);
+            continue;
+        };
+        visibility.set_if_neq(Visibility::Inherited);
+        style.position = UiRect {
+            left: Val::Px(position.x - VIRTUAL_CURSOR_SIZE / 2.),
+            top: Val::Px(window.height() - position.y - VIRTUAL_CURSOR_SIZE / 2.),
+            ..default()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn check_saved_sell_on_s_goes_back_to_its_default() {
+        use super::{Action, Binding, Bindings};
+        use bevy::prelude::KeyCode;
+
+        let mut bindings = Bindings::default();
+        let mut sell = bindings.get(Action::Sell);
+        sell.binding = Some(Binding::Key(KeyCode::S));
+        bindings.0.insert(Action::Sell, sell);
+        bindings.resolve_clashes();
+
+        assert_eq!(bindings.get(Action::Sell).binding, Some(Binding::Key(KeyCode::X)));
+        assert_eq!(bindings.get(Action::PanDown).binding, Some(Binding::Key(KeyCode::S)));
+        // placing and healing keep sharing the main button
+        assert_eq!(bindings, Bindings::default());
+    }
+}
@@ -0,0 +1,117 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::config::GameConfig;
+use crate::simulation::MatchSetup;
+
+// Difficulty of new matches, chosen on the new game screen
+#[derive(Resource, Component, Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+    Nightmare,
+}
+
+// Factors applied to the values of the game config at a difficulty
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Modifiers {
+    pub enemy_count: f32,
+    pub enemy_speed: f32,
+    pub enemy_damage: f32,
+    pub base_health: f32,
+    pub tower_health: f32,
+    pub heal_amount: f32,
+    // the score is multiplied by this, harder matches are worth more
+    pub score: f32,
+}
+
+// Modifiers of each difficulty, set in the game config
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DifficultyModifiers {
+    pub easy: Modifiers,
+    pub normal: Modifiers,
+    pub hard: Modifiers,
+    pub nightmare: Modifiers,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 4] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard, Difficulty::Nightmare];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+            Difficulty::Nightmare => "Nightmare",
+        }
+    }
+
+    // Name used on the command line and in scenario files
+    pub fn from_name(name: &str) -> Option<Difficulty> {
+        Difficulty::ALL.into_iter().find(|difficulty| difficulty.label().eq_ignore_ascii_case(name))
+    }
+
+    pub fn modifiers(self, config: &GameConfig) -> Modifiers {
+        match self {
+            Difficulty::Easy => config.difficulties.easy,
+            Difficulty::Normal => config.difficulties.normal,
+            Difficulty::Hard => config.difficulties.hard,
+            Difficulty::Nightmare => config.difficulties.nightmare,
+        }
+    }
+
+    pub fn enemies_per_interval(self, config: &GameConfig) -> u32 {
+        (config.enemy.spawn_per_interval as f32 * self.modifiers(config).enemy_count).round() as u32
+    }
+
+    pub fn enemy_speed(self, config: &GameConfig) -> f32 {
+        config.enemy.speed * self.modifiers(config).enemy_speed
+    }
+
+    pub fn enemy_damage(self, config: &GameConfig) -> f32 {
+        config.enemy.damage * self.modifiers(config).enemy_damage
+    }
+
+    pub fn base_health(self, config: &GameConfig) -> f32 {
+        config.base.health * self.modifiers(config).base_health
+    }
+
+    pub fn tower_health(self, config: &GameConfig) -> f32 {
+        config.tower.health * self.modifiers(config).tower_health
+    }
+
+    pub fn heal_amount(self, config: &GameConfig) -> f32 {
+        config.heal.amount * self.modifiers(config).heal_amount
+    }
+}
+
+// Difficulty of the match being played. It can differ from the one selected for new matches
+// when a saved game or a replay is played.
+#[derive(Resource, Default, Debug, Clone, Copy)]
+pub struct MatchDifficulty(pub Difficulty);
+
+pub fn set_match_difficulty(
+    mut match_setup: ResMut<MatchSetup>,
+    difficulty: Res<Difficulty>,
+    mut match_difficulty: ResMut<MatchDifficulty>
+) {
+    match_difficulty.0 = match_setup.difficulty.take().unwrap_or(*difficulty);
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn check_difficulty_from_name_ignores_case() {
+        use super::Difficulty;
+
+        assert_eq!(Difficulty::from_name("hard"), Some(Difficulty::Hard));
+        assert_eq!(Difficulty::from_name("NIGHTMARE"), Some(Difficulty::Nightmare));
+        for difficulty in Difficulty::ALL {
+            assert_eq!(Difficulty::from_name(difficulty.label()), Some(difficulty));
+        }
+        assert_eq!(Difficulty::from_name("impossible"), None);
+    }
+}
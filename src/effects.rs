@@ -0,0 +1,104 @@
+use bevy::prelude::*;
+use rand::Rng;
+
+use super::{DisplayQuality, GameState, despawn_with_component};
+use crate::simulation::SimulationEvent;
+
+// This plugin shows short-lived particles for what happens in the simulation: hits, enemies
+// and towers dying, placement blasts and heals. The display quality decides how many are spawned.
+pub struct EffectsPlugin;
+
+impl Plugin for EffectsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(spawn_effects)
+        .add_system(update_particles.run_if(in_state(GameState::Game)))
+        .add_system(despawn_with_component::<Particle>.in_schedule(OnEnter(GameState::Menu)));
+    }
+}
+
+const PARTICLE_SIZE: f32 = 4.;
+const PARTICLE_LIFETIME_SECONDS: f32 = 0.6;
+
+#[derive(Component)]
+struct Particle {
+    velocity: Vec2,
+    timer: Timer,
+}
+
+// Number of particles at the highest display quality, their color and speed
+struct Burst {
+    count: usize,
+    color: Color,
+    speed: f32,
+}
+
+fn burst(event: &SimulationEvent) -> Option<(Vec2, Burst)> {
+    let burst = match *event {
+        SimulationEvent::Shot { .. } => return None,
+        SimulationEvent::EnemyHit { position } =>
+            (position, Burst { count: 4, color: Color::rgb(1., 1., 0.8), speed: 40. }),
+        SimulationEvent::EnemyKilled { position } =>
+            (position, Burst { count: 12, color: Color::rgb(0.8, 0.1, 0.1), speed: 80. }),
+        SimulationEvent::TowerDestroyed { position } =>
+            (position, Burst { count: 24, color: Color::rgb(0.6, 0.6, 0.6), speed: 100. }),
+        SimulationEvent::PlacementBlast { position } =>
+            (position, Burst { count: 40, color: Color::rgb(1., 0.6, 0.1), speed: 250. }),
+        SimulationEvent::Healed { position } =>
+            (position, Burst { count: 16, color: Color::rgb(0.2, 0.9, 0.3), speed: 50. }),
+        SimulationEvent::BossPhaseBroken { position } =>
+            (position, Burst { count: 60, color: Color::rgb(1., 0.85, 0.1), speed: 200. }),
+        SimulationEvent::BossSting { position } =>
+            (position, Burst { count: 30, color: Color::rgb(0.9, 0.2, 0.9), speed: 150. }),
+    };
+    Some(burst)
+}
+
+fn spawn_effects(
+    mut commands: Commands,
+    mut simulation_events: EventReader<SimulationEvent>,
+    display_quality: Res<DisplayQuality>
+) {
+    // the look of the particles does not need to be reproducible
+    let mut rng = rand::thread_rng();
+    for event in simulation_events.iter() {
+        let Some((position, burst)) = burst(event) else {
+            continue;
+        };
+        let count = (burst.count as f32 * display_quality.effects_density()).ceil() as usize;
+        for _ in 0..count {
+            let angle = rng.gen_range(0. ..std::f32::consts::TAU);
+            let speed = burst.speed * rng.gen_range(0.5..1.);
+            commands.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: burst.color,
+                        custom_size: Some(Vec2::splat(PARTICLE_SIZE)),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(position.extend(5.)),
+                    ..default()
+                },
+                Particle {
+                    velocity: Vec2::from_angle(angle) * speed,
+                    timer: Timer::from_seconds(PARTICLE_LIFETIME_SECONDS, TimerMode::Once),
+                },
+            ));
+        }
+    }
+}
+
+fn update_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut particle_query: Query<(Entity, &mut Particle, &mut Transform, &mut Sprite)>
+) {
+    for (particle_entity, mut particle, mut transform, mut sprite) in particle_query.iter_mut() {
+        particle.timer.tick(time.delta());
+        if particle.timer.finished() {
+            commands.entity(particle_entity).despawn();
+            continue;
+        }
+        transform.translation += (particle.velocity * time.delta_seconds()).extend(0.);
+        sprite.color.set_a(particle.timer.percent_left());
+    }
+}
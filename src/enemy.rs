@@ -1,39 +1,31 @@
 use std::f32::consts::PI;
+use std::time::Duration;
 
-use bevy::{
-    prelude::*,
-    sprite::MaterialMesh2dBundle,
-    input::mouse::{MouseButtonInput, MouseMotion, MouseWheel}, window::PrimaryWindow
-};
+use bevy::prelude::*;
 use super::GameState;
-use crate::{base::{Base, BASE_RADIUS}, game, map::{CELL_SIZE, Map, CellCoordinate}};
+use crate::{base::{Base, BASE_RADIUS}, map::{CELL_SIZE, Map, CellCoordinate}};
+use crate::difficulty::{Difficulty, MatchDifficulty};
+use crate::config::GameConfig;
+use crate::boss::Boss;
+use crate::simulation::{SimulationRng, Interpolated};
 use rand::Rng;
 
+// Enemies are simulated by the `SimulationPlugin`, this plugin only draws them
 pub struct EnemyPlugin;
 
 impl Plugin for EnemyPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(spawn_enemy.run_if(in_state(GameState::Game)))
-           .add_system(move_enemy.run_if(in_state(GameState::Game)))
-           .add_system(enemy_damage_base.run_if(in_state(GameState::Game)));    }
+        app.add_system(add_enemy_sprite);
+    }
 }
 
 
-pub const ENEMY_RADIUS: f32 = 5.;
-pub const ENEMY_COLOR: Color = Color::YELLOW;
-pub const ENEMY_SPAWN_INTERVAL_SECONDS: u32 = 1;
-pub const ENEMY_SPAWN_PER_INTERVAL: u32 = 150;
-
-
 #[derive(Resource)]
 pub struct WaveTimer {
     pub timer: Timer,
-    pub force_wave: bool
-}
-
-#[derive(Component, Default)]
-pub struct EnemyState {
-    pub timer: Timer,
+    pub force_wave: bool,
+    // enemies drawn in by heals, spawned as soon as there is a whole one
+    pub extra_enemies: f32
 }
 
 #[derive(Component, Default)]
@@ -47,28 +39,26 @@ pub struct EnemyStats {
 #[derive(Bundle, Default)]
 pub struct EnemyBundle {
     pub stats: EnemyStats,
-    pub state: EnemyState
+    pub interpolated: Interpolated
 }
 
 impl EnemyBundle {
-    pub fn new(x: f32, y: f32, destination: Vec2) -> Self {
+    pub fn new(x: f32, y: f32, destination: Vec2, difficulty: Difficulty, config: &GameConfig) -> Self {
         Self {
             stats: EnemyStats {
-                health: 100.,
-                destination: destination,
-                speed: 50.,
-                damage: 100.
-            },
-            state: EnemyState {
-                timer: Timer::from_seconds(1.0, TimerMode::Repeating),
+                health: config.enemy.health,
+                destination,
+                speed: difficulty.enemy_speed(config),
+                damage: difficulty.enemy_damage(config)
             },
-            ..Default::default()
+            interpolated: Interpolated::new(Vec3::new(x, y, 1.)),
         }
     }
 }
 
-fn move_enemy(
-    time: Res<Time>, 
+pub fn move_enemy(
+    fixed_time: Res<FixedTime>, 
+    mut simulation_rng: ResMut<SimulationRng>,
     mut enemy_query: Query<(&mut EnemyStats, &mut Transform)>,
         map_query: Query<&Map>) {
         
@@ -82,7 +72,7 @@ fn move_enemy(
             .truncate()
             .distance(enemy_stat.destination);
 
-            let delta = time.delta_seconds();
+            let delta = fixed_time.period.as_secs_f32();
             let step = enemy_stat.speed * delta;
             transform.rotation = Quat::from_rotation_z((transform.translation.y - enemy_stat.destination.y).atan2(transform.translation.x - enemy_stat.destination.x) + PI/2.);
 
@@ -100,9 +90,8 @@ fn move_enemy(
                     None => CellCoordinate{x: 0, y: 0},  // TODO: this is a hack. fix this problem 
                   };
                 
-                let mut rng = rand::thread_rng();
-                let x_offset= rng.gen_range((-CELL_SIZE/ 4.)..(CELL_SIZE / 4.));
-                let y_offset = rng.gen_range((-CELL_SIZE/ 4.)..(CELL_SIZE / 4.));
+                let x_offset= simulation_rng.gen_range((-CELL_SIZE/ 4.)..(CELL_SIZE / 4.));
+                let y_offset = simulation_rng.gen_range((-CELL_SIZE/ 4.)..(CELL_SIZE / 4.));
 
                 enemy_stat.destination = Vec2::new(next_cell.x as f32 * CELL_SIZE + x_offset,
                                                    next_cell.y as f32 * CELL_SIZE + y_offset);
@@ -110,84 +99,97 @@ fn move_enemy(
         }
 }
 
-fn enemy_damage_base(
+// The boss does not crash into the base, it stays and stings it
+pub fn enemy_damage_base(
     mut commands: Commands,
-    mut enemy_query: Query<(Entity, &EnemyStats, &Transform)>,
-    mut base_query: Query<(Entity, &mut Base, &Transform)>,
-    mut game_state: ResMut<NextState<GameState>>
+    enemy_query: Query<(Entity, &EnemyStats, &Transform), Without<Boss>>,
+    mut base_query: Query<(&mut Base, &Transform)>
 ) {
 
-    let Ok((base_entity, mut base, base_transform)) = base_query.get_single_mut() else {
+    let Ok((mut base, base_transform)) = base_query.get_single_mut() else {
         info!("no base!");
         return;
     };
 
     for (enemy_entity, enemy_stat, enemy_transform) in enemy_query.iter() {
+        // enemies shot down earlier in this step are only despawned at its end
+        if enemy_stat.health <= 0. {
+            continue;
+        }
         if ((enemy_transform.translation.x - base_transform.translation.x).powi(2) + (enemy_transform.translation.y - base_transform.translation.y).powi(2)).sqrt() < BASE_RADIUS * base_transform.scale.x {
             base.health -= enemy_stat.damage;
             commands.entity(enemy_entity).despawn();
-            if base.health <= 0. {
-                game_state.set(GameState::GameLost);
-            }
         }
     }
 
 }
 
-fn spawn_enemy(mut commands: Commands, 
-    primary_window_query: Query<&Window, With<PrimaryWindow>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_enemy(mut commands: Commands, 
     game_state: Res<State<GameState>>,
-    base_query: Query<(&Base, &Transform)>,
-    time: Res<Time>,
+    fixed_time: Res<FixedTime>,
+    mut simulation_rng: ResMut<SimulationRng>,
     mut wave_timer: ResMut<WaveTimer>,
-    map_query: Query<&Map>, 
-    asset_server: Res<AssetServer>
+    match_difficulty: Res<MatchDifficulty>,
+    config: Res<GameConfig>,
+    map_query: Query<&Map>
 ) {
     if game_state.0 == GameState::Game {
 
-        let Ok(window) = primary_window_query.get_single() else {
-                panic!("no window!");
-        };
-
-        let Ok((base, base_transform)) = base_query.get_single() else {
-            panic!("no base!");
-        };
-
         let Ok(map) = map_query.get_single() else {
             panic!("no map!");
         };
 
-        wave_timer.timer.tick(time.delta());
+        // enemies appear anywhere on the map
+        let width = map.width() as f32 * CELL_SIZE;
+        let height = map.height() as f32 * CELL_SIZE;
+
+        // the interval can change while the game runs when the config is reloaded
+        let interval = Duration::from_secs_f32(config.enemy.spawn_interval_seconds);
+        if wave_timer.timer.duration() != interval {
+            wave_timer.timer.set_duration(interval);
+        }
+        wave_timer.timer.tick(fixed_time.period);
 
+        let mut count = 0;
         if wave_timer.timer.finished() || wave_timer.force_wave {
-            let mut rng = rand::thread_rng();
-
-            for _ in 0..ENEMY_SPAWN_PER_INTERVAL {
-                let x = rng.gen_range((-window.width() / 2.)..(window.width() / 2.));
-                let y = rng.gen_range((-window.height() / 2.)..(window.height() / 2.));
-                let dist = Vec2::new(x, y).distance(Vec2::new(0., 0.));
-                if dist > 300. {
-                    let spawn_cell = CellCoordinate{x: ((x + CELL_SIZE/2.) / CELL_SIZE).floor() as i32, 
-                                                                    y: ((y + CELL_SIZE/2.) /CELL_SIZE).floor() as i32};
-                                                                    
-                    if map.came_from.contains_key(&spawn_cell) && !map.has_wall(&spawn_cell) {
-                        let destination = map.came_from.get(&spawn_cell).unwrap();
-                        commands.spawn((
-                            EnemyBundle::new(x, y, Vec2::new(destination.x as f32 * CELL_SIZE,
-                                                                        destination.y as f32 * CELL_SIZE)),//base_transform.translation.truncate()),
-                            
-                            SpriteBundle {
-                                texture:  asset_server.load("creepulant.png"),
-                                transform: Transform::from_xyz(x, y, 1.).with_scale(Vec3::splat(0.075)),
-                                ..default()
-                            }, 
-                            ));
-                    }
+            count += match_difficulty.0.enemies_per_interval(&config);
+            wave_timer.force_wave = false;
+        }
+        let extra_enemies = wave_timer.extra_enemies.floor();
+        wave_timer.extra_enemies -= extra_enemies;
+        count += extra_enemies as u32;
+
+        for _ in 0..count {
+            let x = simulation_rng.gen_range((-width / 2.)..(width / 2.));
+            let y = simulation_rng.gen_range((-height / 2.)..(height / 2.));
+            let dist = Vec2::new(x, y).distance(Vec2::new(0., 0.));
+            if dist > config.enemy.spawn_min_distance {
+                let spawn_cell = CellCoordinate{x: ((x + CELL_SIZE/2.) / CELL_SIZE).floor() as i32, 
+                                                                y: ((y + CELL_SIZE/2.) /CELL_SIZE).floor() as i32};
+                                                                
+                if map.came_from.contains_key(&spawn_cell) && !map.has_wall(&spawn_cell) {
+                    let destination = map.came_from.get(&spawn_cell).unwrap();
+                    commands.spawn((
+                        EnemyBundle::new(x, y, Vec2::new(destination.x as f32 * CELL_SIZE,
+                                                                    destination.y as f32 * CELL_SIZE),
+                                     match_difficulty.0, &config),//base_transform.translation.truncate()),
+                        TransformBundle::from_transform(Transform::from_xyz(x, y, 1.).with_scale(Vec3::splat(0.075))),
+                        ));
                 }
             }
-            wave_timer.force_wave = false;
         }
     }
 }
+
+fn add_enemy_sprite(mut commands: Commands,
+    enemy_query: Query<Entity, (Added<EnemyStats>, Without<Boss>)>,
+    asset_server: Res<AssetServer>) {
+
+    for enemy_entity in enemy_query.iter() {
+        commands.entity(enemy_entity).insert((
+            asset_server.load::<Image, _>("creepulant.png"),
+            Sprite::default(),
+            VisibilityBundle::default()));
+    }
+}
@@ -1,23 +1,36 @@
 use std::f32::consts::E;
-use std::time::Duration;
 
 use bevy::{
     prelude::*,
-    sprite::MaterialMesh2dBundle,
-    input::{mouse::{MouseButtonInput, MouseMotion, MouseWheel}, keyboard::KeyboardInput}, 
-    window::PrimaryWindow,
+    input::keyboard::KeyboardInput,
     text::{BreakLineOn, Text2dBounds},
 };
 
-use super::GameState;
+use super::{GameState, TEXT_COLOR, new_game_schedule};
 
-use crate::{tower::{TowerBundle, TowerStats, TOWER_RADIUS, TOWER_COLOR, TowerPlugin}, base::BASE_COLOR, game, despawn_with_component, bullet::Bullet, enemy::EnemyStats, season::SeasonSchedule};
-use crate::enemy::{EnemyPlugin, WaveTimer, ENEMY_SPAWN_INTERVAL_SECONDS};
+use crate::{tower::{TowerStats, TowerPlugin}, despawn_with_component, bullet::Bullet, enemy::EnemyStats};
+use crate::blast::BlastPlugin;
+use crate::heal::HealPlugin;
+use crate::synergy::SynergyPlugin;
+use crate::boss::{BossPlugin, BossStage};
+use crate::enemy::{EnemyPlugin, WaveTimer};
 use crate::bullet::BulletPlugin;
-use crate::base::{Base, BASE_RADIUS, BASE_INITIAL_HEALTH};
+use crate::base::Base;
+use crate::config::{ConfigPlugin, GameConfig};
 use crate::season::{SeasonPlugin, SeasonBarPart};
-use crate::map::MapPlugin;
-use crate::map::{Map, Wall};
+use crate::tilemap::TilemapPlugin;
+use crate::selection::SelectionPlugin;
+use crate::minimap::MinimapPlugin;
+use crate::speed::SpeedPlugin;
+use crate::replay::ReplayPlugin;
+use crate::simulation::{SimulationPlugin, InterpolationPlugin, MatchSetupSet};
+use crate::save::SavePlugin;
+use crate::records::{LastRun, RecordsPlugin};
+use crate::effects::EffectsPlugin;
+use crate::controls::ControlsPlugin;
+use crate::sound::SoundPlugin;
+use crate::map::Map;
+use crate::difficulty::MatchDifficulty;
 
 #[derive(Component)]
 struct AnimateTranslation;
@@ -25,25 +38,42 @@ struct AnimateTranslation;
 #[derive(Component)]
 struct EndGameText;
 
-pub struct GamePlugin;
+#[derive(Component)]
+struct CoinsText;
 
-pub const HEAL_AMOUNT: f32 = 100.0;
+// Coins earned by selling towers during the match
+#[derive(Resource, Default)]
+pub struct Coins(pub u32);
+
+pub struct GamePlugin;
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(startup.in_schedule(OnEnter(GameState::Game)))
+        app.add_system(coins_text_setup.in_schedule(new_game_schedule()).after(MatchSetupSet))
         .add_plugin(TowerPlugin)
+        .add_plugin(BlastPlugin)
+        .add_plugin(HealPlugin)
+        .add_plugin(SynergyPlugin)
+        .add_plugin(BossPlugin)
         .add_plugin(EnemyPlugin)
         .add_plugin(BulletPlugin)
         .add_plugin(SeasonPlugin)
-        .add_plugin(MapPlugin)
-        .insert_resource(WaveTimer {
-            // create the repeating timer
-            timer: Timer::new(Duration::from_secs(ENEMY_SPAWN_INTERVAL_SECONDS as u64), TimerMode::Repeating),
-            force_wave: false
-        })
+        .add_plugin(TilemapPlugin)
+        .add_plugin(SelectionPlugin)
+        .add_plugin(MinimapPlugin)
+        .add_plugin(SpeedPlugin)
+        .add_plugin(ReplayPlugin)
+        .add_plugin(SavePlugin)
+        .add_plugin(RecordsPlugin)
+        .add_plugin(EffectsPlugin)
+        .add_plugin(SoundPlugin)
+        .add_plugin(ControlsPlugin)
+        .add_plugin(SimulationPlugin)
+        .add_plugin(ConfigPlugin)
+        .add_plugin(InterpolationPlugin)
         .add_system(animate_translation)
-        .add_system(sync_base_size)
+        .add_system(update_coins_text.run_if(in_state(GameState::Game)))
+        .add_system(add_base_sprite)
         .add_system(end_game.in_schedule(OnEnter(GameState::GameWon)))
         .add_system(end_game.in_schedule(OnEnter(GameState::GameLost)))
         .add_system(
@@ -61,71 +91,145 @@ impl Plugin for GamePlugin {
         .add_system(
             despawn_with_component::<Map>.in_schedule(OnEnter(GameState::Menu)),
         )
-        .add_system(
-            despawn_with_component::<Wall>.in_schedule(OnEnter(GameState::Menu)),
-        )
         .add_system(
             despawn_with_component::<SeasonBarPart>.in_schedule(OnEnter(GameState::Menu)),
         )
         .add_system(
             despawn_with_component::<EndGameText>.in_schedule(OnEnter(GameState::Menu)),
         )
+        .add_system(
+            despawn_with_component::<CoinsText>.in_schedule(OnEnter(GameState::Menu)),
+        )
         .add_system(listen_for_restart.run_if(in_state(GameState::GameLost)))
         .add_system(listen_for_restart.run_if(in_state(GameState::GameWon)));
     }
 }
 
-fn compute_scale(health: f32) -> f32 {
-    (health / BASE_INITIAL_HEALTH).max(0.25) * 0.06
+// Share of its full size the base or a tower has left, it shrinks as it loses health. Its sprite
+// and the reach of clicks on it are scaled by this.
+pub fn health_size(health: f32, max_health: f32) -> f32 {
+    (health / max_health).max(0.25)
+}
+
+// Scale of the base or a tower
+pub fn compute_scale(health: f32, max_health: f32) -> f32 {
+    health_size(health, max_health) * 0.06
 }
 
-fn sync_base_size(mut base_query: Query<(&Base, &mut Transform)>) {
+// The base is hit by enemies within a radius that shrinks with its health
+pub fn sync_base_size(mut base_query: Query<(&Base, &mut Transform)>) {
     for (base, mut base_transform) in base_query.iter_mut() {
-        base_transform.scale = Vec3::splat(compute_scale(base.health));
+        base_transform.scale = Vec3::splat(compute_scale(base.health, base.max_health));
     }
 }
 
-fn startup(mut commands: Commands,    
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
+pub fn check_base_destroyed(base_query: Query<&Base>, mut game_state: ResMut<NextState<GameState>>) {
+    for base in base_query.iter() {
+        if base.health <= 0. {
+            game_state.set(GameState::GameLost);
+        }
+    }
+}
+
+pub fn start_match(mut commands: Commands,
+    mut coins: ResMut<Coins>,
+    mut wave_timer: ResMut<WaveTimer>,
+    mut boss_stage: ResMut<BossStage>,
+    match_difficulty: Res<MatchDifficulty>,
+    config: Res<GameConfig>) {
+
+    coins.0 = 0;
+    wave_timer.timer.reset();
+    wave_timer.force_wave = false;
+    wave_timer.extra_enemies = 0.;
+    *boss_stage = BossStage::Waiting;
+
+    let health = match_difficulty.0.base_health(&config);
+    let max_health = health * config.heal.max_health;
+    commands.spawn((Base {health, max_health},
+        TransformBundle::from_transform(Transform::from_translation(Vec3::new(0., 0., 0.))
+            .with_scale(Vec3::splat(compute_scale(health, max_health))))));
+}
+
+fn coins_text_setup(mut commands: Commands,
+    coins: Res<Coins>,
     asset_server: Res<AssetServer>) {
 
-    commands.spawn((Base {health: BASE_INITIAL_HEALTH}, 
-    SpriteBundle {
-        texture:  asset_server.load("base.png"),
-        transform: Transform::from_translation(Vec3::new(0., 0., 0.)),
-        ..default()
-    }));
-    // MaterialMesh2dBundle {
-    //     mesh: meshes.add(shape::Circle::new(BASE_RADIUS).into()).into(),
-    //     material: materials.add(ColorMaterial::from(BASE_COLOR)),
-    //     transform: Transform::from_translation(Vec3::new(0., 0., 0.)),
-    //     ..default()
-    // }
-    // ));
+    commands.spawn((
+        TextBundle::from_section(
+            format!("Coins: {}", coins.0),
+            TextStyle {
+                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                font_size: 30.0,
+                color: TEXT_COLOR,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(10.0),
+                bottom: Val::Px(10.0),
+                ..default()
+            },
+            ..default()
+        }),
+        CoinsText,
+    ));
+}
+
+fn add_base_sprite(mut commands: Commands,
+    base_query: Query<Entity, Added<Base>>,
+    asset_server: Res<AssetServer>) {
+
+    for base_entity in base_query.iter() {
+        commands.entity(base_entity).insert((
+            asset_server.load::<Image, _>("base.png"),
+            Sprite::default(),
+            VisibilityBundle::default()));
+    }
 }
 
 
-fn end_game(mut commands: Commands, 
+fn update_coins_text(coins: Res<Coins>, mut text_query: Query<&mut Text, With<CoinsText>>) {
+    if !coins.is_changed() {
+        return;
+    }
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = format!("Coins: {}", coins.0);
+    }
+}
+
+pub fn end_game(mut commands: Commands, 
     asset_server: Res<AssetServer>, 
     game_state: Res<State<GameState>>,
-    base_query: Query<&Base> )
+    last_run: Res<LastRun>,
+    match_difficulty: Res<MatchDifficulty>)
     {
     let font = asset_server.load("fonts/FiraSans-Bold.ttf");
     let text_style = TextStyle {
         font: font.clone(),
-        font_size: 60.0,
+        font_size: 40.0,
         color: Color::WHITE,
     };
-    let text_alignment = TextAlignment::Center;
-
-    let base_health = base_query.get_single().unwrap().health as i32;
 
-    let text = match game_state.0 {
-        GameState::GameWon => format!("You win! Score: {}\nPress any key to return to the menu.", base_health),
-        GameState::GameLost => "Game Over! You lost.\nPress any key to return to the menu.".to_string(),
-        _ => "unreachable".to_string()
+    let headline = match game_state.0 {
+        GameState::GameWon => "You win!",
+        GameState::GameLost => "Game Over! You lost.",
+        _ => "unreachable"
+    };
+    // replays are not scored
+    let score = match last_run.score {
+        Some(score) => {
+            let mut lines = format!("Score: {} ({})\nBase health {} - {} kills - {} seasons survived",
+                score.total, match_difficulty.0.label(), score.base_health.max(0.) as i32, score.kills, score.seasons_survived);
+            if let Some(rank) = last_run.high_score_rank {
+                lines += &format!("\nNew high score! #{}", rank + 1);
+            }
+            lines
+        }
+        None => String::new(),
     };
+    let text = format!("{}\n{}\nPress any key or button to return to the menu.", headline, score);
 
     let box_color = match game_state.0 {
         GameState::GameWon => Color::rgb_u8(2, 97, 27),
@@ -176,22 +280,20 @@ fn end_game(mut commands: Commands,
     // ));
 }
 
+// Any key or gamepad button goes back to the menu
 fn listen_for_restart(mut commands: Commands, 
     mut wave_timer: ResMut<WaveTimer>,
     mut key_evr: EventReader<KeyboardInput>, 
+    gamepad_button_input: Res<Input<GamepadButton>>,
     mut game_state: ResMut<NextState<GameState>>,
-    query: Query<Entity, With<EndGameText>>, 
-    mut season_schedule: ResMut<SeasonSchedule>) {
-    for ev in key_evr.iter() {
-        match ev.state {
-            _ => {
-                for entity in query.iter() {
-                    commands.entity(entity).despawn();
-                }
-                game_state.set(GameState::Menu);
-                wave_timer.timer.reset();
-            }, 
+    query: Query<Entity, With<EndGameText>>) {
+    let pressed = key_evr.iter().count() > 0 || gamepad_button_input.get_just_pressed().next().is_some();
+    if pressed {
+        for entity in query.iter() {
+            commands.entity(entity).despawn();
         }
+        game_state.set(GameState::Menu);
+        wave_timer.timer.reset();
     }
 }
 
@@ -0,0 +1,285 @@
+use std::{fs, path::{Path, PathBuf}};
+
+use bevy::prelude::*;
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+
+use super::GameState;
+use crate::base::Base;
+use crate::map::MapLayout;
+use crate::mapgen::MapKind;
+use crate::difficulty::Difficulty;
+use crate::config::GameConfig;
+use crate::records::Score;
+use crate::replay::{Replay, load_replay};
+use crate::season::SeasonSchedule;
+use crate::simulation::{
+    SimulationPlugin, SimulationTick, ScheduledCommand, ScheduledConfig, PendingCommands, MatchSetup, MatchSeed,
+    MatchStats, SimulationEvent, SIMULATION_STEP_SECONDS
+};
+
+// Matches that have not ended after this many steps are reported as unfinished
+const DEFAULT_MAX_TICKS: u64 = (60. * 60. / SIMULATION_STEP_SECONDS) as u64;
+
+// A match to simulate without a window: the seed it is rolled from, its difficulty, the generator
+// of its map or an optional map file replacing it, and the player's commands with the step they
+// happen in. The seed can be left out of the file when it is given with `--seed`.
+#[derive(Debug, Default, Deserialize)]
+pub struct Scenario {
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub difficulty: Difficulty,
+    #[serde(default)]
+    pub generator: MapKind,
+    #[serde(default)]
+    pub map: Option<PathBuf>,
+    #[serde(default)]
+    pub commands: Vec<ScheduledCommand>,
+    #[serde(default)]
+    pub max_ticks: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Won,
+    Lost,
+    Unfinished,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Summary {
+    pub seed: u64,
+    pub outcome: Outcome,
+    pub final_base_health: f32,
+    pub kills: u32,
+    pub towers_built: u32,
+    pub towers_lost: u32,
+    pub seasons_survived: usize,
+    pub score: u32,
+    pub ticks: u64,
+}
+
+// `rustytowers --headless <scenario.json> [--seed <seed>] [--difficulty <difficulty>]
+// [--generator <kind>] [--map <map.json>]` prints the summary of the simulated match as JSON, `rustytowers --headless --replay <replay.json>`
+// that of a replay
+pub fn run_from_args(args: &[String]) -> Result<(), String> {
+    let mut scenario_path = None;
+    let mut seed = None;
+    let mut difficulty = None;
+    let mut generator = None;
+    let mut map = None;
+    let mut replay_path = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seed" => {
+                let value = args.next().ok_or("--seed needs a value")?;
+                seed = Some(value.parse::<u64>().map_err(|error| format!("invalid seed {}: {}", value, error))?);
+            }
+            "--difficulty" => {
+                let value = args.next().ok_or("--difficulty needs a value")?;
+                difficulty = Some(Difficulty::from_name(value).ok_or(format!("unknown difficulty {}", value))?);
+            }
+            "--generator" => {
+                let value = args.next().ok_or("--generator needs a value")?;
+                generator = Some(MapKind::from_name(value).ok_or(format!("unknown generator {}", value))?);
+            }
+            "--map" => {
+                map = Some(PathBuf::from(args.next().ok_or("--map needs a path")?));
+            }
+            "--replay" => {
+                replay_path = Some(PathBuf::from(args.next().ok_or("--replay needs a path")?));
+            }
+            path => scenario_path = Some(PathBuf::from(path)),
+        }
+    }
+
+    let mut scenario = match scenario_path {
+        Some(path) => serde_json::from_str::<Scenario>(&read(&path)?)
+            .map_err(|error| format!("invalid scenario {}: {}", path.display(), error))?,
+        None => Scenario::default(),
+    };
+    if seed.is_some() {
+        scenario.seed = seed;
+    }
+    if let Some(difficulty) = difficulty {
+        scenario.difficulty = difficulty;
+    }
+    if let Some(generator) = generator {
+        scenario.generator = generator;
+    }
+    if map.is_some() {
+        scenario.map = map;
+    }
+
+    let config = GameConfig::read_file()?;
+    let summary = match replay_path {
+        Some(path) => simulate_replay(&load_replay(&path)?, config),
+        None => {
+            if scenario.seed.is_none() {
+                return Err("the scenario has no seed, give one in the file or with --seed".to_string());
+            }
+            let layout = match &scenario.map {
+                Some(path) => Some(serde_json::from_str::<MapLayout>(&read(path)?)
+                    .map_err(|error| format!("invalid map {}: {}", path.display(), error))?),
+                None => None,
+            };
+            simulate(&scenario, layout, config)
+        }
+    };
+    println!("{}", serde_json::to_string_pretty(&summary).map_err(|error| error.to_string())?);
+    Ok(())
+}
+
+fn read(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|error| format!("could not read {}: {}", path.display(), error))
+}
+
+// Play a whole match as fast as possible. Only the simulation is added to the app, the steps
+// are run back to back instead of waiting for real time to pass.
+pub fn simulate(scenario: &Scenario, layout: Option<MapLayout>, config: GameConfig) -> Summary {
+    summarize(&mut play(scenario, layout, None, &[ScheduledConfig { tick: 0, config }]), scenario.difficulty)
+}
+
+// Play a recorded match again, on its map, from the random numbers it had once the map was built
+// and with the configs it was played with. `config` is only used by replays that did not record theirs.
+pub fn simulate_replay(replay: &Replay, config: GameConfig) -> Summary {
+    let scenario = Scenario {
+        seed: Some(replay.seed),
+        difficulty: replay.difficulty,
+        commands: replay.commands.clone(),
+        ..default()
+    };
+    let configs = if replay.configs.is_empty() {
+        vec![ScheduledConfig { tick: 0, config }]
+    } else {
+        replay.configs.clone()
+    };
+    summarize(&mut play(&scenario, Some(replay.map.clone()), replay.rng.clone(), &configs), scenario.difficulty)
+}
+
+// `configs` holds the config the match starts with, then those it switches to at later steps
+fn play(scenario: &Scenario, layout: Option<MapLayout>, rng: Option<ChaCha12Rng>, configs: &[ScheduledConfig]) -> App {
+    let (initial_config, later_configs) = configs.split_first().expect("a match is played with a config");
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .insert_resource(initial_config.config.clone())
+        .add_state::<GameState>()
+        .add_plugin(SimulationPlugin)
+        .insert_resource(scenario.generator)
+        .insert_resource(MatchSetup {
+            seed: scenario.seed,
+            layout,
+            difficulty: Some(scenario.difficulty),
+            rng,
+            saved_game: None
+        });
+
+    // leaving the menu sets up the match, just like pressing New Game
+    for state in [GameState::Menu, GameState::Game] {
+        app.world.resource_mut::<NextState<GameState>>().set(state);
+        apply_state_transition::<GameState>(&mut app.world);
+    }
+
+    let mut commands = scenario.commands.clone();
+    commands.sort_by_key(|scheduled| scheduled.tick);
+    let mut commands = commands.into_iter().peekable();
+    let mut later_configs = later_configs.iter().peekable();
+    let max_ticks = scenario.max_ticks.unwrap_or(DEFAULT_MAX_TICKS);
+
+    loop {
+        let tick = app.world.resource::<SimulationTick>().0;
+        if app.world.resource::<State<GameState>>().0 != GameState::Game || tick >= max_ticks {
+            break;
+        }
+        while let Some(scheduled) = later_configs.next_if(|scheduled| scheduled.tick <= tick) {
+            app.world.insert_resource(scheduled.config.clone());
+        }
+        while let Some(scheduled) = commands.next_if(|scheduled| scheduled.tick <= tick) {
+            app.world.resource_mut::<PendingCommands>().0.push_back(scheduled.command);
+        }
+        app.world.run_schedule(CoreSchedule::FixedUpdate);
+        // nothing shows the events here, and the main schedule that drops them does not run
+        app.world.resource_mut::<Events<SimulationEvent>>().update();
+    }
+    app
+}
+
+fn summarize(app: &mut App, difficulty: Difficulty) -> Summary {
+    let outcome = match app.world.resource::<State<GameState>>().0 {
+        GameState::GameWon => Outcome::Won,
+        GameState::GameLost => Outcome::Lost,
+        _ => Outcome::Unfinished,
+    };
+    let final_base_health = app.world.query::<&Base>()
+        .iter(&app.world)
+        .map(|base| base.health)
+        .next()
+        .unwrap_or(0.);
+    let match_stats = *app.world.resource::<MatchStats>();
+    let seasons_survived = app.world.resource::<SeasonSchedule>().seasons_survived();
+    let config = app.world.resource::<GameConfig>();
+
+    Summary {
+        seed: app.world.resource::<MatchSeed>().0,
+        outcome,
+        final_base_health,
+        kills: match_stats.kills,
+        towers_built: match_stats.towers_built,
+        towers_lost: match_stats.towers_lost,
+        seasons_survived,
+        score: Score::new(final_base_health, match_stats.kills, seasons_survived, difficulty.modifiers(config).score, &config.score).total,
+        ticks: app.world.resource::<SimulationTick>().0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn check_replay_reproduces_the_match() {
+        use super::{Scenario, play, summarize, simulate_replay};
+        use crate::config::GameConfig;
+        use crate::map::Map;
+        use crate::replay::Replay;
+        use crate::simulation::{CommandLog, ConfigLog, MatchSeed, RngAfterMap, ScheduledConfig};
+
+        // the map is generated, which takes a varying number of draws that the replay skips
+        let scenario: Scenario = serde_json::from_str(r#"{
+            "seed": 42,
+            "commands": [
+                { "tick": 60, "type": "PlaceTower", "cell": { "x": 4, "y": 0 } },
+                { "tick": 90, "type": "PlaceTower", "cell": { "x": -4, "y": 0 }, "kind": "mortar" },
+                { "tick": 120, "type": "PlaceTower", "cell": { "x": 0, "y": 4 } },
+                { "tick": 900, "type": "SellTower", "cell": { "x": 0, "y": 4 } }
+            ]
+        }"#).unwrap();
+        // the config is also reloaded during the match
+        let mut reloaded_config = GameConfig::default();
+        reloaded_config.enemy.damage *= 2.;
+        let configs = [
+            ScheduledConfig { tick: 0, config: GameConfig::default() },
+            ScheduledConfig { tick: 300, config: reloaded_config },
+        ];
+        let mut app = play(&scenario, None, None, &configs);
+        let replay = Replay {
+            version: String::new(),
+            seed: app.world.resource::<MatchSeed>().0,
+            difficulty: scenario.difficulty,
+            map: app.world.query::<&Map>().single(&app.world).layout(),
+            rng: app.world.resource::<RngAfterMap>().0.clone(),
+            configs: app.world.resource::<ConfigLog>().0.clone(),
+            commands: app.world.resource::<CommandLog>().0.clone(),
+        };
+        let summary = summarize(&mut app, scenario.difficulty);
+
+        assert_eq!(replay.commands.len(), 4);
+        assert_eq!(replay.configs, configs);
+        // the recorded config is used rather than the one passed in
+        let mut other_config = GameConfig::default();
+        other_config.enemy.speed *= 2.;
+        assert_eq!(simulate_replay(&replay, other_config), summary);
+    }
+}
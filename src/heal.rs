@@ -0,0 +1,154 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{GameState, despawn_with_component, new_game_schedule, TEXT_COLOR};
+use crate::config::{GameConfig, HealConfig};
+use crate::enemy::WaveTimer;
+use crate::season::Season;
+
+// Healing is simulated by the `SimulationPlugin`, this plugin shows the heals left in the
+// current heal season
+pub struct HealPlugin;
+
+impl Plugin for HealPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(heal_charges_text_setup.in_schedule(new_game_schedule()))
+        .add_system(update_heal_charges_text.run_if(in_state(GameState::Game)))
+        .add_system(despawn_with_component::<HealChargesText>.in_schedule(OnEnter(GameState::Menu)));
+    }
+}
+
+// Heals the player has left in the current heal season
+#[derive(Resource, Default, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct HealCharges(pub u32);
+
+// What healing draws in from the enemies
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum HealConsequence {
+    Nothing,
+    // a whole wave right away
+    Wave,
+    // this share of a wave for each full heal, in proportion to the health actually restored
+    ExtraSpawn(f32),
+}
+
+impl HealConsequence {
+    // `heals` is the health restored by a click, counted in full heals
+    pub fn apply(&self, wave_timer: &mut WaveTimer, heals: f32, enemies_per_wave: u32) {
+        match self {
+            HealConsequence::Nothing => {}
+            HealConsequence::Wave => wave_timer.force_wave = true,
+            HealConsequence::ExtraSpawn(share) => wave_timer.extra_enemies += share * enemies_per_wave as f32 * heals,
+        }
+    }
+}
+
+#[derive(Component)]
+struct HealChargesText;
+
+pub fn refill_heal_charges(mut heal_charges: ResMut<HealCharges>, config: Res<GameConfig>) {
+    heal_charges.0 = config.heal.charges_per_season;
+}
+
+// Distance from a click within which something of the given size is healed
+pub fn heal_reach(body_radius: f32, heal: &HealConfig) -> f32 {
+    heal.radius.map_or(body_radius, |radius| radius.max(body_radius))
+}
+
+// Adds up to `amount` to `health` without going over `max_health`, returns the health restored
+pub fn restore_health(health: &mut f32, max_health: f32, amount: f32) -> f32 {
+    let restored = (max_health - *health).clamp(0., amount);
+    *health += restored;
+    restored
+}
+
+fn heal_charges_text_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                font_size: 24.0,
+                color: TEXT_COLOR,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(10.0),
+                bottom: Val::Px(75.0),
+                ..default()
+            },
+            ..default()
+        }),
+        HealChargesText,
+    ));
+}
+
+// The heals left are only shown during heal seasons
+fn update_heal_charges_text(
+    heal_charges: Res<HealCharges>,
+    current_season: Res<State<Season>>,
+    config: Res<GameConfig>,
+    mut text_query: Query<&mut Text, With<HealChargesText>>
+) {
+    let value = if current_season.0 == Season::Heal {
+        format!("Heals: {}/{}", heal_charges.0, config.heal.charges_per_season)
+    } else {
+        String::new()
+    };
+    for mut text in text_query.iter_mut() {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn check_restore_health_stops_at_max_health() {
+        use super::restore_health;
+
+        let mut health = 60.;
+        assert_eq!(restore_health(&mut health, 100., 30.), 30.);
+        assert_eq!(health, 90.);
+        assert_eq!(restore_health(&mut health, 100., 30.), 10.);
+        assert_eq!(health, 100.);
+
+        // health above the maximum is left as it is
+        let mut health = 120.;
+        assert_eq!(restore_health(&mut health, 100., 30.), 0.);
+        assert_eq!(health, 120.);
+    }
+
+    #[test]
+    fn check_heal_consequences() {
+        use super::HealConsequence;
+        use crate::enemy::WaveTimer;
+        use bevy::prelude::{Timer, TimerMode};
+
+        let new_wave_timer = || WaveTimer {
+            timer: Timer::from_seconds(1., TimerMode::Repeating),
+            force_wave: false,
+            extra_enemies: 0.
+        };
+
+        let mut wave_timer = new_wave_timer();
+        HealConsequence::Nothing.apply(&mut wave_timer, 1., 10);
+        assert!(!wave_timer.force_wave);
+        assert_eq!(wave_timer.extra_enemies, 0.);
+
+        let mut wave_timer = new_wave_timer();
+        HealConsequence::Wave.apply(&mut wave_timer, 0.5, 10);
+        assert!(wave_timer.force_wave);
+
+        // half a heal draws in half the share, and it adds up over clicks
+        let mut wave_timer = new_wave_timer();
+        HealConsequence::ExtraSpawn(0.2).apply(&mut wave_timer, 0.5, 10);
+        assert_eq!(wave_timer.extra_enemies, 1.);
+        HealConsequence::ExtraSpawn(0.2).apply(&mut wave_timer, 1., 10);
+        assert_eq!(wave_timer.extra_enemies, 3.);
+        assert!(!wave_timer.force_wave);
+    }
+}
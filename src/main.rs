@@ -1,16 +1,40 @@
 use bevy::{
     prelude::*,
   };
+use serde::{Deserialize, Serialize};
   
   mod splash;
   mod menu;
   mod game; 
   mod tower; 
+  mod blast;
+  mod heal;
+  mod synergy;
+  mod boss;
   mod enemy;
   mod bullet;
   mod base;
   mod season;
   mod map;
+  mod mapgen;
+  mod mapanalysis;
+  mod difficulty;
+  mod config;
+  mod selection;
+  mod speed;
+  mod simulation;
+  mod headless;
+  mod replay;
+  mod save;
+  mod storage;
+  mod records;
+  mod settings;
+  mod effects;
+  mod sound;
+  mod controls;
+  mod camera;
+  mod minimap;
+  mod tilemap;
   
   #[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Hash, States)]
   enum GameState {
@@ -23,34 +47,138 @@ use bevy::{
       GameWon
   }
   
-  #[derive(Resource, Debug, Component, PartialEq, Eq, Clone, Copy)]
+  #[derive(Resource, Debug, Component, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
   enum DisplayQuality {
       Low,
       Medium,
       High,
   }
+
+  impl DisplayQuality {
+    // Share of the visual effects that are shown
+    fn effects_density(self) -> f32 {
+      match self {
+        DisplayQuality::Low => 0.25,
+        DisplayQuality::Medium => 0.5,
+        DisplayQuality::High => 1.,
+      }
+    }
+  }
   
-  #[derive(Resource, Debug, Component, PartialEq, Eq, Clone, Copy)]
+  #[derive(Resource, Debug, Component, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
   struct Volume(u32);
+
+  impl Volume {
+    const MAX: u32 = 9;
+
+    // Gain applied to every sound
+    fn gain(self) -> f32 {
+      self.0.min(Volume::MAX) as f32 / Volume::MAX as f32
+    }
+  }
+
+  // Volume of the music alone, on top of the volume of every sound
+  #[derive(Resource, Debug, Component, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+  struct MusicVolume(u32);
+
+  impl MusicVolume {
+    fn gain(self) -> f32 {
+      Volume(self.0).gain()
+    }
+  }
+
+  // Volume of the sound effects and stingers alone, on top of the volume of every sound
+  #[derive(Resource, Debug, Component, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+  struct EffectsVolume(u32);
+
+  impl EffectsVolume {
+    fn gain(self) -> f32 {
+      Volume(self.0).gain()
+    }
+  }
+
+  #[derive(Resource, Debug, Component, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+  enum WindowSize {
+      Small,
+      Medium,
+      Large,
+  }
+
+  impl WindowSize {
+    fn resolution(self) -> (f32, f32) {
+      match self {
+        WindowSize::Small => (960., 540.),
+        WindowSize::Medium => (1280., 720.),
+        WindowSize::Large => (1600., 900.),
+      }
+    }
+  }
+
+  #[derive(Resource, Debug, Component, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+  enum ScreenMode {
+      Windowed,
+      Fullscreen,
+  }
   
   
   const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
   
   
   fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(index) = args.iter().position(|arg| arg == "--headless") {
+      if let Err(error) = headless::run_from_args(&args[index + 1..]) {
+        eprintln!("{}", error);
+        std::process::exit(1);
+      }
+      return;
+    }
+    if let Some(index) = args.iter().position(|arg| arg == "--analyze-map") {
+      if let Err(error) = mapanalysis::run_from_args(&args[index + 1..]) {
+        eprintln!("{}", error);
+        std::process::exit(1);
+      }
+      return;
+    }
+
+    let playback = match args.iter().position(|arg| arg == "--replay") {
+      Some(index) => {
+        let Some(path) = args.get(index + 1) else {
+          eprintln!("--replay needs a path");
+          std::process::exit(1);
+        };
+        match replay::load_replay(std::path::Path::new(path)) {
+          Ok(replay) => replay::ReplayPlayback::new(replay),
+          Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+          }
+        }
+      }
+      None => replay::ReplayPlayback::default(),
+    };
+
+    let settings = settings::load_settings();
+
     App::new()
       .insert_resource(ClearColor(Color::rgb(0.15, 0.14, 0.14)))
       .add_plugins(DefaultPlugins
         .set(WindowPlugin {primary_window: Some(Window {
             title: String::from("Rusty Towers"),
-            resizable: false,
+            resolution: settings.window_size.resolution().into(),
+            mode: settings::window_mode(settings.screen_mode),
             ..Default::default()
         }),
         ..default()
-        }).set(ImagePlugin::default_nearest()))
-      .insert_resource(DisplayQuality::Medium)
-      .insert_resource(Volume(7))
-      .add_startup_system(setup)
+        }).set(ImagePlugin::default_nearest())
+        // the game config is reloaded when it is edited
+        .set(AssetPlugin {
+            watch_for_changes: true,
+            ..default()
+        }))
+      .add_plugin(settings::SettingsPlugin { settings })
+      .insert_resource(playback)
+      .add_plugin(camera::CameraPlugin)
       .add_state::<GameState>()
       .add_plugin(splash::SplashPlugin)
       .add_plugin(menu::MenuPlugin)
@@ -58,11 +186,12 @@ use bevy::{
       .run();
   }
   
-  fn setup(mut commands: Commands) {
-    commands.spawn(Camera2dBundle::default());
+  // Schedule that sets up a new match. The menu is only ever left to start a game, so unlike
+  // `OnEnter(GameState::Game)` this does not run again when resuming from the pause menu
+  fn new_game_schedule() -> OnExit<GameState> {
+    OnExit(GameState::Menu)
   }
-  
-  
+
   // Generic system that takes a component as a parameter, and will despawn all entities with that component
   fn despawn_with_component<T: Component>(to_despawn: Query<Entity, With<T>>, mut commands: Commands) {
     for entity in &to_despawn {
@@ -1,32 +1,63 @@
 
-use std::cell::Cell;
-
 use bevy::prelude::*;
-use bevy::window::PrimaryWindow;
-use bevy::sprite::MaterialMesh2dBundle;
-use super::GameState;
 use bevy::utils::HashMap;
-use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::mapgen::MapKind;
+use crate::simulation::{MatchSetup, RngAfterMap, SimulationRng};
 
 
 pub const CELL_SIZE: f32 = 30.;  // probably should be an even number for the math to work
 
-pub struct MapPlugin;
+// Logical size of the world shown at the default zoom, centered on the origin. The camera scales
+// it to the window, larger maps are scrolled.
+pub const WORLD_WIDTH: f32 = 1280.;
+pub const WORLD_HEIGHT: f32 = 720.;
+
+// Size in cells of the maps generated for new matches, chosen on the new game screen
+#[derive(Resource, Component, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MapSize {
+    pub width: u32,
+    pub height: u32
+}
+
+impl MapSize {
+    // Sizes offered for new games, the first one fits the world
+    pub const PRESETS: [MapSize; 3] = [
+        MapSize { width: (WORLD_WIDTH / CELL_SIZE) as u32, height: (WORLD_HEIGHT / CELL_SIZE) as u32 },
+        MapSize { width: 64, height: 36 },
+        MapSize { width: 96, height: 54 },
+    ];
+    // Generators need room around the base for their walls, smaller maps are refused
+    pub const MIN: MapSize = MapSize { width: 10, height: 10 };
+}
 
-impl Plugin for MapPlugin {
-    fn build(&self, app: &mut App) {
-        app.add_system(build_map.in_schedule(OnEnter(GameState::Game)));
+impl Default for MapSize {
+    // the cells that fit in the world
+    fn default() -> Self {
+        MapSize::PRESETS[0]
     }
 }
 
-#[derive(Debug, Default, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Component)]
+#[derive(Debug, Default, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Component, Serialize, Deserialize)]
 pub struct CellCoordinate {
     pub x: i32,
     pub y: i32
 }
 
-#[derive(Component)]
-pub struct Wall;
+impl CellCoordinate {
+    // Cell containing a world position, cells are centered on multiples of CELL_SIZE
+    pub fn from_position(position: Vec2) -> Self {
+        CellCoordinate {
+            x: ((position.x + CELL_SIZE / 2.) / CELL_SIZE).floor() as i32,
+            y: ((position.y + CELL_SIZE / 2.) / CELL_SIZE).floor() as i32
+        }
+    }
+
+    pub fn to_position(self) -> Vec2 {
+        Vec2::new(self.x as f32 * CELL_SIZE, self.y as f32 * CELL_SIZE)
+    }
+}
 
 #[derive(Component, Default, Debug)]
 pub struct Map {
@@ -36,6 +67,14 @@ pub struct Map {
     pub came_from: HashMap<CellCoordinate, CellCoordinate>
 }
 
+// Everything needed to rebuild a map, as stored in map files
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MapLayout {
+    pub width: u32,
+    pub height: u32,
+    pub walls: Vec<CellCoordinate>
+}
+
 // Queue from https://www.kirillvasiltsov.com/writing/how-to-write-a-queue-in-rust/
 struct Queue<T> {
     queue: Vec<T>,
@@ -46,10 +85,6 @@ impl<T> Queue<T> {
         Queue { queue: Vec::new() }
     }
 
-    fn length(&self) -> usize {
-        self.queue.len()
-    }
-
     fn enqueue(&mut self, item: T) {
         self.queue.push(item)
     }
@@ -61,13 +96,63 @@ impl<T> Queue<T> {
     fn is_empty(&self) -> bool {
         self.queue.is_empty()
     }
-
-    fn peek(&self) -> Option<&T> {
-        self.queue.first()
-    }
 }
 
 impl Map {
+    pub fn from_layout(layout: MapLayout) -> Self {
+        let mut map = Map{width: layout.width,
+                          height: layout.height,
+                          walls: HashMap::new(),
+                          came_from: HashMap::new()};
+        for wall in layout.walls {
+            map.set_wall(wall, true);
+        }
+        map.compute_paths();
+        map
+    }
+
+    pub fn layout(&self) -> MapLayout {
+        let mut walls: Vec<CellCoordinate> = self.walls.iter()
+            .filter(|(_, &has_wall)| has_wall)
+            .map(|(&coordinate, _)| coordinate)
+            .collect();
+        walls.sort();
+        MapLayout { width: self.width, height: self.height, walls }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    // Area covered by the cells of the map, in world coordinates
+    pub fn world_rect(&self) -> Rect {
+        let half_width = (self.width / 2) as f32 + 0.5;
+        let half_height = (self.height / 2) as f32 + 0.5;
+        Rect::new(-half_width * CELL_SIZE, -half_height * CELL_SIZE, half_width * CELL_SIZE, half_height * CELL_SIZE)
+    }
+
+    // Breadth-first search from the base, every reachable cell points to the next cell towards it
+    fn compute_paths(&mut self) {
+        self.came_from.clear();
+        let mut frontier = Queue::<CellCoordinate>::new();
+        frontier.enqueue(CellCoordinate{x: 0, y:0});
+        self.came_from.insert(CellCoordinate{x: 0, y: 0},  CellCoordinate{x: 0, y: 0});
+
+        while !frontier.is_empty() {
+            let current = frontier.dequeue();
+            for next in self.get_neighbors(current) {
+                if !self.came_from.contains_key(&next) {
+                    frontier.enqueue(next);
+                    self.came_from.insert(next, current);
+                }
+            }
+        }
+    }
+
     pub fn has_wall(&self, coordinate: &CellCoordinate) -> bool {
         match self.walls.get(coordinate) {
             Some(&answer) => answer,
@@ -94,7 +179,7 @@ impl Map {
         let mut output = vec![];
         // for dx in vec![-1, 0, 1] {
         //     for dy in vec![-1, 0, 1] {
-            for (dx, dy) in vec![(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
                     let neighbor = CellCoordinate{x: coordinate.x + dx, y: coordinate.y + dy};
                     if self.in_map(neighbor) && !self.has_wall(&neighbor) {
                         output.push(neighbor);
@@ -105,94 +190,25 @@ impl Map {
 }
 
 
-fn build_map(
+// Build the map of a new match, either from the layout requested for it or with the chosen generator
+pub fn build_map(
     mut commands: Commands,
-    primary_window_query: Query<&Window, With<PrimaryWindow>>, 
-    mouse_button_input: Res<Input<MouseButton>>, 
-    game_state: Res<State<GameState>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>) {
-
-    let wall_color: Color =  Color::rgb_u8(90, 90, 90);
-
-    if game_state.0 == GameState::Game {
-        let Ok(window) = primary_window_query.get_single() else {
-            return;
-        };
-
-        let mut map = Map{width: (window.width() / CELL_SIZE) as u32,
-                                height: (window.height() / CELL_SIZE) as u32,
-                             walls: HashMap::new(), 
-                             came_from: HashMap::new()};
-
-        let half_width: i32 = (map.width/2) as i32;
-        let half_height: i32 = (map.height/2) as i32;
-
-        for _ in 1..100 {
-            let mut rng = rand::thread_rng();
-
-            let x = rng.gen_range(-half_width..half_width);
-            let y = rng.gen_range(-half_height..half_height);
-
-            let dx = rng.gen_range(0..4);
-            let dy = rng.gen_range(0..4);
-            
-            for cell_x in x..x+dx {
-                map.set_wall(CellCoordinate { x: cell_x, y: y}, true);
-            }
-
-            for cell_y in y..y+dy {
-                map.set_wall(CellCoordinate {x: x, y: cell_y}, true);
-            }
-        }
-
-        for x in -2..2 {
-            for y in -2..2 {
-                map.set_wall(CellCoordinate { x: x, y: y }, false);
-            }
-        }
-
-
-        for (coordinate, &has_wall) in map.walls.iter() {
-            if has_wall {
-                commands.spawn((MaterialMesh2dBundle {
-                    mesh: meshes.add(Mesh::from(shape::Quad::new(Vec2::new(CELL_SIZE, CELL_SIZE)))).into(),
-                    transform: Transform::default().with_translation(Vec3::new(coordinate.x as f32 * CELL_SIZE, 
-                                                                                coordinate.y as f32 * CELL_SIZE, 
-                                                                                2.)),
-                    material: materials.add(ColorMaterial::from(wall_color)),
-                    ..default()
-                }, Wall));
-            }
-        }
-
-        let mut frontier = Queue::<CellCoordinate>::new();
-        frontier.enqueue(CellCoordinate{x: 0, y:0});
-        map.came_from.insert(CellCoordinate{x: 0, y: 0},  CellCoordinate{x: 0, y: 0});
-
-        // for _ in 1..1000 {
-        while !frontier.is_empty() {
-            let current = frontier.dequeue();
-            for next in map.get_neighbors(current) {
-                if !map.came_from.contains_key(&next) {
-                    frontier.enqueue(next);
-                    map.came_from.insert(next, current);
-                }
-            }
-        }
-
-        // for (key, value) in map.came_from.iter() {
-        //     commands.spawn((
-        //         MaterialMesh2dBundle {
-        //             mesh: meshes.add(shape::Circle::new(3.).into()).into(),
-        //             material: materials.add(ColorMaterial::from(Color::GREEN)),
-        //             transform: Transform::from_xyz(key.x as f32 * CELL_SIZE, key.y as f32 * CELL_SIZE, 0.),
-        //             ..default()
-        //         }));        
-        //     }
-
-        commands.spawn(map);
+    mut match_setup: ResMut<MatchSetup>,
+    map_size: Res<MapSize>,
+    map_kind: Res<MapKind>,
+    mut simulation_rng: ResMut<SimulationRng>,
+    mut rng_after_map: ResMut<RngAfterMap>) {
+
+    let map = match match_setup.layout.take() {
+        Some(layout) => Map::from_layout(layout),
+        None => map_kind.generator().generate_playable(*map_size, &mut simulation_rng.0)
+    };
+    // a replayed match goes on the way it did once its map was generated
+    if let Some(rng) = match_setup.rng.take() {
+        simulation_rng.0 = rng;
     }
+    rng_after_map.0 = Some(simulation_rng.0.clone());
+    commands.spawn(map);
 }
 
 // fn update_map(
@@ -230,6 +246,7 @@ fn build_map(
 #[cfg(test)]
 mod tests {
     #[test]
+    #[allow(unused_mut)]
     fn check_neighbors_of_origin() {
         use super::CELL_SIZE;
         use super::{Map, CellCoordinate};
@@ -246,6 +263,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(unused_mut, clippy::len_zero)]
     fn check_neighbors_of_out_of_bounds() {
         use super::CELL_SIZE;
         use super::{Map, CellCoordinate};
@@ -260,4 +278,15 @@ mod tests {
         let neighbors = map.get_neighbors(CellCoordinate { x: 100, y: 100 });
         assert!(neighbors.len() == 0);
     }
+
+    #[test]
+    fn check_cell_from_position_snaps_to_nearest_center() {
+        use super::CELL_SIZE;
+        use super::CellCoordinate;
+        use bevy::prelude::Vec2;
+
+        let cell = CellCoordinate::from_position(Vec2::new(CELL_SIZE * 0.4, -CELL_SIZE * 0.6));
+        assert_eq!(cell, CellCoordinate { x: 0, y: -1 });
+        assert_eq!(cell.to_position(), Vec2::new(0., -CELL_SIZE));
+    }
 }
@@ -0,0 +1,377 @@
+use std::{fs, path::PathBuf};
+
+use bevy::utils::{HashMap, HashSet};
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+use serde::Serialize;
+
+use crate::config::GameConfig;
+use crate::map::{CellCoordinate, Map, MapLayout, MapSize};
+use crate::mapgen::MapKind;
+
+// Width of the buckets of the path length histogram, in cells
+const PATH_LENGTH_BUCKET: u32 = 10;
+
+// What a map is like to play on: how much of it enemies can use, how far they walk to the base
+// and where their paths can be cut
+#[derive(Debug, Clone, Serialize)]
+pub struct MapReport {
+    pub width: u32,
+    pub height: u32,
+    pub open_cells: u32,
+    // open cells the base can be reached from
+    pub reachable_cells: u32,
+    // open cells enemies can appear on
+    pub spawn_cells: u32,
+    // regions of open cells cut off from the base, and the cells in them
+    pub unreachable_pockets: u32,
+    pub unreachable_cells: u32,
+    // areas hanging off a single cell, that enemies leave the way they came, and the size of
+    // the largest one
+    pub dead_end_pockets: u32,
+    pub largest_dead_end: u32,
+    pub path_lengths: PathLengths,
+    // fewest cells at the same distance from the base, every enemy walks through each of
+    // these rings on its way in
+    pub base_approaches: u32,
+    // cells that would cut spawn cells off the base if they were walled
+    pub chokepoints: Vec<Chokepoint>,
+}
+
+// Length of the paths from the spawn cells to the base, in cells
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PathLengths {
+    pub min: u32,
+    pub median: u32,
+    pub mean: f32,
+    pub max: u32,
+    // number of spawn cells in every PATH_LENGTH_BUCKET cells of path length
+    pub histogram: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Chokepoint {
+    pub cell: CellCoordinate,
+    pub spawn_cells_cut_off: u32,
+}
+
+// Limits a generated map has to stay within to be played on. They are kept out of the game
+// config on purpose: they decide which roll of the generator is kept, and a seed has to give the
+// same map whatever config is loaded, in headless scenarios as in the game.
+#[derive(Debug, Clone, Copy)]
+pub struct MapThresholds {
+    // share of the open cells enemies can appear on
+    pub min_spawn_share: f32,
+    pub min_base_approaches: u32,
+    // share of the spawn cells a single chokepoint can cut off
+    pub max_chokepoint_share: f32,
+}
+
+impl Default for MapThresholds {
+    fn default() -> Self {
+        MapThresholds { min_spawn_share: 0.3, min_base_approaches: 2, max_chokepoint_share: 0.5 }
+    }
+}
+
+impl MapReport {
+    pub fn new(map: &Map) -> Self {
+        let half_width = (map.width() / 2) as i32;
+        let half_height = (map.height() / 2) as i32;
+        let cells: Vec<CellCoordinate> = (-half_height..=half_height)
+            .flat_map(|y| (-half_width..=half_width).map(move |x| CellCoordinate { x, y }))
+            .collect();
+        let open: Vec<CellCoordinate> = cells.iter().copied().filter(|cell| !map.has_wall(cell)).collect();
+
+        let distances = distances_to_base(map);
+        // maps are judged against the built-in config so that the same seed always rolls the same map
+        let spawn_min_distance = GameConfig::default().enemy.spawn_min_distance;
+        let is_spawn = |cell: &CellCoordinate| {
+            distances.contains_key(cell) && cell.to_position().length() > spawn_min_distance
+        };
+        let spawn_lengths: Vec<u32> = open.iter().filter(|cell| is_spawn(cell)).map(|cell| distances[cell]).collect();
+
+        // every path from a spawn cell goes through each ring closer to the base
+        let closest_spawn = spawn_lengths.iter().copied().min().unwrap_or(0);
+        let mut ring_sizes = vec![0; closest_spawn as usize + 1];
+        for &distance in distances.values() {
+            if (1..=closest_spawn).contains(&distance) {
+                ring_sizes[distance as usize] += 1;
+            }
+        }
+        let base_approaches = ring_sizes.into_iter().skip(1).min().unwrap_or(0);
+
+        let (unreachable_pockets, unreachable_cells) = unreachable_regions(map, &open, &distances);
+        let cuts = find_cuts(map, &distances, is_spawn);
+
+        MapReport {
+            width: map.width(),
+            height: map.height(),
+            open_cells: open.len() as u32,
+            reachable_cells: distances.len() as u32,
+            spawn_cells: spawn_lengths.len() as u32,
+            unreachable_pockets,
+            unreachable_cells,
+            dead_end_pockets: cuts.pocket_sizes.len() as u32,
+            largest_dead_end: cuts.pocket_sizes.iter().copied().max().unwrap_or(0),
+            path_lengths: PathLengths::new(spawn_lengths),
+            base_approaches,
+            chokepoints: cuts.chokepoints,
+        }
+    }
+
+    // Thresholds the map does not meet, described for the player
+    pub fn failures(&self, thresholds: &MapThresholds) -> Vec<String> {
+        let mut failures = Vec::new();
+        let spawn_share = self.spawn_cells as f32 / self.open_cells.max(1) as f32;
+        if spawn_share < thresholds.min_spawn_share {
+            failures.push(format!("enemies can only appear on {:.0}% of the open cells", spawn_share * 100.));
+        }
+        if self.base_approaches < thresholds.min_base_approaches {
+            failures.push(format!("the base can only be reached through {} cells", self.base_approaches));
+        }
+        let max_cut = (thresholds.max_chokepoint_share * self.spawn_cells as f32) as u32;
+        if let Some(chokepoint) = self.chokepoints.iter().find(|chokepoint| chokepoint.spawn_cells_cut_off > max_cut) {
+            failures.push(format!("walling cell ({}, {}) cuts {} of the {} spawn cells off the base",
+                chokepoint.cell.x, chokepoint.cell.y, chokepoint.spawn_cells_cut_off, self.spawn_cells));
+        }
+        failures
+    }
+}
+
+impl PathLengths {
+    fn new(mut lengths: Vec<u32>) -> Self {
+        if lengths.is_empty() {
+            return PathLengths::default();
+        }
+        lengths.sort_unstable();
+        let max = *lengths.last().unwrap();
+        let mut histogram = vec![0; (max / PATH_LENGTH_BUCKET) as usize + 1];
+        for &length in &lengths {
+            histogram[(length / PATH_LENGTH_BUCKET) as usize] += 1;
+        }
+        PathLengths {
+            min: lengths[0],
+            median: lengths[lengths.len() / 2],
+            mean: lengths.iter().sum::<u32>() as f32 / lengths.len() as f32,
+            max,
+            histogram,
+        }
+    }
+}
+
+// Steps from every cell that reaches the base to the base, as enemies walk them
+fn distances_to_base(map: &Map) -> HashMap<CellCoordinate, u32> {
+    let mut distances = HashMap::new();
+    let base = CellCoordinate { x: 0, y: 0 };
+    distances.insert(base, 0);
+    let mut frontier = vec![base];
+    let mut distance = 0;
+    while !frontier.is_empty() {
+        distance += 1;
+        let mut next_frontier = Vec::new();
+        for cell in frontier {
+            for neighbor in map.get_neighbors(cell) {
+                if !distances.contains_key(&neighbor) {
+                    distances.insert(neighbor, distance);
+                    next_frontier.push(neighbor);
+                }
+            }
+        }
+        frontier = next_frontier;
+    }
+    distances
+}
+
+// Number of regions of open cells that are cut off from the base, and of cells in them
+fn unreachable_regions(map: &Map, open: &[CellCoordinate], distances: &HashMap<CellCoordinate, u32>) -> (u32, u32) {
+    let mut seen = HashSet::new();
+    let mut pockets = 0;
+    for &cell in open {
+        if distances.contains_key(&cell) || seen.contains(&cell) {
+            continue;
+        }
+        pockets += 1;
+        seen.insert(cell);
+        let mut frontier = vec![cell];
+        while let Some(current) = frontier.pop() {
+            for neighbor in map.get_neighbors(current) {
+                if seen.insert(neighbor) {
+                    frontier.push(neighbor);
+                }
+            }
+        }
+    }
+    (pockets, seen.len() as u32)
+}
+
+// Where walling a single cell would split the cells reaching the base
+struct Cuts {
+    chokepoints: Vec<Chokepoint>,
+    // cells of every area that would be cut off
+    pocket_sizes: Vec<u32>,
+}
+
+// Cut vertices of the cells reaching the base, found with Tarjan's depth-first search from the
+// base. The search is iterative, paths through large maps are deeper than the stack allows.
+fn find_cuts(
+    map: &Map,
+    distances: &HashMap<CellCoordinate, u32>,
+    is_spawn: impl Fn(&CellCoordinate) -> bool
+) -> Cuts {
+    let mut cells: Vec<CellCoordinate> = distances.keys().copied().collect();
+    cells.sort();
+    let indices: HashMap<CellCoordinate, usize> = cells.iter().enumerate().map(|(index, &cell)| (cell, index)).collect();
+    let neighbors: Vec<Vec<usize>> = cells.iter()
+        .map(|&cell| map.get_neighbors(cell).iter().map(|neighbor| indices[neighbor]).collect())
+        .collect();
+
+    const UNVISITED: usize = usize::MAX;
+    let mut discovered = vec![UNVISITED; cells.len()];
+    let mut low = vec![0; cells.len()];
+    let mut parent = vec![UNVISITED; cells.len()];
+    // cells and spawn cells in the part of the search tree below every cell
+    let mut cells_below = vec![0; cells.len()];
+    let mut spawns_below = vec![0; cells.len()];
+    let mut cut_off = vec![0; cells.len()];
+    let mut pocket_sizes = Vec::new();
+
+    let root = indices[&CellCoordinate { x: 0, y: 0 }];
+    discovered[root] = 0;
+    let mut time = 1;
+    let mut stack = vec![(root, 0)];
+    while let Some((cell, next)) = stack.last_mut() {
+        let cell = *cell;
+        if let Some(&neighbor) = neighbors[cell].get(*next) {
+            *next += 1;
+            if discovered[neighbor] == UNVISITED {
+                discovered[neighbor] = time;
+                low[neighbor] = time;
+                time += 1;
+                parent[neighbor] = cell;
+                cells_below[neighbor] = 1;
+                spawns_below[neighbor] = is_spawn(&cells[neighbor]) as u32;
+                stack.push((neighbor, 0));
+            } else if neighbor != parent[cell] {
+                low[cell] = low[cell].min(discovered[neighbor]);
+            }
+            continue;
+        }
+
+        stack.pop();
+        if let Some(&(above, _)) = stack.last() {
+            low[above] = low[above].min(low[cell]);
+            cells_below[above] += cells_below[cell];
+            spawns_below[above] += spawns_below[cell];
+            // nothing below this cell reaches around the one above it
+            if above != root && low[cell] >= discovered[above] {
+                cut_off[above] += spawns_below[cell];
+                pocket_sizes.push(cells_below[cell]);
+            }
+        }
+    }
+
+    let mut chokepoints: Vec<Chokepoint> = cells.iter().zip(cut_off)
+        .filter(|&(_, spawn_cells_cut_off)| spawn_cells_cut_off > 0)
+        .map(|(&cell, spawn_cells_cut_off)| Chokepoint { cell, spawn_cells_cut_off })
+        .collect();
+    chokepoints.sort_by_key(|chokepoint| (std::cmp::Reverse(chokepoint.spawn_cells_cut_off), chokepoint.cell));
+    Cuts { chokepoints, pocket_sizes }
+}
+
+// `rustytowers --analyze-map <map.json>...` prints the report of every map file as JSON, and
+// `rustytowers --analyze-map --generator <kind> [--seed <seed>] [--size <width>x<height>]` that of
+// a generated map. Maps failing the thresholds are listed on the error output.
+pub fn run_from_args(args: &[String]) -> Result<(), String> {
+    let mut paths = Vec::new();
+    let mut generator = None;
+    let mut seed = 0;
+    let mut size = MapSize::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--generator" => {
+                let value = args.next().ok_or("--generator needs a value")?;
+                generator = Some(MapKind::from_name(value).ok_or(format!("unknown generator {}", value))?);
+            }
+            "--seed" => {
+                let value = args.next().ok_or("--seed needs a value")?;
+                seed = value.parse::<u64>().map_err(|error| format!("invalid seed {}: {}", value, error))?;
+            }
+            "--size" => {
+                let value = args.next().ok_or("--size needs a value")?;
+                let (width, height) = value.split_once('x')
+                    .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+                    .ok_or(format!("invalid size {}, expected <width>x<height>", value))?;
+                if width < MapSize::MIN.width || height < MapSize::MIN.height {
+                    return Err(format!("size {} is too small, maps are at least {}x{}", value,
+                        MapSize::MIN.width, MapSize::MIN.height));
+                }
+                size = MapSize { width, height };
+            }
+            path => paths.push(PathBuf::from(path)),
+        }
+    }
+
+    let mut maps = Vec::new();
+    for path in paths {
+        let contents = fs::read_to_string(&path)
+            .map_err(|error| format!("could not read {}: {}", path.display(), error))?;
+        let layout = serde_json::from_str::<MapLayout>(&contents)
+            .map_err(|error| format!("invalid map {}: {}", path.display(), error))?;
+        maps.push((path.display().to_string(), Map::from_layout(layout)));
+    }
+    if let Some(generator) = generator {
+        let map = generator.generator().generate_playable(size, &mut ChaCha12Rng::seed_from_u64(seed));
+        maps.push((format!("{} seed {}", generator.label(), seed), map));
+    }
+    if maps.is_empty() {
+        return Err("--analyze-map needs map files or a --generator".to_string());
+    }
+
+    for (name, map) in maps {
+        let report = MapReport::new(&map);
+        for failure in report.failures(&MapThresholds::default()) {
+            eprintln!("{}: {}", name, failure);
+        }
+        println!("{}", serde_json::to_string_pretty(&report).map_err(|error| error.to_string())?);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn check_sizes_too_small_to_generate_are_refused() {
+        use super::run_from_args;
+
+        for size in ["1x20", "20x1", "0x0"] {
+            let args = ["--generator", "rivers", "--size", size].map(String::from);
+            assert!(run_from_args(&args).is_err(), "{} was accepted", size);
+        }
+    }
+
+    #[test]
+    fn check_analysis_finds_the_gap_in_a_wall() {
+        use super::{MapReport, MapThresholds};
+        use crate::map::{CellCoordinate, Map, MapLayout};
+
+        // a wall across the map, with a single gap that everything right of it goes through
+        let walls = (-12..=12).filter(|&y| y != 3).map(|y| CellCoordinate { x: 11, y }).collect();
+        let map = Map::from_layout(MapLayout { width: 42, height: 24, walls });
+        let report = MapReport::new(&map);
+
+        assert_eq!(report.unreachable_cells, 0);
+        // the gap, and the cells on either side of it that lead through it
+        let cuts: Vec<(CellCoordinate, u32)> = report.chokepoints.iter()
+            .map(|chokepoint| (chokepoint.cell, chokepoint.spawn_cells_cut_off))
+            .collect();
+        assert_eq!(cuts, vec![
+            (CellCoordinate { x: 10, y: 3 }, 10 * 25 + 1),
+            (CellCoordinate { x: 11, y: 3 }, 10 * 25),
+            (CellCoordinate { x: 12, y: 3 }, 10 * 25 - 1),
+        ]);
+        let thresholds = MapThresholds { max_chokepoint_share: 0.2, ..MapThresholds::default() };
+        assert_eq!(report.failures(&thresholds).len(), 1);
+    }
+}
@@ -0,0 +1,443 @@
+use bevy::prelude::*;
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::map::{CellCoordinate, Map, MapLayout, MapSize};
+use crate::mapanalysis::{MapReport, MapThresholds};
+
+// Maps rolled before one failing the thresholds is played anyway
+const MAX_ROLLS: u32 = 20;
+
+// Generators of the maps of new matches. They only use the random numbers they are given, so a
+// map is rebuilt from the match seed alone, and every open cell they leave can reach the base:
+// enemies can spawn anywhere on the map.
+pub trait MapGenerator {
+    fn generate(&self, size: MapSize, rng: &mut dyn RngCore) -> Map;
+
+    // Roll maps until one is fit to be played on
+    fn generate_playable(&self, size: MapSize, rng: &mut dyn RngCore) -> Map {
+        let thresholds = MapThresholds::default();
+        let mut roll = 1;
+        loop {
+            let map = self.generate(size, rng);
+            let failures = MapReport::new(&map).failures(&thresholds);
+            if failures.is_empty() {
+                return map;
+            }
+            if roll == MAX_ROLLS {
+                warn!("no playable map in {} rolls, keeping one where {}", MAX_ROLLS, failures.join(", "));
+                return map;
+            }
+            roll += 1;
+        }
+    }
+}
+
+// Generator used for new matches, chosen on the new game screen
+#[derive(Resource, Component, Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MapKind {
+    #[default]
+    Scattered,
+    Caves,
+    Maze,
+    Rivers,
+}
+
+impl MapKind {
+    pub const ALL: [MapKind; 4] = [MapKind::Scattered, MapKind::Caves, MapKind::Maze, MapKind::Rivers];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            MapKind::Scattered => "Scattered",
+            MapKind::Caves => "Caves",
+            MapKind::Maze => "Maze",
+            MapKind::Rivers => "Rivers",
+        }
+    }
+
+    // Name used on the command line and in scenario files
+    pub fn from_name(name: &str) -> Option<MapKind> {
+        MapKind::ALL.into_iter().find(|kind| kind.label().eq_ignore_ascii_case(name))
+    }
+
+    // The generator with its default parameters
+    pub fn generator(self) -> Box<dyn MapGenerator> {
+        match self {
+            MapKind::Scattered => Box::new(ScatteredWalls::default()),
+            MapKind::Caves => Box::new(Caves::default()),
+            MapKind::Maze => Box::new(Maze::default()),
+            MapKind::Rivers => Box::new(Rivers::default()),
+        }
+    }
+}
+
+// Cells around the base that are always left open, from -BASE_CLEARING to BASE_CLEARING - 1
+const BASE_CLEARING: i32 = 2;
+
+// Walls of a map being generated, every cell is either a wall or open
+struct Grid {
+    size: MapSize,
+    half_width: i32,
+    half_height: i32,
+    walls: Vec<bool>,
+}
+
+impl Grid {
+    fn new(size: MapSize, wall: bool) -> Self {
+        let half_width = (size.width / 2) as i32;
+        let half_height = (size.height / 2) as i32;
+        let cells = ((2 * half_width + 1) * (2 * half_height + 1)) as usize;
+        Grid { size, half_width, half_height, walls: vec![wall; cells] }
+    }
+
+    fn index(&self, cell: CellCoordinate) -> Option<usize> {
+        let inside = cell.x.abs() <= self.half_width && cell.y.abs() <= self.half_height;
+        let row_length = 2 * self.half_width + 1;
+        inside.then(|| ((cell.y + self.half_height) * row_length + cell.x + self.half_width) as usize)
+    }
+
+    // Cells from the bottom left corner, row by row
+    fn cells(&self) -> impl Iterator<Item = CellCoordinate> {
+        let (half_width, half_height) = (self.half_width, self.half_height);
+        (-half_height..=half_height).flat_map(move |y| (-half_width..=half_width).map(move |x| CellCoordinate { x, y }))
+    }
+
+    // Cells outside of the map are walls
+    fn is_wall(&self, cell: CellCoordinate) -> bool {
+        self.index(cell).is_none_or(|index| self.walls[index])
+    }
+
+    fn set_wall(&mut self, cell: CellCoordinate, wall: bool) {
+        if let Some(index) = self.index(cell) {
+            self.walls[index] = wall;
+        }
+    }
+
+    fn clear_base(&mut self) {
+        for x in -BASE_CLEARING..BASE_CLEARING {
+            for y in -BASE_CLEARING..BASE_CLEARING {
+                self.set_wall(CellCoordinate { x, y }, false);
+            }
+        }
+    }
+
+    // Which cells can reach the base, walking between the sides of open cells
+    fn reachable(&self) -> Vec<bool> {
+        let mut reached = vec![false; self.walls.len()];
+        let origin = CellCoordinate { x: 0, y: 0 };
+        let Some(start) = self.index(origin) else {
+            return reached;
+        };
+        reached[start] = true;
+        let mut frontier = vec![origin];
+        while let Some(cell) = frontier.pop() {
+            for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+                let next = CellCoordinate { x: cell.x + dx, y: cell.y + dy };
+                if self.is_wall(next) {
+                    continue;
+                }
+                let index = self.index(next).unwrap();
+                if !reached[index] {
+                    reached[index] = true;
+                    frontier.push(next);
+                }
+            }
+        }
+        reached
+    }
+
+    // Open cells the base cannot be reached from
+    fn first_unreachable(&self) -> Option<CellCoordinate> {
+        let reached = self.reachable();
+        self.cells().find(|&cell| {
+            let index = self.index(cell).unwrap();
+            !self.walls[index] && !reached[index]
+        })
+    }
+
+    // Open a straight corridor from every isolated area towards the base, until it meets a cell
+    // that already reaches it
+    fn connect_to_base(&mut self) {
+        while let Some(mut cell) = self.first_unreachable() {
+            let reached = self.reachable();
+            while !reached[self.index(cell).unwrap()] {
+                self.set_wall(cell, false);
+                if cell.x.abs() >= cell.y.abs() {
+                    cell.x -= cell.x.signum();
+                } else {
+                    cell.y -= cell.y.signum();
+                }
+            }
+        }
+    }
+
+    // Wall up the pockets that are closed on every side
+    fn fill_unreachable(&mut self) {
+        let reached = self.reachable();
+        for (wall, reached) in self.walls.iter_mut().zip(reached) {
+            *wall |= !reached;
+        }
+    }
+
+    fn into_map(self) -> Map {
+        let walls = self.cells().filter(|&cell| self.is_wall(cell)).collect();
+        Map::from_layout(MapLayout { width: self.size.width, height: self.size.height, walls })
+    }
+}
+
+// Short straight walls thrown anywhere, one every `cells_per_segment` cells of the map. Pockets
+// they close are walled up.
+pub struct ScatteredWalls {
+    pub cells_per_segment: u32,
+    pub max_length: i32,
+}
+
+impl Default for ScatteredWalls {
+    fn default() -> Self {
+        ScatteredWalls { cells_per_segment: 10, max_length: 4 }
+    }
+}
+
+impl MapGenerator for ScatteredWalls {
+    fn generate(&self, size: MapSize, rng: &mut dyn RngCore) -> Map {
+        let mut grid = Grid::new(size, false);
+        let (half_width, half_height) = (grid.half_width, grid.half_height);
+
+        let segment_count = size.width * size.height / self.cells_per_segment;
+        for _ in 1..segment_count {
+            let x = rng.gen_range(-half_width..half_width);
+            let y = rng.gen_range(-half_height..half_height);
+
+            let dx = rng.gen_range(0..self.max_length);
+            let dy = rng.gen_range(0..self.max_length);
+
+            for cell_x in x..x+dx {
+                grid.set_wall(CellCoordinate { x: cell_x, y }, true);
+            }
+            for cell_y in y..y+dy {
+                grid.set_wall(CellCoordinate { x, y: cell_y }, true);
+            }
+        }
+
+        grid.clear_base();
+        grid.fill_unreachable();
+        grid.into_map()
+    }
+}
+
+// Cellular automaton caves: random walls are smoothed into rounded caverns, which are then
+// joined to the base by straight tunnels
+pub struct Caves {
+    // Chances out of 100 for a cell to start as a wall
+    pub wall_chance: u32,
+    pub smoothing_steps: u32,
+}
+
+impl Default for Caves {
+    fn default() -> Self {
+        Caves { wall_chance: 45, smoothing_steps: 4 }
+    }
+}
+
+impl MapGenerator for Caves {
+    fn generate(&self, size: MapSize, rng: &mut dyn RngCore) -> Map {
+        let mut grid = Grid::new(size, false);
+        for index in 0..grid.walls.len() {
+            grid.walls[index] = rng.gen_range(0..100) < self.wall_chance;
+        }
+
+        // a cell becomes a wall when most of its eight neighbours are, the edges of the map count
+        // as walls
+        for _ in 0..self.smoothing_steps {
+            let walls = grid.cells()
+                .map(|cell| {
+                    let neighbors = (-1..=1)
+                        .flat_map(|dx| (-1..=1).map(move |dy| (dx, dy)))
+                        .filter(|&offset| offset != (0, 0))
+                        .filter(|&(dx, dy)| grid.is_wall(CellCoordinate { x: cell.x + dx, y: cell.y + dy }))
+                        .count();
+                    match neighbors {
+                        0..=3 => false,
+                        4 => grid.is_wall(cell),
+                        _ => true,
+                    }
+                })
+                .collect();
+            grid.walls = walls;
+        }
+
+        grid.clear_base();
+        grid.connect_to_base();
+        grid.into_map()
+    }
+}
+
+// Recursive backtracker maze: rooms on a regular grid are joined one by one along a random walk
+// that backs up at dead ends, so that there is a single path between any two of them. A few
+// more walls are then opened to make loops.
+pub struct Maze {
+    pub corridor_width: i32,
+    // Chances out of 100 for each remaining wall between two rooms to be opened
+    pub loop_chance: u32,
+}
+
+impl Default for Maze {
+    fn default() -> Self {
+        Maze { corridor_width: 2, loop_chance: 10 }
+    }
+}
+
+impl MapGenerator for Maze {
+    fn generate(&self, size: MapSize, rng: &mut dyn RngCore) -> Map {
+        let mut grid = Grid::new(size, true);
+        // rooms are separated by walls one cell thick
+        let pitch = self.corridor_width + 1;
+        let columns = (2 * grid.half_width) / pitch;
+        let rows = (2 * grid.half_height) / pitch;
+        if columns == 0 || rows == 0 {
+            grid.clear_base();
+            return grid.into_map();
+        }
+        let (left, bottom) = (-grid.half_width + 1, -grid.half_height + 1);
+
+        // open a rectangle of cells, in rooms counted from the bottom left one
+        let open = |grid: &mut Grid, column: i32, row: i32, width: i32, height: i32| {
+            for x in 0..width {
+                for y in 0..height {
+                    grid.set_wall(CellCoordinate { x: left + column * pitch + x, y: bottom + row * pitch + y }, false);
+                }
+            }
+        };
+        // open the wall between a room and the one right of it or above it
+        let join = |grid: &mut Grid, (column, row): (i32, i32), (next_column, next_row): (i32, i32)| {
+            let (column, row) = (column.min(next_column), row.min(next_row));
+            if next_row == row {
+                open(grid, column, row, pitch + self.corridor_width, self.corridor_width);
+            } else {
+                open(grid, column, row, self.corridor_width, pitch + self.corridor_width);
+            }
+        };
+
+        let mut visited = vec![false; (columns * rows) as usize];
+        let start = (rng.gen_range(0..columns), rng.gen_range(0..rows));
+        visited[(start.1 * columns + start.0) as usize] = true;
+        let mut path = vec![start];
+        let mut joined = Vec::new();
+        while let Some(&(column, row)) = path.last() {
+            let unvisited: Vec<(i32, i32)> = [(1, 0), (-1, 0), (0, 1), (0, -1)].into_iter()
+                .map(|(dx, dy)| (column + dx, row + dy))
+                .filter(|&(x, y)| x >= 0 && x < columns && y >= 0 && y < rows)
+                .filter(|&(x, y)| !visited[(y * columns + x) as usize])
+                .collect();
+            if unvisited.is_empty() {
+                path.pop();
+                continue;
+            }
+            let next = unvisited[rng.gen_range(0..unvisited.len())];
+            visited[(next.1 * columns + next.0) as usize] = true;
+            join(&mut grid, (column, row), next);
+            joined.push(((column, row).min(next), (column, row).max(next)));
+            path.push(next);
+        }
+
+        for column in 0..columns {
+            for row in 0..rows {
+                for next in [(column + 1, row), (column, row + 1)] {
+                    let between = ((column, row), next);
+                    if next.0 < columns && next.1 < rows && !joined.contains(&between)
+                        && rng.gen_range(0..100) < self.loop_chance {
+                        join(&mut grid, (column, row), next);
+                    }
+                }
+            }
+        }
+
+        grid.clear_base();
+        grid.connect_to_base();
+        grid.into_map()
+    }
+}
+
+// Winding rivers crossing the whole map, with bridges at regular intervals. The first river
+// flows from the top to the bottom of the map, the next one from side to side, and so on.
+pub struct Rivers {
+    pub count: u32,
+    pub width: i32,
+    // Cells of river between two bridges
+    pub bridge_spacing: i32,
+    pub bridge_width: i32,
+}
+
+impl Default for Rivers {
+    fn default() -> Self {
+        Rivers { count: 3, width: 2, bridge_spacing: 9, bridge_width: 2 }
+    }
+}
+
+impl MapGenerator for Rivers {
+    fn generate(&self, size: MapSize, rng: &mut dyn RngCore) -> Map {
+        let mut grid = Grid::new(size, false);
+        for river in 0..self.count {
+            let vertical = river % 2 == 0;
+            // (length, across) of the half of the map the river flows along and across
+            let (half_length, half_across) = if vertical {
+                (grid.half_height, grid.half_width)
+            } else {
+                (grid.half_width, grid.half_height)
+            };
+            // rivers start away from the base, on either side of it
+            let side = if rng.gen_bool(0.5) { 1 } else { -1 };
+            let mut across = side * rng.gen_range((half_across / 4).max(1)..=(half_across * 3 / 4).max(1));
+            let first_bridge = rng.gen_range(0..self.bridge_spacing);
+
+            for along in -half_length..=half_length {
+                let step = along + half_length - first_bridge;
+                let bridge = step.rem_euclid(self.bridge_spacing + self.bridge_width) >= self.bridge_spacing;
+                if !bridge {
+                    for offset in 0..self.width {
+                        let cell = if vertical {
+                            CellCoordinate { x: across + offset, y: along }
+                        } else {
+                            CellCoordinate { x: along, y: across + offset }
+                        };
+                        grid.set_wall(cell, true);
+                    }
+                }
+                across = (across + rng.gen_range(-1..=1)).clamp(-half_across, half_across - self.width + 1);
+            }
+        }
+
+        // rivers crossing each other can cut an area off all its bridges
+        grid.clear_base();
+        grid.connect_to_base();
+        grid.into_map()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn check_generated_maps_reach_the_base_from_every_open_cell() {
+        use super::MapKind;
+        use crate::map::{CellCoordinate, MapSize};
+        use rand::SeedableRng;
+        use rand_chacha::ChaCha12Rng;
+
+        for kind in MapKind::ALL {
+            for size in MapSize::PRESETS {
+                for seed in 0..5 {
+                    let map = kind.generator().generate(size, &mut ChaCha12Rng::seed_from_u64(seed));
+                    let (half_width, half_height) = ((size.width / 2) as i32, (size.height / 2) as i32);
+                    for x in -half_width..=half_width {
+                        for y in -half_height..=half_height {
+                            let cell = CellCoordinate { x, y };
+                            assert!(map.has_wall(&cell) || map.came_from.contains_key(&cell),
+                                "{:?} map {}x{} seed {} cuts {:?} off the base", kind, size.width, size.height, seed, cell);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,11 +1,25 @@
 use bevy::{app::AppExit, prelude::*};
 
-use super::{despawn_with_component, DisplayQuality, GameState, Volume, TEXT_COLOR};
+use super::{
+    despawn_with_component, DisplayQuality, EffectsVolume, GameState, MusicVolume, ScreenMode, Volume, WindowSize,
+    TEXT_COLOR
+};
+use crate::controls::{Action, BindingSlot, Bindings, Rebinding};
+use crate::map::MapSize;
+use crate::mapgen::MapKind;
+use crate::difficulty::Difficulty;
+use crate::records::{format_date, load_high_scores, load_history, RunRecord};
+use crate::save::{SaveRequested, load_saved_game, saved_game_exists};
+use crate::simulation::MatchSetup;
 
-// This plugin manages the menu, with 5 different screens:
-// - a main menu with "New Game", "Settings", "Quit"
-// - a settings menu with two submenus and a back button
-// - two settings screen with a setting that can be set and a back button
+// This plugin manages the menu, with 9 different screens:
+// - a main menu with "Continue" (when a game has been saved), "New Game", "Records", "Settings", "Quit"
+// - a new game screen with the options of the next match, a start button and a back button
+// - a records screen with the high scores, the latest runs and a back button
+// - a pause menu shown over the game with "Resume", "Restart", "Settings", "Save & Quit", "Quit to Menu"
+// - a settings menu with three submenus and a back button
+// - two settings screens with the settings that can be set and a back button
+// - a controls screen to rebind every action, with reset and back buttons
 pub struct MenuPlugin;
 
 impl Plugin for MenuPlugin {
@@ -15,33 +29,70 @@ impl Plugin for MenuPlugin {
             // entering the `GameState::Menu` state.
             // Current screen in the menu is handled by an independent state from `GameState`
             .add_state::<MenuState>()
+            .init_resource::<RestartRequested>()
             .add_system(menu_setup.in_schedule(OnEnter(GameState::Menu)))
+            // The pause action opens the pause menu from the game and closes it again
+            .add_system(toggle_pause.run_if(in_state(GameState::Game)))
+            .add_system(toggle_pause.run_if(in_state(GameState::Pause)))
+            .add_system(pause_setup.in_schedule(OnEnter(GameState::Pause)))
+            // Systems to handle the pause menu screen
+            .add_systems((
+                pause_menu_setup.in_schedule(OnEnter(MenuState::Pause)),
+                despawn_with_component::<OnPauseMenuScreen>.in_schedule(OnExit(MenuState::Pause)),
+            ))
             // Systems to handle the main menu screen
             .add_systems((
                 main_menu_setup.in_schedule(OnEnter(MenuState::Main)),
                 despawn_with_component::<OnMainMenuScreen>.in_schedule(OnExit(MenuState::Main)),
             ))
+            // Systems to handle the new game screen
+            .add_systems((
+                new_game_menu_setup.in_schedule(OnEnter(MenuState::NewGame)),
+                setting_button::<MapSize>.in_set(OnUpdate(MenuState::NewGame)),
+                setting_button::<MapKind>.in_set(OnUpdate(MenuState::NewGame)),
+                setting_button::<Difficulty>.in_set(OnUpdate(MenuState::NewGame)),
+                despawn_with_component::<OnNewGameMenuScreen>.in_schedule(OnExit(MenuState::NewGame)),
+            ))
+            // Systems to handle the records screen
+            .add_systems((
+                records_menu_setup.in_schedule(OnEnter(MenuState::Records)),
+                despawn_with_component::<OnRecordsMenuScreen>.in_schedule(OnExit(MenuState::Records)),
+            ))
             // Systems to handle the settings menu screen
-            // .add_systems((
-            //     settings_menu_setup.in_schedule(OnEnter(MenuState::Settings)),
-            //     despawn_with_component::<OnSettingsMenuScreen>.in_schedule(OnExit(MenuState::Settings)),
-            // ))
-            // // Systems to handle the display settings screen
-            // .add_systems((
-            //     display_settings_menu_setup.in_schedule(OnEnter(MenuState::SettingsDisplay)),
-            //     setting_button::<DisplayQuality>.in_set(OnUpdate(MenuState::SettingsDisplay)),
-            //     despawn_with_component::<OnDisplaySettingsMenuScreen>
-            //         .in_schedule(OnExit(MenuState::SettingsDisplay)),
-            // ))
-            // // Systems to handle the sound settings screen
-            // .add_systems((
-            //     sound_settings_menu_setup.in_schedule(OnEnter(MenuState::SettingsSound)),
-            //     setting_button::<Volume>.in_set(OnUpdate(MenuState::SettingsSound)),
-            //     despawn_with_component::<OnSoundSettingsMenuScreen>
-            //         .in_schedule(OnExit(MenuState::SettingsSound)),
-            // ))
-            // // Common systems to all screens that handles buttons behaviour
-            .add_systems((menu_action, button_system).in_set(OnUpdate(GameState::Menu)));
+            .add_systems((
+                settings_menu_setup.in_schedule(OnEnter(MenuState::Settings)),
+                despawn_with_component::<OnSettingsMenuScreen>.in_schedule(OnExit(MenuState::Settings)),
+            ))
+            // Systems to handle the display settings screen
+            .add_systems((
+                display_settings_menu_setup.in_schedule(OnEnter(MenuState::SettingsDisplay)),
+                setting_button::<DisplayQuality>.in_set(OnUpdate(MenuState::SettingsDisplay)),
+                setting_button::<WindowSize>.in_set(OnUpdate(MenuState::SettingsDisplay)),
+                setting_button::<ScreenMode>.in_set(OnUpdate(MenuState::SettingsDisplay)),
+                despawn_with_component::<OnDisplaySettingsMenuScreen>
+                    .in_schedule(OnExit(MenuState::SettingsDisplay)),
+            ))
+            // Systems to handle the sound settings screen
+            .add_systems((
+                sound_settings_menu_setup.in_schedule(OnEnter(MenuState::SettingsSound)),
+                setting_button::<Volume>.in_set(OnUpdate(MenuState::SettingsSound)),
+                setting_button::<MusicVolume>.in_set(OnUpdate(MenuState::SettingsSound)),
+                setting_button::<EffectsVolume>.in_set(OnUpdate(MenuState::SettingsSound)),
+                despawn_with_component::<OnSoundSettingsMenuScreen>
+                    .in_schedule(OnExit(MenuState::SettingsSound)),
+            ))
+            // Systems to handle the controls settings screen
+            .add_systems((
+                controls_settings_menu_setup.in_schedule(OnEnter(MenuState::SettingsControls)),
+                rebind_button.in_set(OnUpdate(MenuState::SettingsControls)),
+                update_binding_labels.in_set(OnUpdate(MenuState::SettingsControls)),
+                cancel_rebinding.in_schedule(OnExit(MenuState::SettingsControls)),
+                despawn_with_component::<OnControlsSettingsMenuScreen>
+                    .in_schedule(OnExit(MenuState::SettingsControls)),
+            ))
+            // Common systems to all screens that handles buttons behaviour
+            .add_systems((menu_action, button_system).in_set(OnUpdate(GameState::Menu)))
+            .add_systems((menu_action, button_system).in_set(OnUpdate(GameState::Pause)));
     }
 }
 
@@ -49,9 +100,13 @@ impl Plugin for MenuPlugin {
 #[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Hash, States)]
 enum MenuState {
     Main,
+    Pause,
+    NewGame,
+    Records,
     Settings,
     SettingsDisplay,
     SettingsSound,
+    SettingsControls,
     #[default]
     Disabled,
 }
@@ -60,6 +115,23 @@ enum MenuState {
 #[derive(Component)]
 struct OnMainMenuScreen;
 
+// Tag component used to tag entities added on the pause menu screen
+#[derive(Component)]
+struct OnPauseMenuScreen;
+
+// Set by the pause menu, or when a replay is played back, so that the next visit to the menu
+// immediately starts a new game
+#[derive(Resource, Default)]
+pub struct RestartRequested(pub bool);
+
+// Tag component used to tag entities added on the new game screen
+#[derive(Component)]
+struct OnNewGameMenuScreen;
+
+// Tag component used to tag entities added on the records screen
+#[derive(Component)]
+struct OnRecordsMenuScreen;
+
 // Tag component used to tag entities added on the settings menu screen
 #[derive(Component)]
 struct OnSettingsMenuScreen;
@@ -72,11 +144,28 @@ struct OnDisplaySettingsMenuScreen;
 #[derive(Component)]
 struct OnSoundSettingsMenuScreen;
 
+// Tag component used to tag entities added on the controls settings menu screen
+#[derive(Component)]
+struct OnControlsSettingsMenuScreen;
+
+// Button waiting for a new key or button for one binding of an action when clicked
+#[derive(Component)]
+struct RebindButton(Action, BindingSlot);
+
+// Text showing one binding of an action
+#[derive(Component)]
+struct BindingLabel(Action, BindingSlot);
+
+// Button putting every binding back to its default
+#[derive(Component)]
+struct ResetBindingsButton;
+
 const NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
 const HOVERED_BUTTON: Color = Color::rgb(0.25, 0.25, 0.25);
 const HOVERED_PRESSED_BUTTON: Color = Color::rgb(0.25, 0.65, 0.25);
 const PRESSED_BUTTON: Color = Color::rgb(0.35, 0.75, 0.35);
 const BACKGROUND: Color = Color::rgb(0.705, 0.302, 0.027);
+const PAUSE_OVERLAY: Color = Color::rgba(0.0, 0.0, 0.0, 0.6);
 
 // Tag component used to mark which setting is currently selected
 #[derive(Component)]
@@ -85,16 +174,25 @@ struct SelectedOption;
 // All actions that can be triggered from a button click
 #[derive(Component)]
 enum MenuButtonAction {
+    NewGame,
     Play,
+    Continue,
+    Records,
     Settings,
     SettingsDisplay,
     SettingsSound,
+    SettingsControls,
     BackToMainMenu,
     BackToSettings,
+    Resume,
+    Restart,
+    SaveAndQuit,
+    QuitToMenu,
     Quit,
 }
 
 // This system handles changing all buttons color based on mouse interaction
+#[allow(clippy::type_complexity)]
 fn button_system(
     mut interaction_query: Query<
         (&Interaction, &mut BackgroundColor, Option<&SelectedOption>),
@@ -111,35 +209,74 @@ fn button_system(
     }
 }
 
-// // This system updates the settings when a new value for a setting is selected, and marks
-// // the button as the one currently selected
-// fn setting_button<T: Resource + Component + PartialEq + Copy>(
-//     interaction_query: Query<(&Interaction, &T, Entity), (Changed<Interaction>, With<Button>)>,
-//     mut selected_query: Query<(Entity, &mut BackgroundColor), With<SelectedOption>>,
-//     mut commands: Commands,
-//     mut setting: ResMut<T>,
-// ) {
-//     for (interaction, button_setting, entity) in &interaction_query {
-//         if *interaction == Interaction::Clicked && *setting != *button_setting {
-//             let (previous_button, mut previous_color) = selected_query.single_mut();
-//             *previous_color = NORMAL_BUTTON.into();
-//             commands.entity(previous_button).remove::<SelectedOption>();
-//             commands.entity(entity).insert(SelectedOption);
-//             *setting = *button_setting;
-//         }
-//     }
-// }
-
-fn menu_setup(mut menu_state: ResMut<NextState<MenuState>>) {
-    menu_state.set(MenuState::Main);
+// This system updates the settings when a new value for a setting is selected, and marks
+// the button as the one currently selected. Several settings can share a screen, each one
+// has its own selected button.
+#[allow(clippy::type_complexity)]
+fn setting_button<T: Resource + Component + PartialEq + Copy>(
+    interaction_query: Query<(&Interaction, &T, Entity), (Changed<Interaction>, With<Button>)>,
+    mut selected_query: Query<(Entity, &mut BackgroundColor), (With<SelectedOption>, With<T>)>,
+    mut commands: Commands,
+    mut setting: ResMut<T>,
+) {
+    for (interaction, button_setting, entity) in &interaction_query {
+        if *interaction == Interaction::Clicked && *setting != *button_setting {
+            // a value set outside of the menu may have no button
+            if let Ok((previous_button, mut previous_color)) = selected_query.get_single_mut() {
+                *previous_color = NORMAL_BUTTON.into();
+                commands.entity(previous_button).remove::<SelectedOption>();
+            }
+            commands.entity(entity).insert(SelectedOption);
+            *setting = *button_setting;
+        }
+    }
+}
+
+fn menu_setup(
+    mut menu_state: ResMut<NextState<MenuState>>,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut restart: ResMut<RestartRequested>,
+) {
+    if restart.0 {
+        // the previous game has been cleaned up on entering the menu, go straight to a new one
+        restart.0 = false;
+        menu_state.set(MenuState::Disabled);
+        game_state.set(GameState::Game);
+    } else {
+        menu_state.set(MenuState::Main);
+    }
+}
+
+fn pause_setup(mut menu_state: ResMut<NextState<MenuState>>) {
+    menu_state.set(MenuState::Pause);
+}
+
+fn toggle_pause(
+    actions: Res<Input<Action>>,
+    current_game_state: Res<State<GameState>>,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut menu_state: ResMut<NextState<MenuState>>,
+) {
+    if !actions.just_pressed(Action::Pause) {
+        return;
+    }
+    match current_game_state.0 {
+        GameState::Game => game_state.set(GameState::Pause),
+        _ => {
+            menu_state.set(MenuState::Disabled);
+            game_state.set(GameState::Game);
+        }
+    }
 }
 
 fn main_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let saved_game_exists = saved_game_exists();
     let font = asset_server.load("fonts/FiraSans-Bold.ttf");
     // Common style for all buttons on the screen
     let button_style = Style {
-        size: Size::new(Val::Px(250.0), Val::Px(65.0)),
-        margin: UiRect::all(Val::Px(20.0)),
+        // up to five buttons have to fit in the smallest window
+        size: Size::new(Val::Px(250.0), Val::Px(55.0)),
+        margin: UiRect::all(Val::Px(10.0)),
         justify_content: JustifyContent::Center,
         align_items: AlignItems::Center,
         ..default()
@@ -199,15 +336,40 @@ fn main_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                             },
                         )
                         .with_style(Style {
-                            margin: UiRect::all(Val::Px(50.0)),
+                            margin: UiRect::all(Val::Px(25.0)),
                             ..default()
                         }),
                     );
 
-                    // Display three buttons for each action available from the main menu:
+                    // Display a button for each action available from the main menu:
+                    // - continue, when a game has been saved
                     // - new game
+                    // - records
                     // - settings
                     // - quit
+                    if saved_game_exists {
+                        parent
+                            .spawn((
+                                ButtonBundle {
+                                    style: button_style.clone(),
+                                    background_color: NORMAL_BUTTON.into(),
+                                    ..default()
+                                },
+                                MenuButtonAction::Continue,
+                            ))
+                            .with_children(|parent| {
+                                let icon = asset_server.load("textures/Game Icons/right.png");
+                                parent.spawn(ImageBundle {
+                                    style: button_icon_style.clone(),
+                                    image: UiImage::new(icon),
+                                    ..default()
+                                });
+                                parent.spawn(TextBundle::from_section(
+                                    "Continue",
+                                    button_text_style.clone(),
+                                ));
+                            });
+                    }
                     parent
                         .spawn((
                             ButtonBundle {
@@ -215,7 +377,7 @@ fn main_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                                 background_color: NORMAL_BUTTON.into(),
                                 ..default()
                             },
-                            MenuButtonAction::Play,
+                            MenuButtonAction::NewGame,
                         ))
                         .with_children(|parent| {
                             let icon = asset_server.load("textures/Game Icons/right.png");
@@ -229,6 +391,48 @@ fn main_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                                 button_text_style.clone(),
                             ));
                         });
+                    parent
+                        .spawn((
+                            ButtonBundle {
+                                style: button_style.clone(),
+                                background_color: NORMAL_BUTTON.into(),
+                                ..default()
+                            },
+                            MenuButtonAction::Records,
+                        ))
+                        .with_children(|parent| {
+                            let icon = asset_server.load("textures/Game Icons/right.png");
+                            parent.spawn(ImageBundle {
+                                style: button_icon_style.clone(),
+                                image: UiImage::new(icon),
+                                ..default()
+                            });
+                            parent.spawn(TextBundle::from_section(
+                                "Records",
+                                button_text_style.clone(),
+                            ));
+                        });
+                    parent
+                        .spawn((
+                            ButtonBundle {
+                                style: button_style.clone(),
+                                background_color: NORMAL_BUTTON.into(),
+                                ..default()
+                            },
+                            MenuButtonAction::Settings,
+                        ))
+                        .with_children(|parent| {
+                            let icon = asset_server.load("textures/Game Icons/wrench.png");
+                            parent.spawn(ImageBundle {
+                                style: button_icon_style.clone(),
+                                image: UiImage::new(icon),
+                                ..default()
+                            });
+                            parent.spawn(TextBundle::from_section(
+                                "Settings",
+                                button_text_style.clone(),
+                            ));
+                        });
                     parent
                         .spawn((
                             ButtonBundle {
@@ -251,261 +455,771 @@ fn main_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
         });
 }
 
-// fn settings_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
-//     let button_style = Style {
-//         size: Size::new(Val::Px(200.0), Val::Px(65.0)),
-//         margin: UiRect::all(Val::Px(20.0)),
-//         justify_content: JustifyContent::Center,
-//         align_items: AlignItems::Center,
-//         ..default()
-//     };
-
-//     let button_text_style = TextStyle {
-//         font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-//         font_size: 40.0,
-//         color: TEXT_COLOR,
-//     };
-
-//     commands
-//         .spawn((
-//             NodeBundle {
-//                 style: Style {
-//                     size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
-//                     align_items: AlignItems::Center,
-//                     justify_content: JustifyContent::Center,
-//                     ..default()
-//                 },
-//                 ..default()
-//             },
-//             OnSettingsMenuScreen,
-//         ))
-//         .with_children(|parent| {
-//             parent
-//                 .spawn(NodeBundle {
-//                     style: Style {
-//                         flex_direction: FlexDirection::Column,
-//                         align_items: AlignItems::Center,
-//                         ..default()
-//                     },
-//                     background_color: BACKGROUND.into(),
-//                     ..default()
-//                 })
-//                 .with_children(|parent| {
-//                     for (action, text) in [
-//                         (MenuButtonAction::SettingsDisplay, "Display"),
-//                         (MenuButtonAction::SettingsSound, "Sound"),
-//                         (MenuButtonAction::BackToMainMenu, "Back"),
-//                     ] {
-//                         parent
-//                             .spawn((
-//                                 ButtonBundle {
-//                                     style: button_style.clone(),
-//                                     background_color: NORMAL_BUTTON.into(),
-//                                     ..default()
-//                                 },
-//                                 action,
-//                             ))
-//                             .with_children(|parent| {
-//                                 parent.spawn(TextBundle::from_section(
-//                                     text,
-//                                     button_text_style.clone(),
-//                                 ));
-//                             });
-//                     }
-//                 });
-//         });
-// }
-
-// fn display_settings_menu_setup(
-//     mut commands: Commands,
-//     asset_server: Res<AssetServer>,
-//     display_quality: Res<DisplayQuality>,
-// ) {
-//     let button_style = Style {
-//         size: Size::new(Val::Px(200.0), Val::Px(65.0)),
-//         margin: UiRect::all(Val::Px(20.0)),
-//         justify_content: JustifyContent::Center,
-//         align_items: AlignItems::Center,
-//         ..default()
-//     };
-//     let button_text_style = TextStyle {
-//         font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-//         font_size: 40.0,
-//         color: TEXT_COLOR,
-//     };
-
-//     commands
-//         .spawn((
-//             NodeBundle {
-//                 style: Style {
-//                     size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
-//                     align_items: AlignItems::Center,
-//                     justify_content: JustifyContent::Center,
-//                     ..default()
-//                 },
-//                 ..default()
-//             },
-//             OnDisplaySettingsMenuScreen,
-//         ))
-//         .with_children(|parent| {
-//             parent
-//                 .spawn(NodeBundle {
-//                     style: Style {
-//                         flex_direction: FlexDirection::Column,
-//                         align_items: AlignItems::Center,
-//                         ..default()
-//                     },
-//                     background_color: BACKGROUND.into(),
-//                     ..default()
-//                 })
-//                 .with_children(|parent| {
-//                     // Create a new `NodeBundle`, this time not setting its `flex_direction`. It will
-//                     // use the default value, `FlexDirection::Row`, from left to right.
-//                     parent
-//                         .spawn(NodeBundle {
-//                             style: Style {
-//                                 align_items: AlignItems::Center,
-//                                 ..default()
-//                             },
-//                             background_color: BACKGROUND.into(),
-//                             ..default()
-//                         })
-//                         .with_children(|parent| {
-//                             // Display a label for the current setting
-//                             parent.spawn(TextBundle::from_section(
-//                                 "Display Quality",
-//                                 button_text_style.clone(),
-//                             ));
-//                             // Display a button for each possible value
-//                             for quality_setting in [
-//                                 DisplayQuality::Low,
-//                                 DisplayQuality::Medium,
-//                                 DisplayQuality::High,
-//                             ] {
-//                                 let mut entity = parent.spawn(ButtonBundle {
-//                                     style: Style {
-//                                         size: Size::new(Val::Px(150.0), Val::Px(65.0)),
-//                                         ..button_style.clone()
-//                                     },
-//                                     background_color: NORMAL_BUTTON.into(),
-//                                     ..default()
-//                                 });
-//                                 entity.insert(quality_setting).with_children(|parent| {
-//                                     parent.spawn(TextBundle::from_section(
-//                                         format!("{quality_setting:?}"),
-//                                         button_text_style.clone(),
-//                                     ));
-//                                 });
-//                                 if *display_quality == quality_setting {
-//                                     entity.insert(SelectedOption);
-//                                 }
-//                             }
-//                         });
-//                     // Display the back button to return to the settings screen
-//                     parent
-//                         .spawn((
-//                             ButtonBundle {
-//                                 style: button_style,
-//                                 background_color: NORMAL_BUTTON.into(),
-//                                 ..default()
-//                             },
-//                             MenuButtonAction::BackToSettings,
-//                         ))
-//                         .with_children(|parent| {
-//                             parent.spawn(TextBundle::from_section("Back", button_text_style));
-//                         });
-//                 });
-//         });
-// }
-
-// fn sound_settings_menu_setup(
-//     mut commands: Commands,
-//     asset_server: Res<AssetServer>,
-//     volume: Res<Volume>,
-// ) {
-//     let button_style = Style {
-//         size: Size::new(Val::Px(200.0), Val::Px(65.0)),
-//         margin: UiRect::all(Val::Px(20.0)),
-//         justify_content: JustifyContent::Center,
-//         align_items: AlignItems::Center,
-//         ..default()
-//     };
-//     let button_text_style = TextStyle {
-//         font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-//         font_size: 40.0,
-//         color: TEXT_COLOR,
-//     };
-
-//     commands
-//         .spawn((
-//             NodeBundle {
-//                 style: Style {
-//                     size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
-//                     align_items: AlignItems::Center,
-//                     justify_content: JustifyContent::Center,
-//                     ..default()
-//                 },
-//                 ..default()
-//             },
-//             OnSoundSettingsMenuScreen,
-//         ))
-//         .with_children(|parent| {
-//             parent
-//                 .spawn(NodeBundle {
-//                     style: Style {
-//                         flex_direction: FlexDirection::Column,
-//                         align_items: AlignItems::Center,
-//                         ..default()
-//                     },
-//                     background_color: BACKGROUND.into(),
-//                     ..default()
-//                 })
-//                 .with_children(|parent| {
-//                     parent
-//                         .spawn(NodeBundle {
-//                             style: Style {
-//                                 align_items: AlignItems::Center,
-//                                 ..default()
-//                             },
-//                             background_color: BACKGROUND.into(),
-//                             ..default()
-//                         })
-//                         .with_children(|parent| {
-//                             parent.spawn(TextBundle::from_section(
-//                                 "Volume",
-//                                 button_text_style.clone(),
-//                             ));
-//                             for volume_setting in [0, 1, 2, 3, 4, 5, 6, 7, 8, 9] {
-//                                 let mut entity = parent.spawn(ButtonBundle {
-//                                     style: Style {
-//                                         size: Size::new(Val::Px(30.0), Val::Px(65.0)),
-//                                         ..button_style.clone()
-//                                     },
-//                                     background_color: NORMAL_BUTTON.into(),
-//                                     ..default()
-//                                 });
-//                                 entity.insert(Volume(volume_setting));
-//                                 if *volume == Volume(volume_setting) {
-//                                     entity.insert(SelectedOption);
-//                                 }
-//                             }
-//                         });
-//                     parent
-//                         .spawn((
-//                             ButtonBundle {
-//                                 style: button_style,
-//                                 background_color: NORMAL_BUTTON.into(),
-//                                 ..default()
-//                             },
-//                             MenuButtonAction::BackToSettings,
-//                         ))
-//                         .with_children(|parent| {
-//                             parent.spawn(TextBundle::from_section("Back", button_text_style));
-//                         });
-//                 });
-//         });
-// }
+fn pause_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let button_style = Style {
+        size: Size::new(Val::Px(300.0), Val::Px(65.0)),
+        margin: UiRect::all(Val::Px(12.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+
+    let button_text_style = TextStyle {
+        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+        font_size: 40.0,
+        color: TEXT_COLOR,
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                // darken the frozen game behind the menu
+                background_color: PAUSE_OVERLAY.into(),
+                ..default()
+            },
+            OnPauseMenuScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    background_color: BACKGROUND.into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn(
+                        TextBundle::from_section(
+                            "Paused",
+                            TextStyle {
+                                font_size: 80.0,
+                                ..button_text_style.clone()
+                            },
+                        )
+                        .with_style(Style {
+                            margin: UiRect::all(Val::Px(50.0)),
+                            ..default()
+                        }),
+                    );
+
+                    for (action, text) in [
+                        (MenuButtonAction::Resume, "Resume"),
+                        (MenuButtonAction::Restart, "Restart"),
+                        (MenuButtonAction::Settings, "Settings"),
+                        (MenuButtonAction::SaveAndQuit, "Save & Quit"),
+                        (MenuButtonAction::QuitToMenu, "Quit to Menu"),
+                    ] {
+                        parent
+                            .spawn((
+                                ButtonBundle {
+                                    style: button_style.clone(),
+                                    background_color: NORMAL_BUTTON.into(),
+                                    ..default()
+                                },
+                                action,
+                            ))
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(
+                                    text,
+                                    button_text_style.clone(),
+                                ));
+                            });
+                    }
+                });
+        });
+}
+
+fn settings_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let button_style = Style {
+        size: Size::new(Val::Px(200.0), Val::Px(65.0)),
+        margin: UiRect::all(Val::Px(20.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+
+    let button_text_style = TextStyle {
+        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+        font_size: 40.0,
+        color: TEXT_COLOR,
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            OnSettingsMenuScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    background_color: BACKGROUND.into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for (action, text) in [
+                        (MenuButtonAction::SettingsDisplay, "Display"),
+                        (MenuButtonAction::SettingsSound, "Sound"),
+                        (MenuButtonAction::SettingsControls, "Controls"),
+                        (MenuButtonAction::BackToMainMenu, "Back"),
+                    ] {
+                        parent
+                            .spawn((
+                                ButtonBundle {
+                                    style: button_style.clone(),
+                                    background_color: NORMAL_BUTTON.into(),
+                                    ..default()
+                                },
+                                action,
+                            ))
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(
+                                    text,
+                                    button_text_style.clone(),
+                                ));
+                            });
+                    }
+                });
+        });
+}
+
+fn new_game_menu_setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    map_size: Res<MapSize>,
+    map_kind: Res<MapKind>,
+    difficulty: Res<Difficulty>,
+) {
+    let button_style = Style {
+        size: Size::new(Val::Px(200.0), Val::Px(65.0)),
+        margin: UiRect::all(Val::Px(20.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let option_style = Style {
+        size: Size::new(Val::Px(170.0), Val::Px(50.0)),
+        margin: UiRect::all(Val::Px(10.0)),
+        ..button_style.clone()
+    };
+    let button_text_style = TextStyle {
+        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+        font_size: 30.0,
+        color: TEXT_COLOR,
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            OnNewGameMenuScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::End,
+                        padding: UiRect::all(Val::Px(10.0)),
+                        ..default()
+                    },
+                    background_color: BACKGROUND.into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    let difficulties = Difficulty::ALL.map(|difficulty| (difficulty, difficulty.label().to_string()));
+                    spawn_setting_row(parent, "Difficulty", &difficulties, *difficulty,
+                        &option_style, &button_text_style);
+
+                    // maps larger than the world are scrolled
+                    let sizes = MapSize::PRESETS
+                        .map(|size| (size, format!("{}x{}", size.width, size.height)));
+                    spawn_setting_row(parent, "Map Size", &sizes, *map_size,
+                        &option_style, &button_text_style);
+                    let kinds = MapKind::ALL.map(|kind| (kind, kind.label().to_string()));
+                    spawn_setting_row(parent, "Map", &kinds, *map_kind,
+                        &option_style, &button_text_style);
+
+                    // Display the start and back buttons side by side
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                align_self: AlignSelf::Center,
+                                ..default()
+                            },
+                            background_color: BACKGROUND.into(),
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            for (action, text) in [
+                                (MenuButtonAction::Play, "Start"),
+                                (MenuButtonAction::BackToMainMenu, "Back"),
+                            ] {
+                                parent
+                                    .spawn((
+                                        ButtonBundle {
+                                            style: button_style.clone(),
+                                            background_color: NORMAL_BUTTON.into(),
+                                            ..default()
+                                        },
+                                        action,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(text, button_text_style.clone()));
+                                    });
+                            }
+                        });
+                });
+        });
+}
+
+// Number of latest runs listed next to the high scores
+const RECENT_RUNS_SHOWN: usize = 10;
+
+fn run_line(run: &RunRecord) -> String {
+    format!("{}  {:>6}  {}  {:<9}  seed {}  {}x{}\n",
+        format_date(run.date),
+        run.score.total,
+        if run.won { "won " } else { "lost" },
+        run.difficulty.label(),
+        run.seed,
+        run.map.width,
+        run.map.height)
+}
+
+fn records_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let button_style = Style {
+        size: Size::new(Val::Px(200.0), Val::Px(65.0)),
+        margin: UiRect::all(Val::Px(20.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let button_text_style = TextStyle {
+        font: font.clone(),
+        font_size: 40.0,
+        color: TEXT_COLOR,
+    };
+    let title_style = TextStyle {
+        font: font.clone(),
+        font_size: 40.0,
+        color: TEXT_COLOR,
+    };
+    let line_style = TextStyle {
+        font,
+        font_size: 20.0,
+        color: TEXT_COLOR,
+    };
+
+    let high_scores = load_high_scores();
+    let high_scores_text = if high_scores.is_empty() {
+        "No finished match yet".to_string()
+    } else {
+        high_scores.iter().enumerate()
+            .map(|(rank, run)| format!("{:>2}. {}", rank + 1, run_line(run)))
+            .collect()
+    };
+    let history_text: String = load_history().iter()
+        .take(RECENT_RUNS_SHOWN)
+        .map(run_line)
+        .collect();
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            OnRecordsMenuScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        padding: UiRect::all(Val::Px(20.0)),
+                        ..default()
+                    },
+                    background_color: BACKGROUND.into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for (title, lines) in [("High Scores", high_scores_text), ("Recent Runs", history_text)] {
+                        if lines.is_empty() {
+                            continue;
+                        }
+                        parent.spawn(TextBundle::from_section(title, title_style.clone()));
+                        parent.spawn(
+                            TextBundle::from_section(lines, line_style.clone()).with_style(Style {
+                                margin: UiRect::all(Val::Px(10.0)),
+                                ..default()
+                            }),
+                        );
+                    }
+                    parent
+                        .spawn((
+                            ButtonBundle {
+                                style: button_style,
+                                background_color: NORMAL_BUTTON.into(),
+                                ..default()
+                            },
+                            MenuButtonAction::BackToMainMenu,
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section("Back", button_text_style));
+                        });
+                });
+        });
+}
+
+// Spawn a row with a label and one button for each value of a setting, the current value
+// being selected
+fn spawn_setting_row<T: Component + PartialEq + Copy>(
+    parent: &mut ChildBuilder,
+    label: &str,
+    options: &[(T, String)],
+    current: T,
+    button_style: &Style,
+    button_text_style: &TextStyle,
+) {
+    // Create a new `NodeBundle`, this time not setting its `flex_direction`. It will
+    // use the default value, `FlexDirection::Row`, from left to right.
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: BACKGROUND.into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            // Display a label for the current setting
+            parent.spawn(TextBundle::from_section(label, button_text_style.clone()));
+            // Display a button for each possible value
+            for (setting, text) in options {
+                let mut entity = parent.spawn(ButtonBundle {
+                    style: button_style.clone(),
+                    background_color: NORMAL_BUTTON.into(),
+                    ..default()
+                });
+                entity.insert(*setting).with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(text.clone(), button_text_style.clone()));
+                });
+                if current == *setting {
+                    entity.insert(SelectedOption);
+                }
+            }
+        });
+}
 
+fn display_settings_menu_setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    display_quality: Res<DisplayQuality>,
+    window_size: Res<WindowSize>,
+    screen_mode: Res<ScreenMode>,
+) {
+    let button_style = Style {
+        size: Size::new(Val::Px(200.0), Val::Px(65.0)),
+        margin: UiRect::all(Val::Px(20.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let option_style = Style {
+        size: Size::new(Val::Px(170.0), Val::Px(50.0)),
+        margin: UiRect::all(Val::Px(10.0)),
+        ..button_style.clone()
+    };
+    let button_text_style = TextStyle {
+        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+        font_size: 30.0,
+        color: TEXT_COLOR,
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            OnDisplaySettingsMenuScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::End,
+                        padding: UiRect::all(Val::Px(10.0)),
+                        ..default()
+                    },
+                    background_color: BACKGROUND.into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    let qualities = [DisplayQuality::Low, DisplayQuality::Medium, DisplayQuality::High]
+                        .map(|quality| (quality, format!("{quality:?}")));
+                    spawn_setting_row(parent, "Display Quality", &qualities, *display_quality,
+                        &option_style, &button_text_style);
+
+                    // sizes above the screen's are left to the window manager
+                    let sizes = [WindowSize::Small, WindowSize::Medium, WindowSize::Large]
+                        .map(|size| {
+                            let (width, height) = size.resolution();
+                            (size, format!("{width}x{height}"))
+                        });
+                    spawn_setting_row(parent, "Window Size", &sizes, *window_size,
+                        &option_style, &button_text_style);
+
+                    let modes = [ScreenMode::Windowed, ScreenMode::Fullscreen]
+                        .map(|mode| (mode, format!("{mode:?}")));
+                    spawn_setting_row(parent, "Screen", &modes, *screen_mode,
+                        &option_style, &button_text_style);
+
+                    // Display the back button to return to the settings screen
+                    parent
+                        .spawn((
+                            ButtonBundle {
+                                style: Style {
+                                    align_self: AlignSelf::Center,
+                                    ..button_style
+                                },
+                                background_color: NORMAL_BUTTON.into(),
+                                ..default()
+                            },
+                            MenuButtonAction::BackToSettings,
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section("Back", button_text_style));
+                        });
+                });
+        });
+}
+
+// A row of buttons picking one of the volumes
+fn spawn_volume_row<T: Component + PartialEq>(
+    parent: &mut ChildBuilder,
+    label: &str,
+    current: T,
+    setting: fn(u32) -> T,
+    button_style: &Style,
+    button_text_style: &TextStyle,
+) {
+    parent
+        .spawn(NodeBundle {
+            style: Style {
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: BACKGROUND.into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(label, button_text_style.clone()));
+            for volume_setting in 0..=Volume::MAX {
+                let mut entity = parent.spawn(ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(30.0), Val::Px(65.0)),
+                        ..button_style.clone()
+                    },
+                    background_color: NORMAL_BUTTON.into(),
+                    ..default()
+                });
+                let button_setting = setting(volume_setting);
+                if button_setting == current {
+                    entity.insert(SelectedOption);
+                }
+                entity.insert(button_setting);
+            }
+        });
+}
+
+fn sound_settings_menu_setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    volume: Res<Volume>,
+    music_volume: Res<MusicVolume>,
+    effects_volume: Res<EffectsVolume>,
+) {
+    let button_style = Style {
+        size: Size::new(Val::Px(200.0), Val::Px(65.0)),
+        margin: UiRect::all(Val::Px(20.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let button_text_style = TextStyle {
+        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+        font_size: 40.0,
+        color: TEXT_COLOR,
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            OnSoundSettingsMenuScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    background_color: BACKGROUND.into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    spawn_volume_row(parent, "Volume", *volume, Volume, &button_style, &button_text_style);
+                    spawn_volume_row(parent, "Music", *music_volume, MusicVolume, &button_style, &button_text_style);
+                    spawn_volume_row(parent, "Effects", *effects_volume, EffectsVolume, &button_style, &button_text_style);
+                    parent
+                        .spawn((
+                            ButtonBundle {
+                                style: button_style,
+                                background_color: NORMAL_BUTTON.into(),
+                                ..default()
+                            },
+                            MenuButtonAction::BackToSettings,
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section("Back", button_text_style));
+                        });
+                });
+        });
+}
+
+fn controls_settings_menu_setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    bindings: Res<Bindings>,
+) {
+    // small enough for every action to fit in the smallest window
+    let binding_style = Style {
+        size: Size::new(Val::Px(190.0), Val::Px(28.0)),
+        margin: UiRect::all(Val::Px(2.0)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..default()
+    };
+    let button_style = Style {
+        size: Size::new(Val::Px(200.0), Val::Px(50.0)),
+        margin: UiRect::all(Val::Px(10.0)),
+        ..binding_style.clone()
+    };
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let label_text_style = TextStyle {
+        font: font.clone(),
+        font_size: 22.0,
+        color: TEXT_COLOR,
+    };
+    let binding_text_style = TextStyle {
+        font: font.clone(),
+        font_size: 18.0,
+        color: TEXT_COLOR,
+    };
+    let button_text_style = TextStyle {
+        font,
+        font_size: 40.0,
+        color: TEXT_COLOR,
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            OnControlsSettingsMenuScreen,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::End,
+                        padding: UiRect::all(Val::Px(10.0)),
+                        ..default()
+                    },
+                    background_color: BACKGROUND.into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    // One row per action, with its keyboard or mouse binding and its gamepad binding
+                    for action in Action::ALL {
+                        parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    align_items: AlignItems::Center,
+                                    ..default()
+                                },
+                                background_color: BACKGROUND.into(),
+                                ..default()
+                            })
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(action.label(), label_text_style.clone()));
+                                for slot in [BindingSlot::KeyboardMouse, BindingSlot::Gamepad] {
+                                    parent
+                                        .spawn((
+                                            ButtonBundle {
+                                                style: binding_style.clone(),
+                                                background_color: NORMAL_BUTTON.into(),
+                                                ..default()
+                                            },
+                                            RebindButton(action, slot),
+                                        ))
+                                        .with_children(|parent| {
+                                            parent.spawn((
+                                                TextBundle::from_section(
+                                                    bindings.get(action).label(slot),
+                                                    binding_text_style.clone(),
+                                                ),
+                                                BindingLabel(action, slot),
+                                            ));
+                                        });
+                                }
+                            });
+                    }
+
+                    // Display the reset and back buttons side by side
+                    parent
+                        .spawn(NodeBundle {
+                            style: Style {
+                                align_self: AlignSelf::Center,
+                                ..default()
+                            },
+                            background_color: BACKGROUND.into(),
+                            ..default()
+                        })
+                        .with_children(|parent| {
+                            parent
+                                .spawn((
+                                    ButtonBundle {
+                                        style: button_style.clone(),
+                                        background_color: NORMAL_BUTTON.into(),
+                                        ..default()
+                                    },
+                                    ResetBindingsButton,
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn(TextBundle::from_section("Reset", button_text_style.clone()));
+                                });
+                            parent
+                                .spawn((
+                                    ButtonBundle {
+                                        style: button_style,
+                                        background_color: NORMAL_BUTTON.into(),
+                                        ..default()
+                                    },
+                                    MenuButtonAction::BackToSettings,
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn(TextBundle::from_section("Back", button_text_style));
+                                });
+                        });
+                });
+        });
+}
+
+// This system starts listening for a new binding when a binding button is clicked, or puts
+// every binding back to its default
+fn rebind_button(
+    rebind_query: Query<(&Interaction, &RebindButton), Changed<Interaction>>,
+    reset_query: Query<&Interaction, (Changed<Interaction>, With<ResetBindingsButton>)>,
+    mut rebinding: ResMut<Rebinding>,
+    mut bindings: ResMut<Bindings>,
+) {
+    for (interaction, rebind_button) in &rebind_query {
+        if *interaction == Interaction::Clicked {
+            rebinding.0 = Some((rebind_button.0, rebind_button.1));
+        }
+    }
+    for interaction in &reset_query {
+        if *interaction == Interaction::Clicked {
+            rebinding.0 = None;
+            bindings.set_if_neq(Bindings::default());
+        }
+    }
+}
+
+fn update_binding_labels(
+    bindings: Res<Bindings>,
+    rebinding: Res<Rebinding>,
+    mut label_query: Query<(&BindingLabel, &mut Text)>,
+) {
+    if !bindings.is_changed() && !rebinding.is_changed() {
+        return;
+    }
+    for (label, mut text) in &mut label_query {
+        text.sections[0].value = if rebinding.0 == Some((label.0, label.1)) {
+            "...".to_string()
+        } else {
+            bindings.get(label.0).label(label.1)
+        };
+    }
+}
+
+fn cancel_rebinding(mut rebinding: ResMut<Rebinding>) {
+    rebinding.0 = None;
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn menu_action(
     interaction_query: Query<
         (&Interaction, &MenuButtonAction),
@@ -513,16 +1227,30 @@ fn menu_action(
     >,
     mut app_exit_events: EventWriter<AppExit>,
     mut menu_state: ResMut<NextState<MenuState>>,
+    current_game_state: Res<State<GameState>>,
     mut game_state: ResMut<NextState<GameState>>,
+    mut restart: ResMut<RestartRequested>,
+    mut save_requested: ResMut<SaveRequested>,
+    mut match_setup: ResMut<MatchSetup>,
 ) {
     for (interaction, menu_button_action) in &interaction_query {
         if *interaction == Interaction::Clicked {
             match menu_button_action {
                 MenuButtonAction::Quit => app_exit_events.send(AppExit),
+                MenuButtonAction::NewGame => menu_state.set(MenuState::NewGame),
                 MenuButtonAction::Play => {
                     menu_state.set(MenuState::Disabled);
                     game_state.set(GameState::Game);
                 }
+                MenuButtonAction::Continue => match load_saved_game() {
+                    Ok(saved_game) => {
+                        match_setup.saved_game = Some(saved_game);
+                        menu_state.set(MenuState::Disabled);
+                        game_state.set(GameState::Game);
+                    }
+                    Err(error) => warn!("could not load the saved game: {}", error),
+                },
+                MenuButtonAction::Records => menu_state.set(MenuState::Records),
                 MenuButtonAction::Settings => menu_state.set(MenuState::Settings),
                 MenuButtonAction::SettingsDisplay => {
                     menu_state.set(MenuState::SettingsDisplay);
@@ -530,10 +1258,34 @@ fn menu_action(
                 MenuButtonAction::SettingsSound => {
                     menu_state.set(MenuState::SettingsSound);
                 }
-                MenuButtonAction::BackToMainMenu => menu_state.set(MenuState::Main),
+                MenuButtonAction::SettingsControls => {
+                    menu_state.set(MenuState::SettingsControls);
+                }
+                // the settings are reachable from both the main menu and the pause menu
+                MenuButtonAction::BackToMainMenu => {
+                    if current_game_state.0 == GameState::Pause {
+                        menu_state.set(MenuState::Pause);
+                    } else {
+                        menu_state.set(MenuState::Main);
+                    }
+                }
                 MenuButtonAction::BackToSettings => {
                     menu_state.set(MenuState::Settings);
                 }
+                MenuButtonAction::Resume => {
+                    menu_state.set(MenuState::Disabled);
+                    game_state.set(GameState::Game);
+                }
+                MenuButtonAction::Restart => {
+                    restart.0 = true;
+                    menu_state.set(MenuState::Disabled);
+                    game_state.set(GameState::Menu);
+                }
+                MenuButtonAction::SaveAndQuit => {
+                    save_requested.0 = true;
+                    game_state.set(GameState::Menu);
+                }
+                MenuButtonAction::QuitToMenu => game_state.set(GameState::Menu),
             }
         }
     }
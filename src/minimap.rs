@@ -0,0 +1,206 @@
+use bevy::{
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+    window::PrimaryWindow,
+};
+
+use super::{GameState, despawn_with_component};
+use crate::base::{Base, BASE_FOOTPRINT_CELLS};
+use crate::camera::{MainCamera, clamp_camera_to_map};
+use crate::controls::Pointer;
+use crate::enemy::EnemyStats;
+use crate::map::{CellCoordinate, Map};
+use crate::tower::TowerStats;
+
+// This plugin shows the whole map in the top left corner of the window, one pixel per cell: the
+// walls, the base, the towers, the enemies and the part of the map in view. Clicking it, or
+// holding the button over it, moves the camera there.
+pub struct MinimapPlugin;
+
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(spawn_minimap)
+        .add_system(update_minimap.run_if(in_state(GameState::Game)))
+        .add_system(move_camera_with_minimap
+            .run_if(in_state(GameState::Game))
+            .before(clamp_camera_to_map))
+        .add_system(despawn_with_component::<Minimap>.in_schedule(OnEnter(GameState::Menu)));
+    }
+}
+
+// Largest size of the minimap on screen, in pixels
+const MINIMAP_MAX_SIZE: Vec2 = Vec2::new(200., 120.);
+// Below the season bar
+const MINIMAP_TOP: f32 = 70.;
+
+const FLOOR_COLOR: [u8; 4] = [30, 30, 30, 200];
+const WALL_COLOR: [u8; 4] = [130, 130, 130, 255];
+const BASE_COLOR: [u8; 4] = [60, 120, 230, 255];
+const TOWER_COLOR: [u8; 4] = [80, 220, 80, 255];
+const ENEMY_COLOR: [u8; 4] = [230, 40, 40, 255];
+const VIEW_COLOR: [u8; 4] = [255, 255, 255, 255];
+
+#[derive(Component)]
+struct Minimap {
+    image: Handle<Image>,
+}
+
+// Cells of the map, from its bottom left corner
+struct MinimapGrid {
+    half_width: i32,
+    half_height: i32,
+}
+
+impl MinimapGrid {
+    fn new(map: &Map) -> Self {
+        MinimapGrid {
+            half_width: (map.width() / 2) as i32,
+            half_height: (map.height() / 2) as i32,
+        }
+    }
+
+    fn width(&self) -> u32 {
+        (2 * self.half_width + 1) as u32
+    }
+
+    fn height(&self) -> u32 {
+        (2 * self.half_height + 1) as u32
+    }
+
+    // Offset of the pixel of a cell in the image data, rows go from the top of the map down
+    fn pixel(&self, cell: CellCoordinate) -> Option<usize> {
+        let column = cell.x + self.half_width;
+        let row = self.half_height - cell.y;
+        let inside = (0..self.width() as i32).contains(&column) && (0..self.height() as i32).contains(&row);
+        inside.then(|| (row as usize * self.width() as usize + column as usize) * 4)
+    }
+}
+
+// Add the minimap of a map once it has been built
+fn spawn_minimap(
+    mut commands: Commands,
+    map_query: Query<&Map, Added<Map>>,
+    mut images: ResMut<Assets<Image>>
+) {
+    for map in map_query.iter() {
+        let grid = MinimapGrid::new(map);
+        let image = images.add(Image::new_fill(
+            Extent3d { width: grid.width(), height: grid.height(), depth_or_array_layers: 1 },
+            TextureDimension::D2,
+            &FLOOR_COLOR,
+            TextureFormat::Rgba8UnormSrgb,
+        ));
+        let pixels_per_cell = (MINIMAP_MAX_SIZE.x / grid.width() as f32).min(MINIMAP_MAX_SIZE.y / grid.height() as f32);
+
+        commands.spawn((
+            // a button, so that clicks on it do not also reach the map underneath
+            ButtonBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        left: Val::Px(10.0),
+                        top: Val::Px(MINIMAP_TOP),
+                        ..default()
+                    },
+                    size: Size::new(Val::Px(grid.width() as f32 * pixels_per_cell),
+                                    Val::Px(grid.height() as f32 * pixels_per_cell)),
+                    ..default()
+                },
+                image: UiImage::new(image.clone()),
+                ..default()
+            },
+            Minimap { image },
+        ));
+    }
+}
+
+fn update_minimap(
+    minimap_query: Query<&Minimap>,
+    map_query: Query<&Map>,
+    base_query: Query<&Transform, With<Base>>,
+    towers_query: Query<&Transform, With<TowerStats>>,
+    enemies_query: Query<&Transform, With<EnemyStats>>,
+    camera_query: Query<(&Transform, &OrthographicProjection), With<MainCamera>>,
+    mut images: ResMut<Assets<Image>>
+) {
+    let (Ok(minimap), Ok(map)) = (minimap_query.get_single(), map_query.get_single()) else {
+        return;
+    };
+    let Some(image) = images.get_mut(&minimap.image) else {
+        return;
+    };
+
+    let grid = MinimapGrid::new(map);
+    let mut paint = |cell: CellCoordinate, color: [u8; 4]| {
+        if let Some(offset) = grid.pixel(cell) {
+            image.data[offset..offset + 4].copy_from_slice(&color);
+        }
+    };
+
+    for x in -grid.half_width..=grid.half_width {
+        for y in -grid.half_height..=grid.half_height {
+            let cell = CellCoordinate { x, y };
+            paint(cell, if map.has_wall(&cell) { WALL_COLOR } else { FLOOR_COLOR });
+        }
+    }
+
+    for base_transform in base_query.iter() {
+        let center = CellCoordinate::from_position(base_transform.translation.truncate());
+        for x in -BASE_FOOTPRINT_CELLS..=BASE_FOOTPRINT_CELLS {
+            for y in -BASE_FOOTPRINT_CELLS..=BASE_FOOTPRINT_CELLS {
+                paint(CellCoordinate { x: center.x + x, y: center.y + y }, BASE_COLOR);
+            }
+        }
+    }
+    for tower_transform in towers_query.iter() {
+        paint(CellCoordinate::from_position(tower_transform.translation.truncate()), TOWER_COLOR);
+    }
+    for enemy_transform in enemies_query.iter() {
+        paint(CellCoordinate::from_position(enemy_transform.translation.truncate()), ENEMY_COLOR);
+    }
+
+    // outline of the part of the map in view
+    for (camera_transform, projection) in camera_query.iter() {
+        let center = camera_transform.translation.truncate();
+        let bottom_left = CellCoordinate::from_position(center + projection.area.min);
+        let top_right = CellCoordinate::from_position(center + projection.area.max);
+        for x in bottom_left.x.max(-grid.half_width)..=top_right.x.min(grid.half_width) {
+            paint(CellCoordinate { x, y: bottom_left.y.max(-grid.half_height) }, VIEW_COLOR);
+            paint(CellCoordinate { x, y: top_right.y.min(grid.half_height) }, VIEW_COLOR);
+        }
+        for y in bottom_left.y.max(-grid.half_height)..=top_right.y.min(grid.half_height) {
+            paint(CellCoordinate { x: bottom_left.x.max(-grid.half_width), y }, VIEW_COLOR);
+            paint(CellCoordinate { x: top_right.x.min(grid.half_width), y }, VIEW_COLOR);
+        }
+    }
+}
+
+// The camera follows the pointer while the minimap is pressed
+fn move_camera_with_minimap(
+    pointer: Res<Pointer>,
+    minimap_query: Query<(&Interaction, &Node, &GlobalTransform), With<Minimap>>,
+    map_query: Query<&Map>,
+    primary_window_query: Query<&Window, With<PrimaryWindow>>,
+    mut camera_query: Query<&mut Transform, With<MainCamera>>
+) {
+    let Ok((interaction, node, global_transform)) = minimap_query.get_single() else {
+        return;
+    };
+    if *interaction != Interaction::Clicked {
+        return;
+    }
+    let (Some(position), Ok(map), Ok(window)) = (pointer.position, map_query.get_single(), primary_window_query.get_single()) else {
+        return;
+    };
+
+    // UI coordinates start at the top left, the map at the bottom left
+    let ui_position = Vec2::new(position.x, window.height() - position.y);
+    let top_left = global_transform.translation().truncate() - node.size() / 2.;
+    let fraction = ((ui_position - top_left) / node.size()).clamp(Vec2::ZERO, Vec2::ONE);
+    let map_rect = map.world_rect();
+    let target = Vec2::new(map_rect.min.x + fraction.x * map_rect.width(),
+                           map_rect.max.y - fraction.y * map_rect.height());
+    for mut camera_transform in camera_query.iter_mut() {
+        camera_transform.translation = target.extend(camera_transform.translation.z);
+    }
+}
@@ -0,0 +1,196 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{GameState, new_game_schedule};
+use crate::base::Base;
+use crate::config::GameConfig;
+use crate::game::end_game;
+use crate::map::{Map, MapLayout};
+use crate::replay::replay_inactive;
+use crate::season::SeasonSchedule;
+use crate::difficulty::{Difficulty, MatchDifficulty};
+use crate::simulation::{MatchSeed, MatchStats};
+use crate::storage;
+
+// This plugin scores every finished match and keeps the best scores and the history of runs
+// between launches. They are shown on the Records screen of the main menu.
+pub struct RecordsPlugin;
+
+impl Plugin for RecordsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LastRun>()
+        .add_system(reset_last_run.in_schedule(new_game_schedule()))
+        // watching a replay does not count as a run
+        .add_system(record_run.before(end_game).in_schedule(OnEnter(GameState::GameWon)).run_if(replay_inactive))
+        .add_system(record_run.before(end_game).in_schedule(OnEnter(GameState::GameLost)).run_if(replay_inactive));
+    }
+}
+
+const HIGH_SCORES_FILE: &str = "highscores.json";
+const HISTORY_FILE: &str = "history.json";
+pub const HIGH_SCORE_COUNT: usize = 10;
+const HISTORY_LENGTH: usize = 50;
+
+// Points of each kind of achievement, set in the game config. The total is then multiplied by
+// the difficulty.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScoreConfig {
+    pub points_per_kill: f32,
+    pub points_per_season: f32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Score {
+    pub base_health: f32,
+    pub kills: u32,
+    pub seasons_survived: usize,
+    pub difficulty_multiplier: f32,
+    pub total: u32,
+}
+
+impl Score {
+    pub fn new(base_health: f32, kills: u32, seasons_survived: usize, difficulty_multiplier: f32, config: &ScoreConfig) -> Self {
+        let points = base_health.max(0.)
+            + kills as f32 * config.points_per_kill
+            + seasons_survived as f32 * config.points_per_season;
+        Score {
+            base_health,
+            kills,
+            seasons_survived,
+            difficulty_multiplier,
+            total: (points * difficulty_multiplier).round() as u32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunRecord {
+    // seconds since the Unix epoch
+    pub date: u64,
+    pub seed: u64,
+    // runs recorded before difficulties existed were played at the normal one
+    #[serde(default)]
+    pub difficulty: Difficulty,
+    pub map: MapLayout,
+    pub won: bool,
+    pub score: Score,
+}
+
+// Outcome of the match that just ended, shown on the end screen
+#[derive(Resource, Default)]
+pub struct LastRun {
+    pub score: Option<Score>,
+    pub high_score_rank: Option<usize>,
+}
+
+fn read_runs(name: &str) -> Vec<RunRecord> {
+    storage::read(name)
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+fn write_runs(name: &str, runs: &[RunRecord]) {
+    let result = serde_json::to_string(runs)
+        .map_err(|error| error.to_string())
+        .and_then(|contents| storage::write(name, &contents));
+    if let Err(error) = result {
+        warn!("could not save {}: {}", name, error);
+    }
+}
+
+// Best runs first
+pub fn load_high_scores() -> Vec<RunRecord> {
+    read_runs(HIGH_SCORES_FILE)
+}
+
+// Latest runs first
+pub fn load_history() -> Vec<RunRecord> {
+    read_runs(HISTORY_FILE)
+}
+
+fn reset_last_run(mut last_run: ResMut<LastRun>) {
+    *last_run = LastRun::default();
+}
+
+#[allow(clippy::too_many_arguments)]
+fn record_run(
+    game_state: Res<State<GameState>>,
+    config: Res<GameConfig>,
+    match_seed: Res<MatchSeed>,
+    match_difficulty: Res<MatchDifficulty>,
+    match_stats: Res<MatchStats>,
+    season_schedule: Res<SeasonSchedule>,
+    base_query: Query<&Base>,
+    map_query: Query<&Map>,
+    mut last_run: ResMut<LastRun>
+) {
+    let (Ok(base), Ok(map)) = (base_query.get_single(), map_query.get_single()) else {
+        return;
+    };
+
+    let score = Score::new(base.health, match_stats.kills, season_schedule.seasons_survived(),
+        match_difficulty.0.modifiers(&config).score, &config.score);
+    let run = RunRecord {
+        date: storage::unix_time_seconds(),
+        seed: match_seed.0,
+        difficulty: match_difficulty.0,
+        map: map.layout(),
+        won: game_state.0 == GameState::GameWon,
+        score,
+    };
+
+    let mut history = load_history();
+    history.insert(0, run.clone());
+    history.truncate(HISTORY_LENGTH);
+    write_runs(HISTORY_FILE, &history);
+
+    let mut high_scores = load_high_scores();
+    let rank = high_scores.iter()
+        .position(|high_score| high_score.score.total < score.total)
+        .unwrap_or(high_scores.len());
+    high_scores.insert(rank, run);
+    high_scores.truncate(HIGH_SCORE_COUNT);
+    write_runs(HIGH_SCORES_FILE, &high_scores);
+
+    last_run.score = Some(score);
+    last_run.high_score_rank = (rank < HIGH_SCORE_COUNT).then_some(rank);
+}
+
+// Calendar date of a Unix timestamp, as YYYY-MM-DD
+pub fn format_date(seconds: u64) -> String {
+    // days to civil date, from http://howardhinnant.github.io/date_algorithms.html
+    let days = (seconds / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn check_score_adds_up_and_scales_with_difficulty() {
+        use super::{Score, ScoreConfig};
+
+        let config = ScoreConfig { points_per_kill: 2., points_per_season: 250. };
+        let score = Score::new(500., 10, 3, 1.5, &config);
+        assert_eq!(score.total, ((500. + 10. * 2. + 3. * 250.) * 1.5) as u32);
+
+        // a destroyed base below zero health costs nothing
+        assert_eq!(Score::new(-40., 0, 1, 1., &config).total, 250);
+    }
+
+    #[test]
+    fn check_format_date() {
+        use super::format_date;
+
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(951_782_400), "2000-02-29");
+        assert_eq!(format_date(1_700_000_000), "2023-11-14");
+    }
+}
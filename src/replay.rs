@@ -0,0 +1,207 @@
+use std::{fs, path::{Path, PathBuf}};
+
+use bevy::prelude::*;
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+
+use super::{GameState, despawn_with_component, new_game_schedule};
+use crate::config::GameConfig;
+use crate::difficulty::{Difficulty, MatchDifficulty};
+use crate::map::{Map, MapLayout};
+use crate::menu::RestartRequested;
+use crate::simulation::{
+    CommandLog, ConfigLog, MatchSeed, MatchSetup, MatchSetupSet, PendingCommands, RngAfterMap, ScheduledCommand,
+    ScheduledConfig, SimulationSet, SimulationTick
+};
+use crate::tower::apply_player_commands;
+use crate::storage;
+
+// This plugin records every match to a replay file when it is left, and plays a replay back
+// when the game is started with `--replay <file>`. A match only depends on its seed, its map, the
+// game config and the player's commands, so feeding the recorded configs and commands back at the
+// same ticks reproduces it.
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ReplayPlayback>()
+        .add_startup_system(begin_playback)
+        // the replayed seed and map have to be in place before the match is rolled
+        .add_system(start_playback.in_schedule(new_game_schedule()).before(MatchSetupSet))
+        .add_system(feed_replay_commands
+            .in_schedule(CoreSchedule::FixedUpdate)
+            .in_set(SimulationSet::Input)
+            .before(apply_player_commands)
+            .run_if(in_state(GameState::Game)))
+        // the map is still around when the menu is entered, it is cleaned up right after
+        .add_system(save_replay
+            .in_schedule(OnEnter(GameState::Menu))
+            .before(despawn_with_component::<Map>));
+    }
+}
+
+const REPLAY_DIRECTORY: &str = "replays";
+
+// Everything needed to play a match again
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    // version of the game that recorded the replay
+    pub version: String,
+    pub seed: u64,
+    // replays recorded before difficulties existed were played at the normal one
+    #[serde(default)]
+    pub difficulty: Difficulty,
+    pub map: MapLayout,
+    // random numbers once the map was built, replays recorded without them only play back
+    // right on maps that were loaded from a file
+    #[serde(default)]
+    pub rng: Option<ChaCha12Rng>,
+    // the config the match started with and those reloaded during it, replays recorded without
+    // them play back with the current config
+    #[serde(default)]
+    pub configs: Vec<ScheduledConfig>,
+    pub commands: Vec<ScheduledCommand>,
+}
+
+// Replay being played back, if any. `started` is set once its match has begun so that leaving
+// that match ends the playback.
+#[derive(Resource, Default)]
+pub struct ReplayPlayback {
+    replay: Option<Replay>,
+    next_command: usize,
+    next_config: usize,
+    started: bool,
+}
+
+impl ReplayPlayback {
+    pub fn new(replay: Replay) -> Self {
+        ReplayPlayback { replay: Some(replay), next_command: 0, next_config: 0, started: false }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.replay.is_some()
+    }
+}
+
+// Run condition for the systems reading the player's input
+pub fn replay_inactive(playback: Res<ReplayPlayback>) -> bool {
+    playback.replay.is_none()
+}
+
+pub fn load_replay(path: &Path) -> Result<Replay, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|error| format!("could not read {}: {}", path.display(), error))?;
+    serde_json::from_str(&contents).map_err(|error| format!("invalid replay {}: {}", path.display(), error))
+}
+
+fn write_replay(replay: &Replay) -> Result<PathBuf, String> {
+    fs::create_dir_all(REPLAY_DIRECTORY).map_err(|error| error.to_string())?;
+    let seconds = storage::unix_time_seconds();
+    let path = Path::new(REPLAY_DIRECTORY).join(format!("replay-{}-{}.json", seconds, replay.seed));
+    let contents = serde_json::to_string_pretty(replay).map_err(|error| error.to_string())?;
+    fs::write(&path, contents).map_err(|error| error.to_string())?;
+    Ok(path)
+}
+
+// Skip the main menu and go straight into the replayed match
+fn begin_playback(playback: Res<ReplayPlayback>, mut restart: ResMut<RestartRequested>) {
+    if playback.replay.is_some() {
+        restart.0 = true;
+    }
+}
+
+fn start_playback(
+    mut playback: ResMut<ReplayPlayback>,
+    mut match_setup: ResMut<MatchSetup>,
+    mut game_config: ResMut<GameConfig>
+) {
+    let Some(replay) = playback.replay.clone() else {
+        return;
+    };
+    match_setup.seed = Some(replay.seed);
+    match_setup.layout = Some(replay.map);
+    match_setup.rng = replay.rng;
+    match_setup.difficulty = Some(replay.difficulty);
+    match replay.configs.first() {
+        Some(scheduled) => *game_config = scheduled.config.clone(),
+        None => warn!("the replay has no game config, it plays back with the current one"),
+    }
+    playback.next_command = 0;
+    playback.next_config = 1;
+    playback.started = true;
+}
+
+fn feed_replay_commands(
+    mut playback: ResMut<ReplayPlayback>,
+    simulation_tick: Res<SimulationTick>,
+    mut pending_commands: ResMut<PendingCommands>,
+    mut game_config: ResMut<GameConfig>
+) {
+    let (next_command, next_config) = (playback.next_command, playback.next_config);
+    let Some(replay) = &playback.replay else {
+        return;
+    };
+
+    // the config reloaded during the match is applied before the commands of its step
+    let due_configs = replay.configs.iter().skip(next_config)
+        .take_while(|scheduled| scheduled.tick <= simulation_tick.0)
+        .map(|scheduled| &scheduled.config)
+        .collect::<Vec<_>>();
+    if let Some(&config) = due_configs.last() {
+        *game_config = config.clone();
+    }
+    let due_configs = due_configs.len();
+
+    let due = replay.commands[next_command..].iter()
+        .take_while(|scheduled| scheduled.tick <= simulation_tick.0)
+        .map(|scheduled| scheduled.command)
+        .collect::<Vec<_>>();
+    pending_commands.0.extend(due.iter().copied());
+    playback.next_command += due.len();
+    playback.next_config += due_configs;
+}
+
+fn save_replay(
+    mut playback: ResMut<ReplayPlayback>,
+    match_seed: Res<MatchSeed>,
+    match_difficulty: Res<MatchDifficulty>,
+    command_log: Res<CommandLog>,
+    config_log: Res<ConfigLog>,
+    rng_after_map: Res<RngAfterMap>,
+    map_query: Query<&Map>
+) {
+    // a replay that has been watched is not recorded again
+    if playback.started {
+        *playback = ReplayPlayback::default();
+        return;
+    }
+
+    // the browser has no directory to keep replays in
+    if cfg!(target_arch = "wasm32") {
+        return;
+    }
+
+    // no match has been played since the last visit to the menu
+    let Ok(map) = map_query.get_single() else {
+        return;
+    };
+    // a match continued from a saved game did not start from its seed
+    let Some(rng) = rng_after_map.0.clone() else {
+        info!("the match was continued from a saved game, it is not recorded");
+        return;
+    };
+
+    let replay = Replay {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        seed: match_seed.0,
+        difficulty: match_difficulty.0,
+        map: map.layout(),
+        rng: Some(rng),
+        configs: config_log.0.clone(),
+        commands: command_log.0.clone(),
+    };
+    match write_replay(&replay) {
+        Ok(path) => info!("replay saved to {}", path.display()),
+        Err(error) => warn!("could not save the replay: {}", error),
+    }
+}
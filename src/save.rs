@@ -0,0 +1,324 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+
+use super::{GameState, new_game_schedule};
+use crate::base::Base;
+use crate::difficulty::{Difficulty, MatchDifficulty};
+use crate::config::GameConfig;
+use crate::heal::HealCharges;
+use crate::boss::{Boss, BossStage, BOSS_SCALE};
+use crate::bullet::Bullet;
+use crate::enemy::{EnemyBundle, EnemyStats, WaveTimer};
+use crate::game::{self, Coins, start_match};
+use crate::map::{CellCoordinate, Map, MapLayout};
+use crate::season::{ElapsedCounter, SavedSeasonSchedule, Season, SeasonSchedule};
+use crate::simulation::{
+    CommandLog, Interpolated, MatchSeed, MatchSetup, MatchSetupSet, MatchStats, PendingCommands, RngAfterMap,
+    ScheduledCommand, SimulationRng, SimulationTick
+};
+use crate::storage;
+use crate::tower::{TowerBundle, TowerState, TowerStats};
+
+// This plugin saves the match in progress when the player picks "Save & Quit" in the pause
+// menu, and restores it when "Continue" is picked in the main menu
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SaveRequested>()
+        .add_system(save_game.in_schedule(OnExit(GameState::Pause)))
+        // a saved game replaces the rolled setup, which is skipped while one is pending
+        .add_system(restore_saved_game
+            .in_set(MatchSetupSet)
+            .after(start_match)
+            .in_schedule(new_game_schedule()));
+    }
+}
+
+const SAVE_FILE: &str = "savegame.json";
+
+// Set by the pause menu, the match is saved as the pause menu is left
+#[derive(Resource, Default)]
+pub struct SaveRequested(pub bool);
+
+// Everything the simulation needs to go on with a match where it was left
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedGame {
+    // version of the game that saved the match
+    pub version: String,
+    seed: u64,
+    // games saved before difficulties existed were played at the normal one
+    #[serde(default)]
+    difficulty: Difficulty,
+    tick: u64,
+    rng: ChaCha12Rng,
+    coins: u32,
+    stats: MatchStats,
+    commands: Vec<ScheduledCommand>,
+    map: MapLayout,
+    base: Base,
+    towers: Vec<SavedTower>,
+    enemies: Vec<SavedEnemy>,
+    bullets: Vec<SavedBullet>,
+    seasons: SavedSeasonSchedule,
+    seconds_elapsed: f32,
+    wave_elapsed: f32,
+    force_wave: bool,
+    #[serde(default)]
+    extra_enemies: f32,
+    // games saved before heals were counted get a full set in a heal season
+    #[serde(default)]
+    heal_charges: Option<HealCharges>,
+    #[serde(default)]
+    boss_stage: BossStage,
+    // config the match was saved with, games saved before it was recorded are not checked
+    #[serde(default)]
+    config: Option<GameConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SavedTower {
+    cell: CellCoordinate,
+    stats: TowerStats,
+    cooldown_elapsed: f32,
+    kills: u32,
+    damage_dealt: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SavedEnemy {
+    x: f32,
+    y: f32,
+    health: f32,
+    destination: (f32, f32),
+    speed: f32,
+    damage: f32,
+    #[serde(default)]
+    boss: Option<Boss>,
+}
+
+// Bullets refer to their target and the tower that fired them by index in the saved lists
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SavedBullet {
+    x: f32,
+    y: f32,
+    target: usize,
+    source: Option<usize>,
+    damage: f32,
+    speed: f32,
+}
+
+pub fn saved_game_exists() -> bool {
+    storage::read(SAVE_FILE).is_some()
+}
+
+pub fn load_saved_game() -> Result<SavedGame, String> {
+    let contents = storage::read(SAVE_FILE).ok_or("there is no saved game")?;
+    serde_json::from_str(&contents).map_err(|error| format!("invalid saved game: {}", error))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn save_game(
+    mut save_requested: ResMut<SaveRequested>,
+    match_seed: Res<MatchSeed>,
+    match_difficulty: Res<MatchDifficulty>,
+    simulation_tick: Res<SimulationTick>,
+    simulation_rng: Res<SimulationRng>,
+    coins: Res<Coins>,
+    match_stats: Res<MatchStats>,
+    command_log: Res<CommandLog>,
+    season_schedule: Res<SeasonSchedule>,
+    elapsed_counter: Res<ElapsedCounter>,
+    // grouped to stay within the number of parameters a system can take
+    (wave_timer, heal_charges, boss_stage, game_config): (Res<WaveTimer>, Res<HealCharges>, Res<BossStage>, Res<GameConfig>),
+    map_query: Query<&Map>,
+    base_query: Query<&Base>,
+    towers_query: Query<(Entity, &CellCoordinate, &TowerStats, &TowerState)>,
+    enemies_query: Query<(Entity, &EnemyStats, &Transform, Option<&Boss>)>,
+    bullets_query: Query<(&Bullet, &Transform)>
+) {
+    if !save_requested.0 {
+        return;
+    }
+    save_requested.0 = false;
+
+    let (Ok(map), Ok(base)) = (map_query.get_single(), base_query.get_single()) else {
+        return;
+    };
+
+    let tower_entities: Vec<Entity> = towers_query.iter().map(|(tower_entity, ..)| tower_entity).collect();
+    let enemy_entities: Vec<Entity> = enemies_query.iter().map(|(enemy_entity, ..)| enemy_entity).collect();
+
+    let saved_game = SavedGame {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        seed: match_seed.0,
+        difficulty: match_difficulty.0,
+        tick: simulation_tick.0,
+        rng: simulation_rng.0.clone(),
+        coins: coins.0,
+        stats: *match_stats,
+        commands: command_log.0.clone(),
+        map: map.layout(),
+        base: base.clone(),
+        towers: towers_query.iter()
+            .map(|(_, &cell, tower_stat, tower_state)| SavedTower {
+                cell,
+                stats: tower_stat.clone(),
+                cooldown_elapsed: tower_state.timer.elapsed_secs(),
+                kills: tower_state.kills,
+                damage_dealt: tower_state.damage_dealt,
+            })
+            .collect(),
+        enemies: enemies_query.iter()
+            .map(|(_, enemy_stat, transform, boss)| SavedEnemy {
+                x: transform.translation.x,
+                y: transform.translation.y,
+                health: enemy_stat.health,
+                destination: (enemy_stat.destination.x, enemy_stat.destination.y),
+                speed: enemy_stat.speed,
+                damage: enemy_stat.damage,
+                boss: boss.cloned(),
+            })
+            .collect(),
+        // a bullet whose target is gone is dropped on the next step anyway
+        bullets: bullets_query.iter()
+            .filter_map(|(bullet, transform)| Some(SavedBullet {
+                x: transform.translation.x,
+                y: transform.translation.y,
+                target: enemy_entities.iter().position(|&enemy_entity| enemy_entity == bullet.target)?,
+                source: tower_entities.iter().position(|&tower_entity| tower_entity == bullet.source),
+                damage: bullet.damage,
+                speed: bullet.speed,
+            }))
+            .collect(),
+        seasons: season_schedule.to_saved(),
+        seconds_elapsed: elapsed_counter.seconds_elapsed,
+        wave_elapsed: wave_timer.timer.elapsed_secs(),
+        force_wave: wave_timer.force_wave,
+        extra_enemies: wave_timer.extra_enemies,
+        heal_charges: Some(*heal_charges),
+        boss_stage: *boss_stage,
+        config: Some(game_config.clone()),
+    };
+
+    let result = serde_json::to_string(&saved_game)
+        .map_err(|error| error.to_string())
+        .and_then(|contents| storage::write(SAVE_FILE, &contents));
+    match result {
+        Ok(()) => info!("game saved"),
+        Err(error) => warn!("could not save the game: {}", error),
+    }
+}
+
+// Rebuild a saved match. This works on the world directly so that everything exists before
+// the rest of the new game setup runs. The save is used up, a match can only be continued once.
+fn restore_saved_game(world: &mut World) {
+    let Some(saved_game) = world.resource_mut::<MatchSetup>().saved_game.take() else {
+        return;
+    };
+    if let Err(error) = storage::remove(SAVE_FILE) {
+        warn!("could not remove the saved game: {}", error);
+    }
+
+    world.insert_resource(MatchSeed(saved_game.seed));
+    world.insert_resource(MatchDifficulty(saved_game.difficulty));
+    world.insert_resource(SimulationTick(saved_game.tick));
+    world.insert_resource(SimulationRng(saved_game.rng));
+    world.insert_resource(RngAfterMap(None));
+    world.insert_resource(Coins(saved_game.coins));
+    world.insert_resource(saved_game.stats);
+    world.insert_resource(CommandLog(saved_game.commands));
+    world.resource_mut::<PendingCommands>().0.clear();
+
+    world.spawn(Map::from_layout(saved_game.map));
+
+    let config = world.resource::<GameConfig>().clone();
+    if saved_game.config.as_ref().is_some_and(|saved_config| *saved_config != config) {
+        warn!("the game config changed since the game was saved, the match goes on with the current one");
+    }
+    let mut base = saved_game.base;
+    if base.max_health == 0. {
+        base.max_health = saved_game.difficulty.base_health(&config) * config.heal.max_health;
+    }
+    let base_scale = game::compute_scale(base.health, base.max_health);
+    world.spawn((base,
+        TransformBundle::from_transform(Transform::default().with_scale(Vec3::splat(base_scale)))));
+
+    let tower_entities: Vec<Entity> = saved_game.towers.into_iter()
+        .map(|saved_tower| {
+            let Vec2 { x, y } = saved_tower.cell.to_position();
+            let mut tower_bundle = TowerBundle::new(x, y, saved_tower.stats.kind, saved_game.difficulty, &config);
+            tower_bundle.state.timer.set_elapsed(Duration::from_secs_f32(saved_tower.cooldown_elapsed));
+            tower_bundle.state.kills = saved_tower.kills;
+            tower_bundle.state.damage_dealt = saved_tower.damage_dealt;
+            let max_health = tower_bundle.stats.max_health;
+            tower_bundle.stats = saved_tower.stats;
+            if tower_bundle.stats.max_health == 0. {
+                tower_bundle.stats.max_health = max_health;
+            }
+            let scale = game::compute_scale(tower_bundle.stats.health, tower_bundle.stats.max_health);
+            world.spawn((tower_bundle, saved_tower.cell,
+                TransformBundle::from_transform(Transform::from_xyz(x, y, 3.).with_scale(Vec3::splat(scale)))))
+                .id()
+        })
+        .collect();
+
+    let enemy_entities: Vec<Entity> = saved_game.enemies.into_iter()
+        .map(|saved_enemy| {
+            let (destination_x, destination_y) = saved_enemy.destination;
+            let mut enemy_bundle = EnemyBundle::new(saved_enemy.x, saved_enemy.y, Vec2::new(destination_x, destination_y),
+                saved_game.difficulty, &config);
+            enemy_bundle.stats.health = saved_enemy.health;
+            enemy_bundle.stats.speed = saved_enemy.speed;
+            enemy_bundle.stats.damage = saved_enemy.damage;
+            let Some(boss) = saved_enemy.boss else {
+                return world.spawn((enemy_bundle,
+                    TransformBundle::from_transform(Transform::from_xyz(saved_enemy.x, saved_enemy.y, 1.).with_scale(Vec3::splat(0.075)))))
+                    .id();
+            };
+            world.spawn((enemy_bundle, boss,
+                TransformBundle::from_transform(Transform::from_xyz(saved_enemy.x, saved_enemy.y, 1.5).with_scale(Vec3::splat(BOSS_SCALE)))))
+                .id()
+        })
+        .collect();
+
+    for saved_bullet in saved_game.bullets {
+        let Some(&target) = enemy_entities.get(saved_bullet.target) else {
+            continue;
+        };
+        world.spawn((
+            TransformBundle::from_transform(Transform::from_xyz(saved_bullet.x, saved_bullet.y, 0.)),
+            Bullet {
+                target,
+                source: saved_bullet.source
+                    .and_then(|index| tower_entities.get(index).copied())
+                    .unwrap_or(Entity::PLACEHOLDER),
+                damage: saved_bullet.damage,
+                speed: saved_bullet.speed
+            },
+            Interpolated::new(Vec3::new(saved_bullet.x, saved_bullet.y, 0.)),
+        ));
+    }
+
+    let season_schedule = SeasonSchedule::from_saved(&saved_game.seasons);
+    let season: Season = season_schedule.current_season();
+    world.insert_resource(season_schedule);
+    world.resource_mut::<NextState<Season>>().set(season);
+    apply_state_transition::<Season>(world);
+    world.resource_mut::<ElapsedCounter>().seconds_elapsed = saved_game.seconds_elapsed;
+
+    let mut wave_timer = world.resource_mut::<WaveTimer>();
+    wave_timer.timer.set_elapsed(Duration::from_secs_f32(saved_game.wave_elapsed));
+    wave_timer.force_wave = saved_game.force_wave;
+    wave_timer.extra_enemies = saved_game.extra_enemies;
+    // the heals already used in the current season stay used
+    let heal_charges = saved_game.heal_charges.unwrap_or_else(|| match season {
+        Season::Heal => HealCharges(config.heal.charges_per_season),
+        _ => HealCharges::default(),
+    });
+    world.insert_resource(heal_charges);
+    world.insert_resource(saved_game.boss_stage);
+}
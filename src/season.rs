@@ -1,29 +1,23 @@
-use bevy::{prelude::*,
-            window::PrimaryWindow,
-            sprite::MaterialMesh2dBundle,
-};
-use std::{time::Duration, num};
+use bevy::prelude::*;
+use std::time::Duration;
 
-use super::GameState;
+use super::{GameState, new_game_schedule};
+use crate::simulation::{SimulationRng, MatchSetupSet};
+use crate::config::GameConfig;
+use crate::boss::BossStage;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 pub struct SeasonPlugin;
 
 impl Plugin for SeasonPlugin {
     fn build(&self, app: &mut App) {
         app.add_system(update_season_bar.run_if(in_state(GameState::Game)))
-           .add_system(initialize_season_bar.in_schedule(OnEnter(GameState::Game)))
-           .add_state::<Season>()
-           .insert_resource(ElapsedCounter {seconds_elapsed: 0., pixels_per_second: 0.})
-           .insert_resource(SeasonSchedule {
-            intervals: vec![SeasonInterval{season: Season::Build, duration: 10.}],
-            current_season_index: 0,
-            current_season_timer: Timer::new(Duration::from_secs(10. as u64), TimerMode::Once)
-           });
+           .add_system(initialize_season_bar.in_schedule(new_game_schedule()).after(MatchSetupSet));
     }
 }
 
 
-#[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Hash, States)]
+#[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Hash, States, Serialize, Deserialize)]
 pub enum Season {
       #[default]
       Build,
@@ -42,17 +36,82 @@ fn season_color(season: Season) -> Color {
     }
 }
 
-#[derive(Resource)]
+#[derive(Resource, Default)]
 pub struct ElapsedCounter {
-    pub seconds_elapsed: f32,
-    pub pixels_per_second: f32
+    pub seconds_elapsed: f32
 }
 
 #[derive(Resource)]
 pub struct SeasonSchedule {
     intervals: Vec<SeasonInterval>,
     current_season_timer: Timer,
-    current_season_index: usize
+    current_season_index: usize,
+    // the last season ends with a boss that has to be defeated to win
+    boss: bool
+}
+
+impl Default for SeasonSchedule {
+    fn default() -> Self {
+        SeasonSchedule {
+            intervals: vec![SeasonInterval{season: Season::Build, duration: 10.}],
+            current_season_index: 0,
+            current_season_timer: Timer::new(Duration::from_secs(10. as u64), TimerMode::Once),
+            boss: false
+        }
+    }
+}
+
+// Progress through the seasons as stored in saved games
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedSeasonSchedule {
+    intervals: Vec<(Season, f32)>,
+    current_season_index: usize,
+    current_season_elapsed: f32,
+    #[serde(default)]
+    boss: bool
+}
+
+impl SeasonSchedule {
+    pub fn to_saved(&self) -> SavedSeasonSchedule {
+        SavedSeasonSchedule {
+            intervals: self.intervals.iter().map(|interval| (interval.season, interval.duration)).collect(),
+            current_season_index: self.current_season_index,
+            current_season_elapsed: self.current_season_timer.elapsed_secs(),
+            boss: self.boss
+        }
+    }
+
+    pub fn from_saved(saved: &SavedSeasonSchedule) -> Self {
+        let intervals: Vec<SeasonInterval> = saved.intervals.iter()
+            .map(|&(season, duration)| SeasonInterval { season, duration })
+            .collect();
+        let duration = intervals.get(saved.current_season_index).map_or(0., |interval| interval.duration);
+        let mut current_season_timer = Timer::new(Duration::from_secs(duration as u64), TimerMode::Once);
+        current_season_timer.set_elapsed(Duration::from_secs_f32(saved.current_season_elapsed));
+        SeasonSchedule {
+            intervals,
+            current_season_index: saved.current_season_index,
+            current_season_timer,
+            boss: saved.boss
+        }
+    }
+
+    pub fn current_season(&self) -> Season {
+        self.intervals.get(self.current_season_index).map_or(Season::Build, |interval| interval.season)
+    }
+
+    // Move past the last season once the boss that ends it is defeated
+    pub fn finish(&mut self) {
+        self.current_season_index = self.intervals.len();
+    }
+
+    // Number of heal seasons the player has already reached
+    pub fn seasons_survived(&self) -> usize {
+        self.intervals.iter()
+            .take(self.current_season_index)
+            .filter(|interval| interval.season == Season::Heal)
+            .count()
+    }
 }
 
 struct SeasonInterval {
@@ -63,91 +122,111 @@ struct SeasonInterval {
 pub const SEASON_BAR_HEIGHT: f32 = 30.;
 
 #[derive(Component)]
-pub struct SeasonBarTimeIndicator;
+pub struct SeasonBarTimeIndicator {
+    // share of the bar the indicator moves by every second, in percent
+    percent_per_second: f32
+}
 
 #[derive(Component)]
 pub struct SeasonBarPart;
 
-fn initialize_season_bar(mut commands: Commands, 
+// Roll the seasons of a new match: a first build season followed by build/heal pairs, their
+// number and lengths are drawn from the ranges in the game config
+pub fn start_season_schedule(
     mut elapsed_counter: ResMut<ElapsedCounter>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    primary_window_query: Query<&Window, With<PrimaryWindow>>,
     mut season_schedule: ResMut<SeasonSchedule>,
     mut current_season: ResMut<NextState<Season>>,
+    mut simulation_rng: ResMut<SimulationRng>,
+    config: Res<GameConfig>,
 ) {
-    info!("initialize season bar");
-    season_schedule.intervals = vec![SeasonInterval{season: Season::Build, duration: 10.}];
-    
+    let seasons = &config.seasons;
+    season_schedule.intervals = vec![SeasonInterval{season: Season::Build, duration: seasons.first_build_seconds as f32}];
 
-    let mut rng = rand::thread_rng();
-    let num_seasons = rng.gen_range(3..6);
+    let num_seasons = simulation_rng.gen_range(seasons.pairs.0..=seasons.pairs.1);
     for _ in 0..num_seasons {
-        let build_length = rng.gen_range(8..16);
-        let heal_length = rng.gen_range(3..10);
+        let build_length = simulation_rng.gen_range(seasons.build_seconds.0..=seasons.build_seconds.1);
+        let heal_length = simulation_rng.gen_range(seasons.heal_seconds.0..=seasons.heal_seconds.1);
         season_schedule.intervals.push(SeasonInterval { season: Season::Build, duration: build_length as f32 });
         season_schedule.intervals.push(SeasonInterval { season: Season::Heal, duration: heal_length as f32 });
     }
 
     season_schedule.current_season_index = 0;
+    season_schedule.boss = config.boss.is_some();
     let next_interval = season_schedule.intervals.get(season_schedule.current_season_index).unwrap();
     current_season.set(next_interval.season);
     season_schedule.current_season_timer = Timer::new(Duration::from_secs(next_interval.duration as u64), TimerMode::Once);
+    elapsed_counter.seconds_elapsed = 0.;
+}
 
-    let Ok(window) = primary_window_query.get_single() else {
-        panic!("no window!");
-    };
+// The bar is part of the HUD so that it stays across the top of the window while the camera moves
+fn initialize_season_bar(mut commands: Commands, 
+    season_schedule: Res<SeasonSchedule>,
+) {
+    info!("initialize season bar");
 
     let total_duration: f32 = season_schedule.intervals.iter()
         .map(|interval| interval.duration)
         .sum();
 
-    elapsed_counter.seconds_elapsed = 0.;
-    elapsed_counter.pixels_per_second = window.width() / total_duration;
-
-
-    let bar_y = window.height()/2. - SEASON_BAR_HEIGHT;
-    let mut accumulated_shift = -window.width() / 2.;
-
-    commands.spawn((SpriteBundle {
-        sprite: Sprite {
-            color: Color::WHITE,
-            custom_size: Some(Vec2::new(10., SEASON_BAR_HEIGHT)),
+    // the interaction tracks the pointer, so that clicks on the bar do not reach the map underneath
+    commands.spawn((NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(0.),
+                top: Val::Px(SEASON_BAR_HEIGHT / 2.),
+                ..default()
+            },
+            size: Size::new(Val::Percent(100.), Val::Px(SEASON_BAR_HEIGHT)),
             ..default()
         },
-        transform: Transform::from_translation(Vec3::new(accumulated_shift+5., bar_y, 5.)),
         ..default()
-    }, SeasonBarTimeIndicator, SeasonBarPart));
-
-    for interval in season_schedule.intervals.iter() {
-        let width = interval.duration / total_duration * window.width();
-        commands.spawn((SpriteBundle {
-            sprite: Sprite {
-                color: season_color(interval.season),
-                custom_size: Some(Vec2::new(width, SEASON_BAR_HEIGHT)),
+    }, Interaction::default(), SeasonBarPart)).with_children(|parent| {
+        for interval in season_schedule.intervals.iter() {
+            parent.spawn(NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(interval.duration / total_duration * 100.), Val::Percent(100.)),
+                    ..default()
+                },
+                background_color: season_color(interval.season).into(),
+                ..default()
+            });
+        }
+
+        parent.spawn((NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Percent(0.),
+                    ..default()
+                },
+                size: Size::new(Val::Px(10.), Val::Percent(100.)),
                 ..default()
             },
-            transform: Transform::from_translation(Vec3::new(accumulated_shift+width/2., bar_y, 4.)),
+            background_color: Color::WHITE.into(),
             ..default()
-        }, SeasonBarPart));
-
-        accumulated_shift += width;
-    }
-    
+        }, SeasonBarTimeIndicator {percent_per_second: 100. / total_duration}));
+    });
 }
 
 
-fn update_season_bar(time: Res<Time>,
+pub fn advance_season(fixed_time: Res<FixedTime>,
     mut elapsed_counter: ResMut<ElapsedCounter>, 
-    mut season_time_indicator_query: Query<(&SeasonBarTimeIndicator, &mut Transform)>,
     mut season_schedule: ResMut<SeasonSchedule>,
     mut current_season: ResMut<NextState<Season>>,
+    mut boss_stage: ResMut<BossStage>,
     mut game_state: ResMut<NextState<GameState>>
 ) {
-    let delta = time.delta_seconds();
+    elapsed_counter.seconds_elapsed += fixed_time.period.as_secs_f32();
 
-    season_schedule.current_season_timer.tick(time.delta());
-    if season_schedule.current_season_timer.finished() {
+    season_schedule.current_season_timer.tick(fixed_time.period);
+    if season_schedule.current_season_timer.just_finished() {
+        // the last season goes on until its boss is defeated
+        if season_schedule.boss && season_schedule.current_season_index + 1 == season_schedule.intervals.len() {
+            info!("the boss is coming");
+            *boss_stage = BossStage::Due;
+            return;
+        }
         season_schedule.current_season_index += 1;
         if season_schedule.current_season_index < season_schedule.intervals.len() {
             let next_interval = season_schedule.intervals.get(season_schedule.current_season_index).unwrap();
@@ -159,12 +238,19 @@ fn update_season_bar(time: Res<Time>,
             game_state.set(GameState::GameWon);
         }
     }
+}
 
-    let Ok((_, mut transform)) = season_time_indicator_query.get_single_mut() else {
+fn update_season_bar(fixed_time: Res<FixedTime>,
+    elapsed_counter: Res<ElapsedCounter>, 
+    mut season_time_indicator_query: Query<(&SeasonBarTimeIndicator, &mut Style)>
+) {
+    let Ok((indicator, mut style)) = season_time_indicator_query.get_single_mut() else {
         info!("no bar indicator!");
         return;
     };
 
-    transform.translation.x += delta * elapsed_counter.pixels_per_second;
-    
+    // include the part of a step that has already passed so the indicator moves smoothly
+    // and stop at the end of the bar while a boss is fought
+    let seconds_elapsed = elapsed_counter.seconds_elapsed + fixed_time.accumulated().as_secs_f32();
+    style.position.left = Val::Percent((seconds_elapsed * indicator.percent_per_second).min(100.));
 }
\ No newline at end of file
@@ -0,0 +1,157 @@
+use bevy::{
+    prelude::*,
+    sprite::MaterialMesh2dBundle
+};
+
+use super::{GameState, despawn_with_component, TEXT_COLOR};
+use crate::controls::{Action, Pointer};
+use crate::config::GameConfig;
+use crate::synergy::Synergy;
+use crate::tower::{TowerStats, TowerState, Relocating, tower_under_cursor, sell_value};
+
+// This plugin lets the player inspect a tower: selecting it (right click) opens a side panel with its
+// stats and draws its range, selecting anywhere else closes it again
+pub struct SelectionPlugin;
+
+impl Plugin for SelectionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(select_tower.run_if(in_state(GameState::Game)))
+        .add_system(update_selection_panel.run_if(in_state(GameState::Game)))
+        .add_system(despawn_with_component::<SelectionPanel>.in_schedule(OnEnter(GameState::Menu)))
+        .add_system(despawn_with_component::<RangeIndicator>.in_schedule(OnEnter(GameState::Menu)));
+    }
+}
+
+const PANEL_COLOR: Color = Color::rgba(0.1, 0.1, 0.1, 0.8);
+const RANGE_COLOR: Color = Color::rgba(1., 1., 1., 0.15);
+
+// Marks the tower currently shown in the panel
+#[derive(Component)]
+pub struct Selected;
+
+#[derive(Component)]
+struct SelectionPanel;
+
+#[derive(Component)]
+struct SelectionPanelText;
+
+#[derive(Component)]
+struct RangeIndicator;
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn select_tower(
+    mut commands: Commands,
+    actions: Res<Input<Action>>,
+    pointer: Res<Pointer>,
+    towers_query: Query<(Entity, &TowerStats, &Transform), Without<Relocating>>,
+    selected_query: Query<Entity, With<Selected>>,
+    panel_query: Query<Entity, Or<(With<SelectionPanel>, With<RangeIndicator>)>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    asset_server: Res<AssetServer>
+) {
+    if !actions.just_pressed(Action::Select) {
+        return;
+    }
+
+    let Some(position) = pointer.world_position else {
+        return;
+    };
+
+    for selected_entity in selected_query.iter() {
+        commands.entity(selected_entity).remove::<Selected>();
+    }
+    for panel_entity in panel_query.iter() {
+        commands.entity(panel_entity).despawn_recursive();
+    }
+
+    let Some((tower_entity, tower_stat)) = tower_under_cursor(position, towers_query.iter()) else {
+        return;
+    };
+
+    commands.entity(tower_entity).insert(Selected);
+
+    commands.spawn((
+        MaterialMesh2dBundle {
+            mesh: meshes.add(shape::Circle::new(tower_stat.range).into()).into(),
+            material: materials.add(ColorMaterial::from(RANGE_COLOR)),
+            transform: Transform::from_xyz(tower_stat.x, tower_stat.y, 2.5),
+            ..default()
+        },
+        RangeIndicator,
+    ));
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        right: Val::Px(10.0),
+                        top: Val::Px(60.0),
+                        ..default()
+                    },
+                    padding: UiRect::all(Val::Px(10.0)),
+                    ..default()
+                },
+                background_color: PANEL_COLOR.into(),
+                ..default()
+            },
+            SelectionPanel,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                        font_size: 20.0,
+                        color: TEXT_COLOR,
+                    },
+                ),
+                SelectionPanelText,
+            ));
+        });
+}
+
+#[allow(clippy::type_complexity)]
+fn update_selection_panel(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    selected_query: Query<(&TowerStats, &TowerState, &Synergy), With<Selected>>,
+    mut text_query: Query<&mut Text, With<SelectionPanelText>>,
+    mut range_query: Query<&mut Transform, With<RangeIndicator>>,
+    panel_query: Query<Entity, Or<(With<SelectionPanel>, With<RangeIndicator>)>>
+) {
+    // the selected tower may have been sold or destroyed by a placement
+    let Ok((tower_stat, tower_state, synergy)) = selected_query.get_single() else {
+        for panel_entity in panel_query.iter() {
+            commands.entity(panel_entity).despawn_recursive();
+        }
+        return;
+    };
+
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = format!(
+            "{}\nLevel: {}\nRange: {:.0}\nDamage: {:.0}\nSpeed: {:.0}\nHealth: {:.0}/{:.0}\nBoosts: x{:.1} damage, x{:.1} fire rate\nKills: {}\nDamage dealt: {:.0}\nUpgrade price: {}\nSell value: {}",
+            tower_stat.kind.label(),
+            tower_stat.level,
+            tower_stat.range,
+            tower_stat.damage,
+            tower_stat.speed,
+            tower_stat.health,
+            tower_stat.max_health,
+            synergy.damage,
+            synergy.fire_rate,
+            tower_state.kills,
+            tower_state.damage_dealt,
+            tower_stat.upgrade_price,
+            sell_value(tower_stat, &config)
+        );
+    }
+
+    for mut range_transform in range_query.iter_mut() {
+        range_transform.translation.x = tower_stat.x;
+        range_transform.translation.y = tower_stat.y;
+    }
+}
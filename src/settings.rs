@@ -0,0 +1,143 @@
+use bevy::{prelude::*, window::{PrimaryWindow, WindowMode}};
+use serde::{Deserialize, Serialize};
+
+use super::{DisplayQuality, EffectsVolume, MusicVolume, ScreenMode, Volume, WindowSize};
+use crate::map::MapSize;
+use crate::mapgen::MapKind;
+use crate::difficulty::Difficulty;
+use crate::storage;
+
+// This plugin holds the player's settings. They are read before the window is created and
+// saved whenever one of them changes in the settings menu, or on the new game screen for the
+// difficulty and the size and kind of generated maps.
+pub struct SettingsPlugin {
+    pub settings: Settings,
+}
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.settings.display_quality)
+        .insert_resource(self.settings.volume)
+        .insert_resource(self.settings.music_volume)
+        .insert_resource(self.settings.effects_volume)
+        .insert_resource(self.settings.window_size)
+        .insert_resource(self.settings.screen_mode)
+        .insert_resource(self.settings.map_size)
+        .insert_resource(self.settings.map_kind)
+        .insert_resource(self.settings.difficulty)
+        .add_system(save_settings)
+        .add_system(apply_window_settings);
+    }
+}
+
+const SETTINGS_FILE: &str = "settings.json";
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub display_quality: DisplayQuality,
+    pub volume: Volume,
+    pub music_volume: MusicVolume,
+    pub effects_volume: EffectsVolume,
+    pub window_size: WindowSize,
+    pub screen_mode: ScreenMode,
+    pub map_size: MapSize,
+    pub map_kind: MapKind,
+    pub difficulty: Difficulty,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            display_quality: DisplayQuality::Medium,
+            volume: Volume(7),
+            music_volume: MusicVolume(Volume::MAX),
+            effects_volume: EffectsVolume(Volume::MAX),
+            window_size: WindowSize::Medium,
+            screen_mode: ScreenMode::Windowed,
+            map_size: MapSize::default(),
+            map_kind: MapKind::default(),
+            difficulty: Difficulty::default(),
+        }
+    }
+}
+
+// Settings saved by a previous launch, or the defaults
+pub fn load_settings() -> Settings {
+    let Some(contents) = storage::read(SETTINGS_FILE) else {
+        return Settings::default();
+    };
+    serde_json::from_str(&contents).unwrap_or_else(|error| {
+        warn!("invalid settings, using the defaults: {}", error);
+        Settings::default()
+    })
+}
+
+pub fn window_mode(screen_mode: ScreenMode) -> WindowMode {
+    match screen_mode {
+        ScreenMode::Windowed => WindowMode::Windowed,
+        ScreenMode::Fullscreen => WindowMode::BorderlessFullscreen,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn save_settings(
+    display_quality: Res<DisplayQuality>,
+    volume: Res<Volume>,
+    music_volume: Res<MusicVolume>,
+    effects_volume: Res<EffectsVolume>,
+    window_size: Res<WindowSize>,
+    screen_mode: Res<ScreenMode>,
+    map_size: Res<MapSize>,
+    map_kind: Res<MapKind>,
+    difficulty: Res<Difficulty>
+) {
+    let changed = display_quality.is_changed() || volume.is_changed()
+        || music_volume.is_changed() || effects_volume.is_changed()
+        || window_size.is_changed() || screen_mode.is_changed() || map_size.is_changed()
+        || map_kind.is_changed() || difficulty.is_changed();
+    // everything looks changed on the first run, when the settings have just been loaded
+    if !changed || display_quality.is_added() {
+        return;
+    }
+
+    let settings = Settings {
+        display_quality: *display_quality,
+        volume: *volume,
+        music_volume: *music_volume,
+        effects_volume: *effects_volume,
+        window_size: *window_size,
+        screen_mode: *screen_mode,
+        map_size: *map_size,
+        map_kind: *map_kind,
+        difficulty: *difficulty,
+    };
+    let result = serde_json::to_string_pretty(&settings)
+        .map_err(|error| error.to_string())
+        .and_then(|contents| storage::write(SETTINGS_FILE, &contents));
+    if let Err(error) = result {
+        warn!("could not save the settings: {}", error);
+    }
+}
+
+fn apply_window_settings(
+    window_size: Res<WindowSize>,
+    screen_mode: Res<ScreenMode>,
+    mut primary_window_query: Query<&mut Window, With<PrimaryWindow>>
+) {
+    if !window_size.is_changed() && !screen_mode.is_changed() {
+        return;
+    }
+    let Ok(mut window) = primary_window_query.get_single_mut() else {
+        return;
+    };
+
+    let (width, height) = window_size.resolution();
+    if window.resolution.width() != width || window.resolution.height() != height {
+        window.resolution.set(width, height);
+    }
+    let mode = window_mode(*screen_mode);
+    if window.mode != mode {
+        window.mode = mode;
+    }
+}
@@ -0,0 +1,336 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use bevy::{prelude::*, transform::TransformSystem};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+
+use super::{GameState, new_game_schedule};
+use crate::map::{CellCoordinate, MapLayout, MapSize, build_map};
+use crate::mapgen::MapKind;
+use crate::difficulty::{Difficulty, MatchDifficulty, set_match_difficulty};
+use crate::season::{Season, ElapsedCounter, SeasonSchedule, start_season_schedule, advance_season};
+use crate::enemy::{WaveTimer, spawn_enemy, move_enemy, enemy_damage_base};
+use crate::bullet::{move_bullets, resolve_bullet_hits};
+use crate::tower::{TowerKind, apply_player_commands, shoot_enemies, sync_size};
+use crate::game::{Coins, start_match, sync_base_size, check_base_destroyed};
+use crate::save::SavedGame;
+use crate::config::GameConfig;
+use crate::heal::{HealCharges, refill_heal_charges};
+use crate::synergy::apply_synergies;
+use crate::boss::{BossStage, spawn_boss, update_boss, check_boss_defeated};
+
+// This plugin runs the gameplay on a fixed timestep so that a match plays out the same way
+// regardless of the frame rate. Every step runs the simulation sets in a fixed order. It only
+// touches gameplay state, never the window, assets or rendering, so it can also run headless.
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        let spawn_interval = app.world.get_resource_or_insert_with(GameConfig::default).enemy.spawn_interval_seconds;
+        app.insert_resource(FixedTime::new_from_secs(SIMULATION_STEP_SECONDS))
+        .insert_resource(SimulationRng(ChaCha12Rng::seed_from_u64(0)))
+        .init_resource::<RngAfterMap>()
+        .insert_resource(WaveTimer {
+            // create the repeating timer
+            timer: Timer::new(Duration::from_secs_f32(spawn_interval), TimerMode::Repeating),
+            force_wave: false,
+            extra_enemies: 0.
+        })
+        .init_resource::<SimulationTick>()
+        .init_resource::<PendingCommands>()
+        .init_resource::<CommandLog>()
+        .init_resource::<ConfigLog>()
+        .init_resource::<MatchSetup>()
+        .init_resource::<MatchSeed>()
+        .init_resource::<MatchStats>()
+        .init_resource::<MapSize>()
+        .init_resource::<MapKind>()
+        .init_resource::<Difficulty>()
+        .init_resource::<MatchDifficulty>()
+        .init_resource::<Coins>()
+        .init_resource::<ElapsedCounter>()
+        .init_resource::<SeasonSchedule>()
+        .init_resource::<HealCharges>()
+        .init_resource::<BossStage>()
+        .add_event::<SimulationEvent>()
+        .add_state::<Season>()
+        .edit_schedule(CoreSchedule::FixedUpdate, |schedule| {
+            schedule.configure_sets((
+                SimulationSet::Input,
+                SimulationSet::Spawn,
+                SimulationSet::Move,
+                SimulationSet::Shoot,
+                SimulationSet::ResolveDamage,
+                SimulationSet::CheckOutcome,
+            ).chain());
+        })
+        // the match is rolled from a single seed, in a fixed order, unless a saved game is being
+        // loaded. The first season starts right away, the previous match may have ended in another one.
+        .add_systems((seed_simulation, start_config_log, set_match_difficulty, build_map, start_season_schedule, apply_state_transition::<Season>, start_match)
+            .chain()
+            .distributive_run_if(rolling_new_match)
+            .in_set(MatchSetupSet)
+            .in_schedule(new_game_schedule()))
+        .add_system(refill_heal_charges.in_schedule(OnEnter(Season::Heal)))
+        .add_system(record_previous_translation
+            .in_schedule(CoreSchedule::FixedUpdate)
+            .before(SimulationSet::Input))
+        // a config reloaded during the match takes effect from the next step
+        .add_system(log_config_changes
+            .in_schedule(CoreSchedule::FixedUpdate)
+            .before(SimulationSet::Input)
+            .run_if(in_state(GameState::Game)))
+        .add_system(apply_player_commands
+            .in_schedule(CoreSchedule::FixedUpdate)
+            .in_set(SimulationSet::Input)
+            .run_if(in_state(GameState::Game)))
+        .add_systems((spawn_enemy, spawn_boss)
+            .chain()
+            .distributive_run_if(in_state(GameState::Game))
+            .in_set(SimulationSet::Spawn)
+            .in_schedule(CoreSchedule::FixedUpdate))
+        // systems of one stage that touch the same data run in a fixed order, the executor would
+        // otherwise pick one and a match would not play out the same way twice
+        .add_systems((move_enemy, move_bullets)
+            .chain()
+            .distributive_run_if(in_state(GameState::Game))
+            .in_set(SimulationSet::Move)
+            .in_schedule(CoreSchedule::FixedUpdate))
+        .add_systems((apply_synergies, shoot_enemies)
+            .chain()
+            .distributive_run_if(in_state(GameState::Game))
+            .in_set(SimulationSet::Shoot)
+            .in_schedule(CoreSchedule::FixedUpdate))
+        .add_systems((resolve_bullet_hits, enemy_damage_base, update_boss)
+            .chain()
+            .distributive_run_if(in_state(GameState::Game))
+            .in_set(SimulationSet::ResolveDamage)
+            .in_schedule(CoreSchedule::FixedUpdate))
+        // losing the base takes precedence over defeating the boss in the same step
+        .add_systems((sync_size, sync_base_size, check_boss_defeated, check_base_destroyed, advance_season)
+            .chain()
+            .distributive_run_if(in_state(GameState::Game))
+            .in_set(SimulationSet::CheckOutcome)
+            .in_schedule(CoreSchedule::FixedUpdate))
+        // season changes and the end of the match take effect before the next step, even when
+        // several steps run in one frame
+        .add_systems((
+                advance_tick.run_if(in_state(GameState::Game)),
+                apply_state_transition::<Season>,
+                apply_state_transition::<GameState>,
+            )
+            .chain()
+            .after(SimulationSet::CheckOutcome)
+            .in_schedule(CoreSchedule::FixedUpdate));
+    }
+}
+
+// Smooths the movement of simulated entities between steps for rendering
+pub struct InterpolationPlugin;
+
+impl Plugin for InterpolationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(restore_simulated_translation.in_base_set(CoreSet::PreUpdate))
+        .add_system(interpolate_translation
+            .in_base_set(CoreSet::PostUpdate)
+            .before(TransformSystem::TransformPropagate));
+    }
+}
+
+pub const SIMULATION_STEP_SECONDS: f32 = 1. / 60.;
+
+// Stages of a simulation step, run in this order in `CoreSchedule::FixedUpdate`
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone, Copy)]
+pub enum SimulationSet {
+    Input,
+    Spawn,
+    Move,
+    Shoot,
+    ResolveDamage,
+    CheckOutcome,
+}
+
+// Random numbers used by the simulation, reseeded for every match. Its state is saved with the
+// match so that a loaded game goes on with the numbers the original would have drawn next. The
+// match can still play out differently, the saved entities come back in another order.
+#[derive(Resource, Deref, DerefMut)]
+pub struct SimulationRng(pub ChaCha12Rng);
+
+// State of the random numbers once the map of the current match was built. Generating a map takes
+// a varying number of draws and a replay loads its map instead, so it goes on from this state.
+// There is none for a match continued from a saved game, it cannot be replayed from its start.
+#[derive(Resource, Default, Debug, Clone)]
+pub struct RngAfterMap(pub Option<ChaCha12Rng>);
+
+// Systems setting up a new match when the menu is left
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone, Copy)]
+pub struct MatchSetupSet;
+
+// Number of steps simulated since the start of the match
+#[derive(Resource, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SimulationTick(pub u64);
+
+// Something the player did that changes the match. Input systems only queue commands, they are
+// applied at the start of the next step so that a match only depends on its seed and commands.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum PlayerCommand {
+    PlaceTower {
+        cell: CellCoordinate,
+        // commands recorded before there were several kinds of towers place turrets
+        #[serde(default)]
+        kind: TowerKind
+    },
+    // world position that was clicked during a heal season
+    Heal { x: f32, y: f32 },
+    SellTower { cell: CellCoordinate },
+    RelocateTower { from: CellCoordinate, to: CellCoordinate },
+}
+
+// A command together with the step it is applied in
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ScheduledCommand {
+    pub tick: u64,
+    #[serde(flatten)]
+    pub command: PlayerCommand,
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct PendingCommands(pub VecDeque<PlayerCommand>);
+
+// Every command applied in the current match, in order
+#[derive(Resource, Default, Debug)]
+pub struct CommandLog(pub Vec<ScheduledCommand>);
+
+// A game config together with the step it was first used in
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduledConfig {
+    pub tick: u64,
+    pub config: GameConfig,
+}
+
+// The config the current match started with, then every config reloaded during it, in order
+#[derive(Resource, Default, Debug)]
+pub struct ConfigLog(pub Vec<ScheduledConfig>);
+
+// How the next match should be set up. Anything left empty is rolled at random, a saved game
+// replaces the whole setup.
+#[derive(Resource, Default, Debug)]
+pub struct MatchSetup {
+    pub seed: Option<u64>,
+    pub layout: Option<MapLayout>,
+    pub difficulty: Option<Difficulty>,
+    // random numbers to go on with once the map is built, for a replayed match
+    pub rng: Option<ChaCha12Rng>,
+    pub saved_game: Option<SavedGame>,
+}
+
+// Seed the current match was rolled from
+#[derive(Resource, Default, Debug, Clone, Copy)]
+pub struct MatchSeed(pub u64);
+
+// Running totals of the current match
+#[derive(Resource, Default, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct MatchStats {
+    pub kills: u32,
+    pub towers_built: u32,
+    pub towers_lost: u32,
+}
+
+// Something that happened during a step, for the presentation to show. The simulation never
+// reads these back.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SimulationEvent {
+    Shot { position: Vec2 },
+    EnemyHit { position: Vec2 },
+    EnemyKilled { position: Vec2 },
+    TowerDestroyed { position: Vec2 },
+    PlacementBlast { position: Vec2 },
+    Healed { position: Vec2 },
+    BossPhaseBroken { position: Vec2 },
+    BossSting { position: Vec2 },
+}
+
+// Simulated translation of an entity at the previous and the latest step. Outside of the
+// simulation the `Transform` holds a blend of the two for smooth rendering.
+#[derive(Component, Default)]
+pub struct Interpolated {
+    previous: Vec3,
+    current: Vec3,
+}
+
+impl Interpolated {
+    pub fn new(translation: Vec3) -> Self {
+        Self {
+            previous: translation,
+            current: translation,
+        }
+    }
+}
+
+pub fn seed_simulation(
+    mut match_setup: ResMut<MatchSetup>,
+    mut match_seed: ResMut<MatchSeed>,
+    mut simulation_rng: ResMut<SimulationRng>,
+    mut simulation_tick: ResMut<SimulationTick>,
+    mut pending_commands: ResMut<PendingCommands>,
+    mut command_log: ResMut<CommandLog>,
+    mut match_stats: ResMut<MatchStats>
+) {
+    let seed = match_setup.seed.take().unwrap_or_else(|| rand::thread_rng().gen());
+    match_seed.0 = seed;
+    simulation_rng.0 = ChaCha12Rng::seed_from_u64(seed);
+    *simulation_tick = SimulationTick::default();
+    *match_stats = MatchStats::default();
+    pending_commands.0.clear();
+    command_log.0.clear();
+}
+
+fn start_config_log(game_config: Res<GameConfig>, mut config_log: ResMut<ConfigLog>) {
+    config_log.0 = vec![ScheduledConfig { tick: 0, config: game_config.clone() }];
+}
+
+fn log_config_changes(
+    game_config: Res<GameConfig>,
+    simulation_tick: Res<SimulationTick>,
+    mut config_log: ResMut<ConfigLog>
+) {
+    if !game_config.is_changed() || config_log.0.last().map(|scheduled| &scheduled.config) == Some(&*game_config) {
+        return;
+    }
+    config_log.0.push(ScheduledConfig { tick: simulation_tick.0, config: game_config.clone() });
+}
+
+fn rolling_new_match(match_setup: Res<MatchSetup>) -> bool {
+    match_setup.saved_game.is_none()
+}
+
+fn advance_tick(mut simulation_tick: ResMut<SimulationTick>) {
+    simulation_tick.0 += 1;
+}
+
+fn record_previous_translation(mut query: Query<(&mut Interpolated, &Transform)>) {
+    for (mut interpolated, transform) in query.iter_mut() {
+        interpolated.previous = transform.translation;
+    }
+}
+
+// Put the simulated translation back before any gameplay system reads the transform
+fn restore_simulated_translation(mut query: Query<(&Interpolated, &mut Transform)>) {
+    for (interpolated, mut transform) in query.iter_mut() {
+        transform.translation = interpolated.current;
+    }
+}
+
+fn interpolate_translation(
+    fixed_time: Res<FixedTime>,
+    mut query: Query<(&mut Interpolated, &mut Transform)>
+) {
+    let alpha = (fixed_time.accumulated().as_secs_f32() / fixed_time.period.as_secs_f32()).min(1.);
+    for (mut interpolated, mut transform) in query.iter_mut() {
+        interpolated.current = transform.translation;
+        transform.translation = interpolated.previous.lerp(interpolated.current, alpha);
+    }
+}
@@ -0,0 +1,390 @@
+use std::{f32::consts::TAU, sync::Arc, time::Duration};
+
+use bevy::{
+    prelude::*,
+    audio::{AddAudioSource, Source},
+    reflect::TypeUuid,
+};
+
+use super::{EffectsVolume, GameState, MusicVolume, Volume};
+use crate::season::Season;
+use crate::simulation::SimulationEvent;
+
+// This plugin plays a sound for what happens in the simulation, a stinger when the match is
+// won or lost, and a music loop for every season that crossfades when the season changes.
+// The sounds are synthesized when the game starts, there are no audio files to load.
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_audio_source::<Sound>()
+        .init_resource::<Music>()
+        .add_startup_system(create_sounds)
+        .add_system(play_simulation_sounds)
+        .add_system(play_stinger.in_schedule(OnEnter(GameState::GameWon)))
+        .add_system(play_stinger.in_schedule(OnEnter(GameState::GameLost)))
+        .add_system(switch_music)
+        .add_system(fade_music.after(switch_music));
+    }
+}
+
+const SAMPLE_RATE: u32 = 22_050;
+// Level of each channel at full volume, scaled by the volume of every sound and by that of the channel
+const MUSIC_LEVEL: f32 = 0.5;
+const EFFECTS_LEVEL: f32 = 0.8;
+const CROSSFADE_SECONDS: f32 = 1.5;
+// Many towers firing at once would otherwise be a wall of noise
+const MIN_SHOT_INTERVAL_SECONDS: f32 = 0.06;
+
+// Mono samples of a synthesized sound
+#[derive(TypeUuid)]
+#[uuid = "5f0e4a52-8c1e-4d3b-9a8e-2b7c6d1f3e90"]
+pub struct Sound {
+    samples: Arc<[f32]>,
+}
+
+impl Decodable for Sound {
+    type DecoderItem = f32;
+    type Decoder = SoundDecoder;
+
+    fn decoder(&self) -> Self::Decoder {
+        SoundDecoder { samples: self.samples.clone(), position: 0 }
+    }
+}
+
+pub struct SoundDecoder {
+    samples: Arc<[f32]>,
+    position: usize,
+}
+
+impl Iterator for SoundDecoder {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.samples.get(self.position).copied();
+        self.position += 1;
+        sample
+    }
+}
+
+impl Source for SoundDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.samples.len().saturating_sub(self.position))
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(Duration::from_secs_f32(self.samples.len() as f32 / SAMPLE_RATE as f32))
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Waveform {
+    Sine,
+    Square,
+    Noise,
+}
+
+// A note gliding from `from` to `to` hertz. It fades in over `attack` and out over `release`
+// seconds, and `decay` makes it die away over its duration (0 holds it).
+#[derive(Clone, Copy)]
+struct Note {
+    waveform: Waveform,
+    from: f32,
+    to: f32,
+    start: f32,
+    duration: f32,
+    level: f32,
+    attack: f32,
+    release: f32,
+    decay: f32,
+}
+
+impl Note {
+    fn new(waveform: Waveform, from: f32, to: f32, start: f32, duration: f32, level: f32) -> Self {
+        Note { waveform, from, to, start, duration, level, attack: 0.005, release: 0.01, decay: 1. }
+    }
+
+    fn envelope(self, attack: f32, release: f32, decay: f32) -> Self {
+        Note { attack, release, decay, ..self }
+    }
+}
+
+// Frequency of a MIDI note number
+fn pitch(note: u8) -> f32 {
+    440. * 2f32.powf((note as f32 - 69.) / 12.)
+}
+
+fn render(notes: &[Note], seconds: f32) -> Sound {
+    let sample_rate = SAMPLE_RATE as f32;
+    let mut samples = vec![0.; (seconds * sample_rate) as usize];
+    // xorshift, the noise only has to sound random
+    let mut noise: u32 = 0x9e37_79b9;
+
+    for note in notes {
+        let first = (note.start * sample_rate) as usize;
+        let count = (note.duration * sample_rate) as usize;
+        let mut phase: f32 = 0.;
+        for (index, sample) in samples.iter_mut().skip(first).take(count).enumerate() {
+            let time = index as f32 / sample_rate;
+            let progress = time / note.duration;
+            phase = (phase + (note.from + (note.to - note.from) * progress) / sample_rate).fract();
+            let wave = match note.waveform {
+                Waveform::Sine => (phase * TAU).sin(),
+                Waveform::Square => if phase < 0.5 { 1. } else { -1. },
+                Waveform::Noise => {
+                    noise ^= noise << 13;
+                    noise ^= noise >> 17;
+                    noise ^= noise << 5;
+                    noise as f32 / u32::MAX as f32 * 2. - 1.
+                }
+            };
+            let envelope = (time / note.attack).min(1.)
+                * ((note.duration - time) / note.release).min(1.)
+                * (1. - progress).powf(note.decay);
+            *sample += wave * envelope * note.level;
+        }
+    }
+
+    Sound { samples: samples.into() }
+}
+
+// A loop of sustained chords over a bass line, `pulse` plays the bass as short repeated notes
+fn music_loop(chords: &[[u8; 3]], bass: &[u8], chord_seconds: f32, pulse: bool) -> Sound {
+    let mut notes = Vec::new();
+    for (index, (chord, &bass_note)) in chords.iter().zip(bass).enumerate() {
+        let start = index as f32 * chord_seconds;
+        for &chord_note in chord {
+            notes.push(Note::new(Waveform::Sine, pitch(chord_note), pitch(chord_note), start, chord_seconds, 0.12)
+                .envelope(0.4, 0.6, 0.));
+        }
+        if pulse {
+            let beat = chord_seconds / 4.;
+            for step in 0..4 {
+                notes.push(Note::new(Waveform::Square, pitch(bass_note), pitch(bass_note),
+                    start + step as f32 * beat, beat * 0.8, 0.04).envelope(0.01, 0.05, 1.5));
+            }
+        } else {
+            notes.push(Note::new(Waveform::Sine, pitch(bass_note), pitch(bass_note), start, chord_seconds, 0.12)
+                .envelope(0.2, 0.4, 0.));
+        }
+    }
+    render(&notes, chords.len() as f32 * chord_seconds)
+}
+
+#[derive(Resource)]
+struct Sounds {
+    shot: Handle<Sound>,
+    hit: Handle<Sound>,
+    enemy_killed: Handle<Sound>,
+    tower_destroyed: Handle<Sound>,
+    placement_blast: Handle<Sound>,
+    heal: Handle<Sound>,
+    boss_phase_broken: Handle<Sound>,
+    boss_sting: Handle<Sound>,
+    win: Handle<Sound>,
+    loss: Handle<Sound>,
+    // one loop per season, in the order of `music`
+    music: [Handle<Sound>; 4],
+}
+
+impl Sounds {
+    fn music(&self, season: Season) -> Handle<Sound> {
+        let index = match season {
+            Season::Build => 0,
+            Season::Heal => 1,
+            Season::Upgrade => 2,
+            Season::Neutralize => 3,
+        };
+        self.music[index].clone()
+    }
+}
+
+fn create_sounds(mut commands: Commands, mut sound_assets: ResMut<Assets<Sound>>) {
+    use Waveform::*;
+
+    let mut add = |notes: &[Note], seconds: f32| sound_assets.add(render(notes, seconds));
+    let shot = add(&[Note::new(Square, 1200., 800., 0., 0.05, 0.12)], 0.05);
+    let hit = add(&[Note::new(Noise, 0., 0., 0., 0.04, 0.2).envelope(0.001, 0.01, 2.)], 0.04);
+    let enemy_killed = add(&[
+        Note::new(Sine, 500., 120., 0., 0.15, 0.4),
+        Note::new(Noise, 0., 0., 0., 0.05, 0.1),
+    ], 0.15);
+    let tower_destroyed = add(&[
+        Note::new(Noise, 0., 0., 0., 0.45, 0.35).envelope(0.002, 0.05, 2.),
+        Note::new(Sine, 180., 45., 0., 0.45, 0.45),
+    ], 0.45);
+    let placement_blast = add(&[
+        Note::new(Noise, 0., 0., 0., 0.7, 0.4).envelope(0.002, 0.1, 3.),
+        Note::new(Sine, 90., 35., 0., 0.7, 0.5),
+    ], 0.7);
+    let heal = add(&[
+        Note::new(Sine, pitch(81), pitch(81), 0., 0.25, 0.25).envelope(0.005, 0.05, 2.),
+        Note::new(Sine, pitch(88), pitch(88), 0.08, 0.27, 0.25).envelope(0.005, 0.05, 2.),
+        Note::new(Sine, pitch(93), pitch(93), 0.16, 0.29, 0.25).envelope(0.005, 0.05, 2.),
+    ], 0.45);
+    let boss_phase_broken = add(&[
+        Note::new(Square, 220., 110., 0., 0.6, 0.15).envelope(0.005, 0.1, 1.),
+        Note::new(Noise, 0., 0., 0., 0.4, 0.3).envelope(0.002, 0.05, 2.),
+    ], 0.6);
+    // a low buzz
+    let boss_sting = add(&[Note::new(Square, 70., 55., 0., 0.35, 0.2).envelope(0.01, 0.05, 1.)], 0.35);
+    let win = add(&[72, 76, 79, 84].iter().enumerate()
+        .map(|(index, &note)| Note::new(Square, pitch(note), pitch(note), index as f32 * 0.15, 0.6, 0.1)
+            .envelope(0.01, 0.1, 1.))
+        .collect::<Vec<_>>(), 1.05);
+    let loss = add(&[67, 63, 60, 48].iter().enumerate()
+        .map(|(index, &note)| Note::new(Square, pitch(note), pitch(note), index as f32 * 0.25,
+            if index == 3 { 1. } else { 0.5 }, 0.1).envelope(0.01, 0.1, 1.))
+        .collect::<Vec<_>>(), 1.75);
+
+    let music = [
+        // build: calm, in C major
+        music_loop(&[[60, 64, 67], [59, 62, 67], [57, 60, 64], [57, 60, 65]], &[48, 43, 45, 41], 2., false),
+        // heal: slow and soft, in F major
+        music_loop(&[[65, 69, 72], [64, 67, 72], [62, 65, 69], [64, 67, 70]], &[53, 48, 50, 48], 2.5, false),
+        // upgrade: brighter and driving, in G major
+        music_loop(&[[67, 71, 74], [66, 69, 74], [64, 67, 71], [62, 66, 69]], &[43, 50, 40, 38], 1.5, true),
+        // neutralize: tense, in A minor
+        music_loop(&[[57, 60, 64], [57, 60, 65], [56, 59, 64], [57, 60, 64]], &[45, 41, 40, 45], 1.5, true),
+    ].map(|sound| sound_assets.add(sound));
+
+    commands.insert_resource(Sounds {
+        shot, hit, enemy_killed, tower_destroyed, placement_blast, heal, boss_phase_broken, boss_sting, win, loss, music
+    });
+}
+
+fn play_effect(audio: &Audio<Sound>, sound: Handle<Sound>, volume: Volume, effects_volume: EffectsVolume) {
+    // the sink is dropped right away, which lets the sound play out on its own
+    audio.play_with_settings(sound,
+        PlaybackSettings::ONCE.with_volume(EFFECTS_LEVEL * volume.gain() * effects_volume.gain()));
+}
+
+fn play_simulation_sounds(
+    mut simulation_events: EventReader<SimulationEvent>,
+    sounds: Res<Sounds>,
+    audio: Res<Audio<Sound>>,
+    volume: Res<Volume>,
+    effects_volume: Res<EffectsVolume>,
+    time: Res<Time>,
+    mut last_shot: Local<f32>
+) {
+    // a burst of identical events, like a blast killing a whole wave, plays its sound once
+    let mut played: Vec<&Handle<Sound>> = Vec::new();
+    for event in simulation_events.iter() {
+        let sound = match event {
+            SimulationEvent::Shot { .. } => {
+                let now = time.raw_elapsed_seconds();
+                if now - *last_shot < MIN_SHOT_INTERVAL_SECONDS {
+                    continue;
+                }
+                *last_shot = now;
+                &sounds.shot
+            }
+            SimulationEvent::EnemyHit { .. } => &sounds.hit,
+            SimulationEvent::EnemyKilled { .. } => &sounds.enemy_killed,
+            SimulationEvent::TowerDestroyed { .. } => &sounds.tower_destroyed,
+            SimulationEvent::PlacementBlast { .. } => &sounds.placement_blast,
+            SimulationEvent::Healed { .. } => &sounds.heal,
+            SimulationEvent::BossPhaseBroken { .. } => &sounds.boss_phase_broken,
+            SimulationEvent::BossSting { .. } => &sounds.boss_sting,
+        };
+        if played.contains(&sound) {
+            continue;
+        }
+        play_effect(&audio, sound.clone(), *volume, *effects_volume);
+        played.push(sound);
+    }
+}
+
+fn play_stinger(
+    game_state: Res<State<GameState>>,
+    sounds: Res<Sounds>,
+    audio: Res<Audio<Sound>>,
+    volume: Res<Volume>,
+    effects_volume: Res<EffectsVolume>
+) {
+    let sound = match game_state.0 {
+        GameState::GameWon => &sounds.win,
+        _ => &sounds.loss,
+    };
+    play_effect(&audio, sound.clone(), *volume, *effects_volume);
+}
+
+// A playing music loop and how loud it is, relative to the music channel. Loops fade towards
+// their target and are stopped once faded out.
+struct MusicTrack {
+    sink: Handle<AudioSink>,
+    level: f32,
+    target: f32,
+}
+
+#[derive(Resource, Default)]
+struct Music {
+    // season whose loop is playing, none outside of a match
+    season: Option<Season>,
+    tracks: Vec<MusicTrack>,
+}
+
+fn switch_music(
+    game_state: Res<State<GameState>>,
+    season: Res<State<Season>>,
+    sounds: Res<Sounds>,
+    audio: Res<Audio<Sound>>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    mut music: ResMut<Music>
+) {
+    let wanted = matches!(game_state.0, GameState::Game | GameState::Pause).then_some(season.0);
+    if music.season == wanted {
+        return;
+    }
+    music.season = wanted;
+
+    for track in music.tracks.iter_mut() {
+        track.target = 0.;
+    }
+    if let Some(season) = wanted {
+        let sink = audio.play_with_settings(sounds.music(season), PlaybackSettings::LOOP.with_volume(0.));
+        // a strong handle keeps the loop alive until it is faded out
+        music.tracks.push(MusicTrack { sink: audio_sinks.get_handle(sink), level: 0., target: 1. });
+    }
+}
+
+// Fades in real time, so that the game speed does not rush them. Running every frame also
+// picks up changes of the volume settings.
+fn fade_music(
+    time: Res<Time>,
+    volume: Res<Volume>,
+    music_volume: Res<MusicVolume>,
+    audio_sinks: Res<Assets<AudioSink>>,
+    mut music: ResMut<Music>
+) {
+    let step = time.raw_delta_seconds() / CROSSFADE_SECONDS;
+    for track in music.tracks.iter_mut() {
+        track.level = if track.target > track.level {
+            (track.level + step).min(track.target)
+        } else {
+            (track.level - step).max(track.target)
+        };
+        if let Some(sink) = audio_sinks.get(&track.sink) {
+            sink.set_volume(track.level * MUSIC_LEVEL * volume.gain() * music_volume.gain());
+        }
+    }
+
+    music.tracks.retain(|track| {
+        let faded_out = track.target == 0. && track.level == 0.;
+        if faded_out {
+            if let Some(sink) = audio_sinks.get(&track.sink) {
+                sink.stop();
+            }
+        }
+        !faded_out
+    });
+}
@@ -0,0 +1,145 @@
+use bevy::prelude::*;
+
+use super::{GameState, despawn_with_component, new_game_schedule, TEXT_COLOR};
+use crate::controls::Action;
+
+// This plugin lets the player fast-forward the game. The chosen speed is applied to the
+// relative speed of `Time`, whose scaled delta fills the accumulator of the fixed-step
+// simulation: more steps run each frame, each as long as at normal speed, so a match plays out
+// the same at any speed.
+pub struct SpeedPlugin;
+
+impl Plugin for SpeedPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(GameSpeed(1.))
+        .add_system(apply_game_speed)
+        .add_system(reset_game_speed.in_schedule(new_game_schedule()))
+        .add_system(speed_buttons_setup.in_schedule(new_game_schedule()))
+        .add_system(cycle_speed_with_action.run_if(in_state(GameState::Game)))
+        .add_system(speed_button_action.run_if(in_state(GameState::Game)))
+        .add_system(speed_button_colors)
+        .add_system(despawn_with_component::<SpeedControls>.in_schedule(OnEnter(GameState::Menu)));
+    }
+}
+
+pub const GAME_SPEEDS: [f32; 3] = [1., 2., 4.];
+
+const NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
+const HOVERED_BUTTON: Color = Color::rgb(0.25, 0.25, 0.25);
+const SELECTED_BUTTON: Color = Color::rgb(0.35, 0.75, 0.35);
+
+// Multiplier applied to the simulation while a game is running
+#[derive(Resource, Debug, PartialEq, Clone, Copy)]
+pub struct GameSpeed(pub f32);
+
+#[derive(Component)]
+struct SpeedControls;
+
+// HUD button that sets the game speed to the given multiplier
+#[derive(Component)]
+struct SpeedButton(f32);
+
+fn apply_game_speed(
+    mut time: ResMut<Time>,
+    game_speed: Res<GameSpeed>,
+    game_state: Res<State<GameState>>
+) {
+    // menus and the pause overlay always run in real time
+    let speed = if game_state.0 == GameState::Game { game_speed.0 } else { 1. };
+    if time.relative_speed() != speed {
+        time.set_relative_speed(speed);
+    }
+}
+
+fn reset_game_speed(mut game_speed: ResMut<GameSpeed>) {
+    game_speed.0 = 1.;
+}
+
+// The speed up action goes to the next speed and wraps around
+fn cycle_speed_with_action(
+    actions: Res<Input<Action>>,
+    mut game_speed: ResMut<GameSpeed>
+) {
+    if actions.just_pressed(Action::SpeedUp) {
+        let current = GAME_SPEEDS.iter().position(|&speed| speed == game_speed.0).unwrap_or(0);
+        game_speed.0 = GAME_SPEEDS[(current + 1) % GAME_SPEEDS.len()];
+    }
+}
+
+fn speed_buttons_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let button_text_style = TextStyle {
+        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+        font_size: 24.0,
+        color: TEXT_COLOR,
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        right: Val::Px(10.0),
+                        bottom: Val::Px(10.0),
+                        ..default()
+                    },
+                    ..default()
+                },
+                ..default()
+            },
+            SpeedControls,
+        ))
+        .with_children(|parent| {
+            for speed in GAME_SPEEDS {
+                parent
+                    .spawn((
+                        ButtonBundle {
+                            style: Style {
+                                size: Size::new(Val::Px(50.0), Val::Px(35.0)),
+                                margin: UiRect::all(Val::Px(4.0)),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            background_color: NORMAL_BUTTON.into(),
+                            ..default()
+                        },
+                        SpeedButton(speed),
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(
+                            format!("{}x", speed),
+                            button_text_style.clone(),
+                        ));
+                    });
+            }
+        });
+}
+
+#[allow(clippy::type_complexity)]
+fn speed_button_action(
+    interaction_query: Query<(&Interaction, &SpeedButton), (Changed<Interaction>, With<Button>)>,
+    mut game_speed: ResMut<GameSpeed>
+) {
+    for (interaction, speed_button) in interaction_query.iter() {
+        if *interaction == Interaction::Clicked {
+            game_speed.0 = speed_button.0;
+        }
+    }
+}
+
+// Highlight the button of the current speed, whether it was picked with the mouse or the speed up action
+fn speed_button_colors(
+    game_speed: Res<GameSpeed>,
+    mut button_query: Query<(&Interaction, &SpeedButton, &mut BackgroundColor), With<Button>>
+) {
+    for (interaction, speed_button, mut color) in button_query.iter_mut() {
+        *color = if speed_button.0 == game_speed.0 {
+            SELECTED_BUTTON.into()
+        } else if *interaction == Interaction::Hovered {
+            HOVERED_BUTTON.into()
+        } else {
+            NORMAL_BUTTON.into()
+        };
+    }
+}
@@ -0,0 +1,65 @@
+// Small text files kept between launches: files in the `saves` directory on native builds and
+// entries of the browser's local storage on the wasm build
+
+#[cfg(not(target_arch = "wasm32"))]
+const SAVE_DIRECTORY: &str = "saves";
+
+#[cfg(target_arch = "wasm32")]
+const KEY_PREFIX: &str = "rustytowers.";
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read(name: &str) -> Option<String> {
+    std::fs::read_to_string(std::path::Path::new(SAVE_DIRECTORY).join(name)).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write(name: &str, contents: &str) -> Result<(), String> {
+    std::fs::create_dir_all(SAVE_DIRECTORY).map_err(|error| error.to_string())?;
+    std::fs::write(std::path::Path::new(SAVE_DIRECTORY).join(name), contents).map_err(|error| error.to_string())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn remove(name: &str) -> Result<(), String> {
+    match std::fs::remove_file(std::path::Path::new(SAVE_DIRECTORY).join(name)) {
+        Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(error.to_string()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn read(name: &str) -> Option<String> {
+    local_storage()?.get_item(&format!("{}{}", KEY_PREFIX, name)).ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn write(name: &str, contents: &str) -> Result<(), String> {
+    let storage = local_storage().ok_or("local storage is not available")?;
+    storage.set_item(&format!("{}{}", KEY_PREFIX, name), contents)
+        .map_err(|error| format!("{:?}", error))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn remove(name: &str) -> Result<(), String> {
+    let storage = local_storage().ok_or("local storage is not available")?;
+    storage.remove_item(&format!("{}{}", KEY_PREFIX, name))
+        .map_err(|error| format!("{:?}", error))
+}
+
+// Seconds since the Unix epoch. `SystemTime` is not available in the browser.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn unix_time_seconds() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn unix_time_seconds() -> u64 {
+    (js_sys::Date::now() / 1000.) as u64
+}
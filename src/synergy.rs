@@ -0,0 +1,172 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{GameState, despawn_with_component};
+use crate::config::GameConfig;
+use crate::game::euclidean_distance;
+use crate::tower::{TowerKind, TowerStats};
+
+// Support towers boost the towers around them, following the synergy rules of the game config.
+// The boosts are simulated by the `SimulationPlugin`, this plugin draws a line from every
+// support tower to each tower it boosts.
+pub struct SynergyPlugin;
+
+impl Plugin for SynergyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(show_synergy_links)
+        .add_system(despawn_with_component::<SynergyLink>.in_schedule(OnEnter(GameState::Menu)));
+    }
+}
+
+const LINK_WIDTH: f32 = 3.;
+const DAMAGE_LINK_COLOR: Color = Color::rgba(1., 0.45, 0.85, 0.6);
+const FIRE_RATE_LINK_COLOR: Color = Color::rgba(0.5, 1., 0.5, 0.6);
+
+// What a support tower does for the towers around it, the bonus is added for each support in range
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Buff {
+    Damage(f32),
+    FireRate(f32),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SynergyRule {
+    // kind of the support tower
+    pub source: TowerKind,
+    pub radius: f32,
+    pub buff: Buff,
+    // supports beyond this many around one tower add nothing more
+    pub max_stacks: u32,
+}
+
+impl SynergyRule {
+    // Whether `source` boosts `target` under this rule, towers never boost themselves
+    fn links(&self, source: (Entity, &TowerStats), target: (Entity, &TowerStats)) -> bool {
+        let ((source_entity, source_stat), (target_entity, target_stat)) = (source, target);
+        source_entity != target_entity
+            && source_stat.kind == self.source
+            && euclidean_distance(source_stat.x, source_stat.y, target_stat.x, target_stat.y) < self.radius
+    }
+}
+
+// Multipliers a tower gets from the supports around it
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct Synergy {
+    pub damage: f32,
+    pub fire_rate: f32,
+}
+
+impl Default for Synergy {
+    fn default() -> Self {
+        Synergy { damage: 1., fire_rate: 1. }
+    }
+}
+
+#[derive(Component)]
+struct SynergyLink;
+
+pub fn apply_synergies(
+    config: Res<GameConfig>,
+    mut tower_query: Query<(Entity, &TowerStats, &mut Synergy)>,
+    source_query: Query<(Entity, &TowerStats)>
+) {
+    for (tower_entity, tower_stat, mut synergy) in tower_query.iter_mut() {
+        let mut boosted = Synergy::default();
+        for rule in config.synergies.iter() {
+            let sources = source_query.iter()
+                .filter(|&source| rule.links(source, (tower_entity, tower_stat)))
+                .count()
+                .min(rule.max_stacks as usize);
+            match rule.buff {
+                Buff::Damage(bonus) => boosted.damage += bonus * sources as f32,
+                Buff::FireRate(bonus) => boosted.fire_rate += bonus * sources as f32,
+            }
+        }
+        if *synergy != boosted {
+            *synergy = boosted;
+        }
+    }
+}
+
+// The links are rebuilt whenever a tower is built, moved, damaged or removed
+fn show_synergy_links(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    tower_query: Query<(Entity, &TowerStats)>,
+    changed_query: Query<(), Changed<TowerStats>>,
+    mut removed_towers: RemovedComponents<TowerStats>,
+    link_query: Query<Entity, With<SynergyLink>>
+) {
+    let removed = removed_towers.iter().count() > 0;
+    if changed_query.is_empty() && !removed && !config.is_changed() {
+        return;
+    }
+
+    for link_entity in link_query.iter() {
+        commands.entity(link_entity).despawn();
+    }
+
+    for rule in config.synergies.iter() {
+        let color = match rule.buff {
+            Buff::Damage(_) => DAMAGE_LINK_COLOR,
+            Buff::FireRate(_) => FIRE_RATE_LINK_COLOR,
+        };
+        for source in tower_query.iter() {
+            for target in tower_query.iter().filter(|&target| rule.links(source, target)) {
+                let from = Vec2::new(source.1.x, source.1.y);
+                let to = Vec2::new(target.1.x, target.1.y);
+                let direction = to - from;
+                commands.spawn((SynergyLink,
+                    SpriteBundle {
+                        sprite: Sprite {
+                            color,
+                            custom_size: Some(Vec2::new(direction.length(), LINK_WIDTH)),
+                            ..default()
+                        },
+                        transform: Transform::from_translation(((from + to) / 2.).extend(2.8))
+                            .with_rotation(Quat::from_rotation_z(direction.y.atan2(direction.x))),
+                        ..default()
+                    }));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn check_supports_stack_up_to_their_limit() {
+        use bevy::prelude::*;
+        use super::{Buff, Synergy, SynergyRule, apply_synergies};
+        use crate::config::GameConfig;
+        use crate::difficulty::Difficulty;
+        use crate::tower::{TowerBundle, TowerKind};
+
+        let config = GameConfig {
+            synergies: vec![
+                SynergyRule { source: TowerKind::Amplifier, radius: 90., buff: Buff::Damage(0.5), max_stacks: 2 },
+                SynergyRule { source: TowerKind::Supply, radius: 90., buff: Buff::FireRate(0.25), max_stacks: 2 },
+            ],
+            ..GameConfig::default()
+        };
+        let mut world = World::new();
+        let turret = world.spawn(TowerBundle::new(0., 0., TowerKind::Turret, Difficulty::Normal, &config)).id();
+        // three amplifiers in range of the turret, one beyond it, and a single supply
+        for (x, y, kind) in [
+            (45., 0., TowerKind::Amplifier),
+            (-45., 0., TowerKind::Amplifier),
+            (0., 45., TowerKind::Amplifier),
+            (200., 0., TowerKind::Amplifier),
+            (0., -45., TowerKind::Supply),
+        ] {
+            world.spawn(TowerBundle::new(x, y, kind, Difficulty::Normal, &config));
+        }
+        world.insert_resource(config);
+
+        let mut schedule = Schedule::new();
+        schedule.add_system(apply_synergies);
+        schedule.run(&mut world);
+
+        assert_eq!(*world.get::<Synergy>(turret).unwrap(), Synergy { damage: 2., fire_rate: 1.25 });
+    }
+}
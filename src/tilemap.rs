@@ -0,0 +1,339 @@
+use bevy::{
+    prelude::*,
+    asset::LoadState,
+    render::{mesh::Indices, render_resource::PrimitiveTopology},
+    sprite::MaterialMesh2dBundle,
+    utils::HashMap,
+};
+
+use super::{GameState, despawn_with_component};
+use crate::map::{CellCoordinate, Map, CELL_SIZE};
+
+// This plugin draws the map with the rpg tileset: dirt ground, walls as raised grass whose edges
+// are matched to their neighbours, and props scattered as decoration. The tiles are packed into
+// a texture atlas once loaded, and the map is drawn as a few chunk meshes instead of one entity
+// per tile.
+pub struct TilemapPlugin;
+
+impl Plugin for TilemapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(load_tile_textures)
+        .add_system(build_tile_atlas)
+        .add_system(spawn_tilemap)
+        .add_system(despawn_with_component::<TileChunk>.in_schedule(OnEnter(GameState::Menu)));
+    }
+}
+
+// Size of a tile of the tileset, in pixels
+const TILE_SIZE: f32 = 16.;
+// Tileset pixels are drawn this large so that a tile covers a cell
+const PIXEL_SCALE: f32 = CELL_SIZE / TILE_SIZE;
+// Cells on each side of a chunk mesh
+const CHUNK_CELLS: i32 = 16;
+const GROUND_Z: f32 = -2.;
+const PROPS_Z: f32 = -1.;
+
+// Ground tiles, relative to `textures/rpg`. The dirt tiles have a grass fringe on their
+// sides, quarters of them are combined so that a single wall cell is fringed on every side.
+const DIRT: [&str; 2] = ["tiles/generic-rpg-tile09.png", "tiles/generic-rpg-tile71.png"];
+const DIRT_TOP: &str = "tiles/generic-rpg-tile06.png";
+const DIRT_BOTTOM: &str = "tiles/generic-rpg-tile41.png";
+const DIRT_LEFT: &str = "tiles/generic-rpg-tile12.png";
+const DIRT_RIGHT: &str = "tiles/generic-rpg-tile48.png";
+const DIRT_TOP_LEFT: &str = "tiles/generic-rpg-tile59.png";
+const DIRT_TOP_RIGHT: &str = "tiles/generic-rpg-tile60.png";
+const DIRT_BOTTOM_LEFT: &str = "tiles/generic-rpg-tile57.png";
+const DIRT_BOTTOM_RIGHT: &str = "tiles/generic-rpg-tile46.png";
+const GRASS: [&str; 5] = [
+    "tiles/generic-rpg-tile01.png",
+    "tiles/generic-rpg-tile03.png",
+    "tiles/generic-rpg-tile05.png",
+    "tiles/generic-rpg-tile18.png",
+    "tiles/generic-rpg-tile20.png",
+];
+
+// Props, relative to `textures/rpg`
+const TREES: [&str; 2] = ["props/generic-rpg-tree01.png", "props/generic-rpg-tree02.png"];
+const FENCE: &str = "props/generic-rpg-fence08.png";
+const ROCKS: [&str; 5] = [
+    "props/generic-rpg-rock02.png",
+    "props/generic-rpg-rock03.png",
+    "props/generic-rpg-rock04.png",
+    "props/generic-rpg-rock05.png",
+    "props/generic-rpg-rock06.png",
+];
+const GROUND_PROPS: [&str; 6] = [
+    "props/generic-rpg-grass01.png",
+    "props/generic-rpg-grass02.png",
+    "props/generic-rpg-flower01.png",
+    "props/generic-rpg-flower02.png",
+    "props/generic-rpg-flower03.png",
+    "props/generic-rpg-rock01.png",
+];
+// Chances out of 100 for a cell to get a prop
+const ROCK_CHANCE: u32 = 20;
+const GROUND_PROP_CHANCE: u32 = 5;
+
+#[derive(Resource)]
+struct TileTextures(HashMap<&'static str, Handle<Image>>);
+
+// Where every tile and prop is in the atlas
+#[derive(Resource)]
+struct TileAtlas {
+    material: Handle<ColorMaterial>,
+    size: Vec2,
+    rects: HashMap<&'static str, Rect>,
+}
+
+impl TileAtlas {
+    fn rect(&self, name: &str) -> Rect {
+        self.rects[name]
+    }
+}
+
+#[derive(Component)]
+struct TileChunk;
+
+fn load_tile_textures(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let names = [DIRT_TOP, DIRT_BOTTOM, DIRT_LEFT, DIRT_RIGHT,
+        DIRT_TOP_LEFT, DIRT_TOP_RIGHT, DIRT_BOTTOM_LEFT, DIRT_BOTTOM_RIGHT, FENCE].into_iter()
+        .chain(DIRT)
+        .chain(GRASS)
+        .chain(TREES)
+        .chain(ROCKS)
+        .chain(GROUND_PROPS);
+    let textures = names
+        .map(|name| (name, asset_server.load(format!("textures/rpg/{}", name))))
+        .collect();
+    commands.insert_resource(TileTextures(textures));
+}
+
+// Pack the tiles into a single texture once they have all been loaded
+fn build_tile_atlas(
+    mut commands: Commands,
+    tile_textures: Option<Res<TileTextures>>,
+    asset_server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<ColorMaterial>>
+) {
+    let Some(tile_textures) = tile_textures else {
+        return;
+    };
+    match asset_server.get_group_load_state(tile_textures.0.values().map(|handle| handle.id())) {
+        LoadState::Loaded => (),
+        LoadState::Failed => {
+            warn!("could not load the tileset, the map will not be drawn");
+            commands.remove_resource::<TileTextures>();
+            return;
+        }
+        _ => return,
+    }
+
+    let mut builder = TextureAtlasBuilder::default();
+    for handle in tile_textures.0.values() {
+        if let Some(image) = images.get(handle) {
+            builder.add_texture(handle.clone(), image);
+        }
+    }
+    let atlas = match builder.finish(&mut images) {
+        Ok(atlas) => atlas,
+        Err(error) => {
+            warn!("could not build the tile atlas: {:?}", error);
+            commands.remove_resource::<TileTextures>();
+            return;
+        }
+    };
+
+    let rects = tile_textures.0.iter()
+        .filter_map(|(&name, handle)| atlas.get_texture_index(handle).map(|index| (name, atlas.textures[index])))
+        .collect();
+    commands.insert_resource(TileAtlas {
+        material: materials.add(ColorMaterial::from(atlas.texture.clone())),
+        size: atlas.size,
+        rects,
+    });
+    // the tiles are now copied in the atlas
+    commands.remove_resource::<TileTextures>();
+}
+
+// Quads of a chunk, drawn with a single mesh
+#[derive(Default)]
+struct ChunkMesh {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    indices: Vec<u32>,
+}
+
+impl ChunkMesh {
+    // Draw the `texture` part of the atlas over the `world` area
+    fn quad(&mut self, world: Rect, texture: Rect, atlas_size: Vec2) {
+        let start = self.positions.len() as u32;
+        // texture coordinates go down, world coordinates go up
+        let corners = [
+            (Vec2::new(world.min.x, world.max.y), texture.min),
+            (world.max, Vec2::new(texture.max.x, texture.min.y)),
+            (Vec2::new(world.max.x, world.min.y), texture.max),
+            (world.min, Vec2::new(texture.min.x, texture.max.y)),
+        ];
+        for (position, uv) in corners {
+            self.positions.push([position.x, position.y, 0.]);
+            self.normals.push([0., 0., 1.]);
+            self.uvs.push((uv / atlas_size).to_array());
+        }
+        self.indices.extend([start, start + 2, start + 1, start, start + 3, start + 2]);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    fn build(self) -> Mesh {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, self.positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs);
+        mesh.set_indices(Some(Indices::U32(self.indices)));
+        mesh
+    }
+}
+
+// Same pseudo-random number for a cell every time the map is drawn, the decoration must not
+// use the simulation's random numbers
+fn cell_noise(cell: CellCoordinate, salt: u32) -> u32 {
+    let mut hash = (cell.x as u32).wrapping_mul(0x9e37_79b1)
+        ^ (cell.y as u32).wrapping_mul(0x85eb_ca77)
+        ^ salt.wrapping_mul(0xc2b2_ae3d);
+    hash ^= hash >> 15;
+    hash = hash.wrapping_mul(0x2c1b_3c6d);
+    hash ^= hash >> 12;
+    hash
+}
+
+fn pick<'a>(names: &[&'a str], cell: CellCoordinate, salt: u32) -> &'a str {
+    names[cell_noise(cell, salt) as usize % names.len()]
+}
+
+fn cell_rect(cell: CellCoordinate) -> Rect {
+    Rect::from_center_size(cell.to_position(), Vec2::splat(CELL_SIZE))
+}
+
+// Part of a ground tile for one quarter of a dirt cell, given whether there is a wall on the
+// vertical and horizontal sides of that quarter
+fn dirt_quarter(top: bool, left: bool, vertical_wall: bool, horizontal_wall: bool, plain: &'static str) -> &'static str {
+    match (vertical_wall, horizontal_wall, top, left) {
+        (true, true, true, true) => DIRT_TOP_LEFT,
+        (true, true, true, false) => DIRT_TOP_RIGHT,
+        (true, true, false, true) => DIRT_BOTTOM_LEFT,
+        (true, true, false, false) => DIRT_BOTTOM_RIGHT,
+        (true, false, true, _) => DIRT_TOP,
+        (true, false, false, _) => DIRT_BOTTOM,
+        (false, true, _, true) => DIRT_LEFT,
+        (false, true, _, false) => DIRT_RIGHT,
+        (false, false, _, _) => plain,
+    }
+}
+
+fn add_ground(ground: &mut ChunkMesh, atlas: &TileAtlas, cell: CellCoordinate, blocked: &impl Fn(CellCoordinate) -> bool) {
+    let world = cell_rect(cell);
+    if blocked(cell) {
+        ground.quad(world, atlas.rect(pick(&GRASS, cell, 0)), atlas.size);
+        return;
+    }
+
+    let plain = pick(&DIRT, cell, 0);
+    for (top, left) in [(true, true), (true, false), (false, true), (false, false)] {
+        let vertical = CellCoordinate { x: cell.x, y: cell.y + if top { 1 } else { -1 } };
+        let horizontal = CellCoordinate { x: cell.x + if left { -1 } else { 1 }, y: cell.y };
+        let tile = atlas.rect(dirt_quarter(top, left, blocked(vertical), blocked(horizontal), plain));
+
+        let half_tile = tile.half_size();
+        let texture_min = Vec2::new(if left { tile.min.x } else { tile.min.x + half_tile.x },
+                                    if top { tile.min.y } else { tile.min.y + half_tile.y });
+        let world_min = Vec2::new(if left { world.min.x } else { world.center().x },
+                                  if top { world.center().y } else { world.min.y });
+        ground.quad(Rect::from_corners(world_min, world_min + world.half_size()),
+                    Rect::from_corners(texture_min, texture_min + half_tile),
+                    atlas.size);
+    }
+}
+
+fn add_prop(props: &mut ChunkMesh, atlas: &TileAtlas, name: &str, bottom_center: Vec2) {
+    let texture = atlas.rect(name);
+    let size = texture.size() * PIXEL_SCALE;
+    let world = Rect::from_corners(bottom_center - Vec2::new(size.x / 2., 0.), bottom_center + Vec2::new(size.x / 2., size.y));
+    props.quad(world, texture, atlas.size);
+}
+
+// Trees grow in the middle of thick walls, fences along thin ones, rocks on the rest and small
+// plants on the ground
+fn add_props(props: &mut ChunkMesh, atlas: &TileAtlas, cell: CellCoordinate, blocked: &impl Fn(CellCoordinate) -> bool) {
+    let neighbor = |dx: i32, dy: i32| blocked(CellCoordinate { x: cell.x + dx, y: cell.y + dy });
+    let bottom = cell.to_position() - Vec2::new(0., CELL_SIZE / 2.);
+    let chance = cell_noise(cell, 1) % 100;
+
+    if !blocked(cell) {
+        if chance < GROUND_PROP_CHANCE {
+            add_prop(props, atlas, pick(&GROUND_PROPS, cell, 2), bottom + Vec2::new(0., CELL_SIZE / 4.));
+        }
+        return;
+    }
+
+    let thick = (-1..=1).all(|dx| (-1..=1).all(|dy| neighbor(dx, dy)));
+    let horizontal = (neighbor(-1, 0) || neighbor(1, 0)) && !neighbor(0, 1) && !neighbor(0, -1);
+    if thick && cell.x.rem_euclid(3) == 0 && cell.y.rem_euclid(3) == 0 {
+        add_prop(props, atlas, pick(&TREES, cell, 2), bottom - Vec2::new(0., CELL_SIZE));
+    } else if horizontal {
+        add_prop(props, atlas, FENCE, bottom);
+    } else if chance < ROCK_CHANCE {
+        add_prop(props, atlas, pick(&ROCKS, cell, 2), bottom + Vec2::new(0., CELL_SIZE / 4.));
+    }
+}
+
+// Draw a map once it has been built and the tileset is ready
+fn spawn_tilemap(
+    mut commands: Commands,
+    atlas: Option<Res<TileAtlas>>,
+    map_query: Query<&Map>,
+    chunk_query: Query<(), With<TileChunk>>,
+    mut meshes: ResMut<Assets<Mesh>>
+) {
+    let (Some(atlas), Ok(map)) = (atlas, map_query.get_single()) else {
+        return;
+    };
+    if !chunk_query.is_empty() {
+        return;
+    }
+
+    // the outside of the map looks like a wall, so that the map is framed with grass
+    let blocked = |cell: CellCoordinate| !map.in_map(cell) || map.has_wall(&cell);
+    let half_width = (map.width() / 2) as i32;
+    let half_height = (map.height() / 2) as i32;
+
+    for chunk_x in (-half_width..=half_width).step_by(CHUNK_CELLS as usize) {
+        for chunk_y in (-half_height..=half_height).step_by(CHUNK_CELLS as usize) {
+            let mut ground = ChunkMesh::default();
+            let mut props = ChunkMesh::default();
+            // from the top down, so that props overlap the ones behind them
+            for y in (chunk_y..(chunk_y + CHUNK_CELLS).min(half_height + 1)).rev() {
+                for x in chunk_x..(chunk_x + CHUNK_CELLS).min(half_width + 1) {
+                    let cell = CellCoordinate { x, y };
+                    add_ground(&mut ground, &atlas, cell, &blocked);
+                    add_props(&mut props, &atlas, cell, &blocked);
+                }
+            }
+
+            for (mesh, z) in [(ground, GROUND_Z), (props, PROPS_Z)] {
+                if mesh.is_empty() {
+                    continue;
+                }
+                commands.spawn((MaterialMesh2dBundle {
+                    mesh: meshes.add(mesh.build()).into(),
+                    material: atlas.material.clone(),
+                    transform: Transform::from_xyz(0., 0., z),
+                    ..default()
+                }, TileChunk));
+            }
+        }
+    }
+}
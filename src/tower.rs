@@ -1,46 +1,138 @@
-use bevy::{
-    prelude::*,
-    sprite::MaterialMesh2dBundle,
-    input::mouse::{MouseButtonInput, MouseMotion, MouseWheel}, window::PrimaryWindow
-};
-
-use crate::{enemy::{EnemyStats, WaveTimer, ENEMY_SPAWN_INTERVAL_SECONDS}, base::BASE_RADIUS};
-use crate::bullet::{BULLET_COLOR, BULLET_RADIUS, Bullet};
-use super::GameState;
-use crate::game::{fall_off_damage_curve, euclidean_distance, HEAL_AMOUNT};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{enemy::{EnemyStats, WaveTimer}, base::{BASE_RADIUS, BASE_FOOTPRINT_CELLS}};
+use crate::bullet::Bullet;
+use super::{GameState, despawn_with_component, new_game_schedule, TEXT_COLOR};
+use crate::game::{compute_scale, euclidean_distance, health_size, Coins};
+use crate::difficulty::{Difficulty, MatchDifficulty};
+use crate::config::GameConfig;
+use crate::blast::{BlastProfile, BlastTarget};
+use crate::heal::{HealCharges, heal_reach, restore_health};
+use crate::synergy::Synergy;
 use crate::base::Base;
 use crate::season::Season;
+use crate::map::{Map, CellCoordinate};
+use crate::controls::{Action, Pointer};
+use crate::simulation::{Interpolated, PlayerCommand, PendingCommands, CommandLog, ScheduledCommand, SimulationTick, MatchStats, SimulationEvent};
+use crate::replay::replay_inactive;
 
+// Towers are simulated by the `SimulationPlugin`, this plugin turns the player's clicks and
+// keys into commands for it and draws the towers
 pub struct TowerPlugin;
 
 pub const TOWER_RADIUS: f32 = 25.;
-pub const TOWER_COLOR: Color = Color::PURPLE;
-pub const TOWER_INITIAL_HEALTH: f32 = 100.;
+const PREVIEW_ALPHA: f32 = 0.5;
+const PREVIEW_DAMAGE_COLOR: Color = Color::ORANGE;
+const PREVIEW_DESTROYED_COLOR: Color = Color::RED;
+const PREVIEW_INVALID_COLOR: Color = Color::rgba(1., 0.2, 0.2, 0.5);
+const RELOCATING_ALPHA: f32 = 0.3;
 
 impl Plugin for TowerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(place_tower)
-        .add_system(sync_size)
-        .add_system(shoot_enemies)
-        .add_system(heal_tower_and_base);
+        // the player's input is ignored while a replay is playing back
+        app.init_resource::<SelectedTowerKind>()
+        .add_system(place_tower.run_if(in_state(GameState::Game)).run_if(replay_inactive))
+        .add_system(cycle_tower_kind.run_if(in_state(GameState::Game)).run_if(replay_inactive))
+        .add_system(tower_kind_text_setup.in_schedule(new_game_schedule()))
+        .add_system(update_tower_kind_text.run_if(in_state(GameState::Game)))
+        .add_system(heal_tower_and_base.run_if(in_state(GameState::Game)).run_if(replay_inactive))
+        .add_system(preview_placement.run_if(in_state(GameState::Game)).run_if(replay_inactive))
+        .add_system(sell_or_relocate_tower.run_if(in_state(GameState::Game)).run_if(replay_inactive))
+        .add_system(cancel_relocation.run_if(in_state(GameState::Game)))
+        .add_system(add_tower_sprite)
+        .add_system(show_relocating_towers)
+        .add_system(despawn_with_component::<PlacementPreview>.in_schedule(OnExit(GameState::Game)))
+        .add_system(despawn_with_component::<DamagePreviewLabel>.in_schedule(OnExit(GameState::Game)))
+        .add_system(despawn_with_component::<TowerKindText>.in_schedule(OnEnter(GameState::Menu)));
     }
 }
 
-#[derive(Component, Default, Debug)]
+// Towers all shoot the same way. They differ by the blast they make when they are put down, and
+// support towers also boost the towers around them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TowerKind {
+    #[default]
+    Turret,
+    Mortar,
+    Bunker,
+    Amplifier,
+    Supply,
+}
+
+impl TowerKind {
+    pub const ALL: [TowerKind; 5] = [TowerKind::Turret, TowerKind::Mortar, TowerKind::Bunker, TowerKind::Amplifier, TowerKind::Supply];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            TowerKind::Turret => "Turret",
+            TowerKind::Mortar => "Mortar",
+            TowerKind::Bunker => "Bunker",
+            TowerKind::Amplifier => "Amplifier",
+            TowerKind::Supply => "Supply",
+        }
+    }
+
+    // Tint of the turret sprite, told apart at a glance
+    fn color(self) -> Color {
+        match self {
+            TowerKind::Turret => Color::WHITE,
+            TowerKind::Mortar => Color::rgb(1., 0.7, 0.4),
+            TowerKind::Bunker => Color::rgb(0.5, 0.7, 1.),
+            TowerKind::Amplifier => Color::rgb(1., 0.45, 0.85),
+            TowerKind::Supply => Color::rgb(0.5, 1., 0.5),
+        }
+    }
+
+    fn next(self) -> TowerKind {
+        let index = TowerKind::ALL.iter().position(|&kind| kind == self).unwrap_or(0);
+        TowerKind::ALL[(index + 1) % TowerKind::ALL.len()]
+    }
+}
+
+// Kind of tower put down by the next click
+#[derive(Resource, Default)]
+pub struct SelectedTowerKind(pub TowerKind);
+
+#[derive(Component)]
+struct TowerKindText;
+
+// Ghost turret that follows the cursor during the build season
+#[derive(Component)]
+pub struct PlacementPreview;
+
+// Tower that has been picked up and is waiting to be dropped on a new cell. It keeps firing
+// from its old cell until it is dropped.
+#[derive(Component)]
+pub struct Relocating;
+
+// Text shown above a tower or the base with the damage a placement at the cursor would do
+#[derive(Component)]
+pub struct DamagePreviewLabel;
+
+#[derive(Component, Default, Debug, Clone, Serialize, Deserialize)]
 pub struct TowerStats {
     pub x: f32,
     pub y: f32,
+    #[serde(default)]
+    pub kind: TowerKind,
     pub level: u32,
     pub range: f32,
     pub damage: f32,
     pub upgrade_price: u32,
     pub speed: f32,
-    pub health: f32
+    pub health: f32,
+    // games saved before there was a maximum get it back from the difficulty when they are loaded
+    #[serde(default)]
+    pub max_health: f32
 }
 
 #[derive(Component, Default)]
 pub struct TowerState {
     pub timer: Timer,
+    pub kills: u32,
+    pub damage_dealt: f32,
 }
 
 
@@ -48,40 +140,45 @@ pub struct TowerState {
 pub struct TowerBundle {
     pub stats: TowerStats,
     pub state: TowerState,
+    pub synergy: Synergy,
 }
 
 impl TowerBundle {
-    pub fn new(x: f32, y: f32) -> Self {
+    pub fn new(x: f32, y: f32, kind: TowerKind, difficulty: Difficulty, config: &GameConfig) -> Self {
         Self {
             stats: TowerStats {
-                x: x,
-                y: y,
+                x,
+                y,
+                kind,
                 level: 1,
-                range: 128.0,
-                damage: 100.,
-                upgrade_price: 10,
-                speed: 500.0,
-                health: TOWER_INITIAL_HEALTH
+                range: config.tower.range,
+                damage: config.tower.damage,
+                upgrade_price: config.tower.upgrade_price,
+                speed: config.tower.bullet_speed,
+                health: difficulty.tower_health(config),
+                max_health: difficulty.tower_health(config) * config.heal.max_health
             },
             state: TowerState {
-                timer: Timer::from_seconds(0.05, TimerMode::Repeating),
+                timer: Timer::from_seconds(config.tower.cooldown_seconds, TimerMode::Repeating),
+                ..default()
             },
-            ..Default::default()
+            synergy: Synergy::default(),
         }
     }
 }
 
-fn shoot_enemies(
-    mut commands: Commands, 
-    time: Res<Time>, 
-    mut tower_query: Query<(&TowerStats, &mut TowerState)>, 
-    enemy_query: Query<(Entity, &Transform), With<EnemyStats>>, 
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>> ) {
-
-    for (tower_stat, mut tower_state) in tower_query.iter_mut() {
-        // only fire if the tower is not on cooldown
-        tower_state.timer.tick(time.delta());
+pub fn shoot_enemies(
+    mut commands: Commands,
+    fixed_time: Res<FixedTime>,
+    mut tower_query: Query<(Entity, &TowerStats, &mut TowerState, &Synergy)>,
+    enemy_query: Query<(Entity, &Transform), With<EnemyStats>>,
+    mut simulation_events: EventWriter<SimulationEvent>) {
+
+    for (tower_entity, tower_stat, mut tower_state, synergy) in tower_query.iter_mut() {
+        // only fire if the tower is not on cooldown, a faster fire rate runs down the cooldown
+        // faster. Towers without a boost run it down by exactly one step.
+        let period = if synergy.fire_rate == 1. { fixed_time.period } else { fixed_time.period.mul_f32(synergy.fire_rate) };
+        tower_state.timer.tick(period);
         if !tower_state.timer.finished() {
             continue;
         }
@@ -89,163 +186,604 @@ fn shoot_enemies(
         // find the closest enemy
         let closest_result = enemy_query.iter()
             .map(|(enemy, transform)| (enemy, ((transform.translation.x - tower_stat.x).powi(2) + (transform.translation.y - tower_stat.y).powi(2)).sqrt()))
-            .min_by(|(enemy1, distance1), (enemy2, distance2)| distance1.total_cmp(distance2));
+            .min_by(|(_, distance1), (_, distance2)| distance1.total_cmp(distance2));
 
         // if there was a closest enemy, fire a bullet if they're within range
         if let Some((closest_enemy, closest_distance)) = closest_result {
             if closest_distance < tower_stat.range {
                 commands.spawn((
-                    MaterialMesh2dBundle {
-                        mesh: meshes.add(shape::Circle::new(BULLET_RADIUS).into()).into(),
-                        material: materials.add(ColorMaterial::from(BULLET_COLOR)),
-                        transform: Transform::from_xyz(tower_stat.x, tower_stat.y, 0.),
-                        ..default()
-                    },
+                    TransformBundle::from_transform(Transform::from_xyz(tower_stat.x, tower_stat.y, 0.)),
                     Bullet {
                         target: closest_enemy,
-                        damage: tower_stat.damage,
+                        source: tower_entity,
+                        damage: tower_stat.damage * synergy.damage,
                         speed: tower_stat.speed
                     },
+                    Interpolated::new(Vec3::new(tower_stat.x, tower_stat.y, 0.)),
                 ));
+                simulation_events.send(SimulationEvent::Shot { position: Vec2::new(tower_stat.x, tower_stat.y) });
             }
         }
     }
 }
 
-fn place_tower(
-    mut commands: Commands, 
-    mouse_button_input: Res<Input<MouseButton>>, 
-    primary_window_query: Query<&Window, With<PrimaryWindow>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
+// Apply the oldest queued player command. Commands only take effect through `Commands` at the
+// end of the step, so one command per step keeps every command seeing the previous one's result.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn apply_player_commands(
+    mut commands: Commands,
+    mut pending_commands: ResMut<PendingCommands>,
+    simulation_tick: Res<SimulationTick>,
+    mut command_log: ResMut<CommandLog>,
     current_season: Res<State<Season>>,
-    game_state: Res<State<GameState>>,
     mut update_game_state: ResMut<NextState<GameState>>,
-    mut other_towers_query: Query<(Entity, &mut TowerStats, &Transform)>,
-    mut enemies_query: Query<(Entity, &mut EnemyStats, &Transform)>,
-    mut base_query: Query<(&mut Base, &Transform)>,
-    asset_server: Res<AssetServer>
+    mut towers_query: Query<(Entity, &mut TowerStats, &mut Transform, &mut CellCoordinate)>,
+    mut enemies_query: Query<(Entity, &mut EnemyStats, &Transform), Without<TowerStats>>,
+    mut base_query: Query<(&mut Base, &Transform), (Without<TowerStats>, Without<EnemyStats>)>,
+    map_query: Query<&Map>,
+    mut coins: ResMut<Coins>,
+    // grouped to stay within the number of parameters a system can take
+    (mut wave_timer, mut heal_charges): (ResMut<WaveTimer>, ResMut<HealCharges>),
+    mut match_stats: ResMut<MatchStats>,
+    match_difficulty: Res<MatchDifficulty>,
+    config: Res<GameConfig>,
+    mut simulation_events: EventWriter<SimulationEvent>
 ) {
+    let Some(command) = pending_commands.0.pop_front() else {
+        return;
+    };
+    command_log.0.push(ScheduledCommand { tick: simulation_tick.0, command });
+
+    let Ok(map) = map_query.get_single() else {
+        return;
+    };
+
+    let season = current_season.0;
+    match command {
+        PlayerCommand::PlaceTower { cell, kind } => {
+            if season != Season::Build
+                || !can_place_tower(cell, map, towers_query.iter().map(|(_, _, _, tower_cell)| tower_cell)) {
+                return;
+            }
 
-    if game_state.0 == GameState::Game && current_season.0 == Season::Build {
+            apply_placement_damage(cell, None, config.blasts.profile(kind), &mut commands, &mut update_game_state,
+                &mut towers_query, &mut enemies_query, &mut base_query, &mut match_stats, &mut simulation_events);
 
-        let Ok(window) = primary_window_query.get_single() else {
+            let Vec2 { x, y } = cell.to_position();
+            match_stats.towers_built += 1;
+            commands.spawn((TowerBundle::new(x, y, kind, match_difficulty.0, &config), cell,
+                TransformBundle::from_transform(Transform::from_xyz(x, y, 3.).with_scale(Vec3::splat(0.06)))));
+        }
+        PlayerCommand::RelocateTower { from, to } => {
+            if !relocation_allowed(season, &config)
+                || !can_place_tower(to, map, towers_query.iter().map(|(_, _, _, tower_cell)| tower_cell)) {
                 return;
-        };
+            }
+            let Some(tower_entity) = tower_at_cell(from, towers_query.iter().map(|(entity, _, _, cell)| (entity, cell))) else {
+                return;
+            };
+            let Ok((_, &TowerStats { kind, .. }, _, _)) = towers_query.get(tower_entity) else {
+                return;
+            };
+
+            apply_placement_damage(to, Some(tower_entity), config.blasts.profile(kind), &mut commands, &mut update_game_state,
+                &mut towers_query, &mut enemies_query, &mut base_query, &mut match_stats, &mut simulation_events);
+
+            // a relocated tower keeps its health and only moves to the new cell
+            let Vec2 { x, y } = to.to_position();
+            if let Ok((_, mut tower_stat, mut tower_transform, mut tower_cell)) = towers_query.get_mut(tower_entity) {
+                tower_stat.x = x;
+                tower_stat.y = y;
+                tower_transform.translation.x = x;
+                tower_transform.translation.y = y;
+                *tower_cell = to;
+            }
+        }
+        PlayerCommand::SellTower { cell } => {
+            let Some(tower_entity) = tower_at_cell(cell, towers_query.iter().map(|(entity, _, _, cell)| (entity, cell))) else {
+                return;
+            };
+            if let Ok((_, tower_stat, _, _)) = towers_query.get(tower_entity) {
+                coins.0 += sell_value(tower_stat, &config);
+                commands.entity(tower_entity).despawn();
+            }
+        }
+        PlayerCommand::Heal { x, y } => {
+            if season != Season::Heal || heal_charges.0 == 0 {
+                return;
+            }
 
-        if let Some(_position) = window.cursor_position() {
-            if mouse_button_input.just_pressed(MouseButton::Left) {
-                let x = _position.x - window.width() / 2.0;
-                let y = _position.y - window.height() / 2.0;
-
-                                
-                for (tower_entity, mut tower_stat, tower_transform) in other_towers_query.iter_mut() {
-                    let distance = euclidean_distance(x, y, tower_transform.translation.x, tower_transform.translation.y);
-                    let damage = fall_off_damage_curve(distance, 100., 10., 4.);
-                    if tower_stat.health >= damage {
-                        tower_stat.health -= damage;
-                    } else {
-                        info!("tower despawned");
-                        commands.entity(tower_entity).despawn();
+            let amount = match_difficulty.0.heal_amount(&config);
+            let mut restored = 0.;
+
+            for (_, mut tower_stat, tower_transform, _) in towers_query.iter_mut() {
+                let distance = euclidean_distance(x, y, tower_transform.translation.x, tower_transform.translation.y);
+                if distance < heal_reach(TOWER_RADIUS * health_size(tower_stat.health, tower_stat.max_health), &config.heal) {
+                    let max_health = tower_stat.max_health;
+                    let healed = restore_health(&mut tower_stat.health, max_health, amount);
+                    if healed > 0. {
+                        restored += healed;
+                        simulation_events.send(SimulationEvent::Healed { position: tower_transform.translation.truncate() });
                     }
                 }
+            }
 
-                for (enemy_entity, mut enemy_stat, enemy_transform) in enemies_query.iter_mut() {
-                    let distance = euclidean_distance(x, y, enemy_transform.translation.x, enemy_transform.translation.y);
-                    let damage = fall_off_damage_curve(distance, 200., 10., 4.);
-                    if enemy_stat.health >= damage {
-                        enemy_stat.health -= damage;
-                    } else {
-                        info!("enemy despawned");
-                        commands.entity(enemy_entity).despawn();
+            for (mut base, base_transform) in base_query.iter_mut() {
+                let distance = euclidean_distance(x, y, base_transform.translation.x, base_transform.translation.y);
+                if distance < heal_reach(BASE_RADIUS * health_size(base.health, base.max_health), &config.heal) {
+                    let max_health = base.max_health;
+                    let healed = restore_health(&mut base.health, max_health, amount);
+                    if healed > 0. {
+                        restored += healed;
+                        simulation_events.send(SimulationEvent::Healed { position: base_transform.translation.truncate() });
                     }
                 }
+            }
 
-                for (mut base, base_transform) in base_query.iter_mut() {
-                    let distance = euclidean_distance(x, y, base_transform.translation.x, base_transform.translation.y);
-                    let damage = fall_off_damage_curve(distance, 100., 10., 4.);
-                    if base.health >= damage {
-                        base.health -= damage;
-                    } else {
-                        info!("base destroyed");
-                        update_game_state.set(GameState::GameLost);
-                    }
-                }
+            // a click that heals nothing, with everything around at full health, is free
+            if restored > 0. {
+                heal_charges.0 -= 1;
+                config.heal.consequence.apply(&mut wave_timer, restored / amount,
+                    match_difficulty.0.enemies_per_interval(&config));
+            }
+        }
+    }
+}
 
-                // commands.spawn((
-                //     TowerBundle::new(x, y),
-                //     MaterialMesh2dBundle {
-                //         mesh: meshes.add(shape::Circle::new(TOWER_RADIUS).into()).into(),
-                //         material: materials.add(ColorMaterial::from(TOWER_COLOR)),
-                //         transform: Transform::from_xyz(x, y, 3.),
-                //         ..default()
-                // }));
+// Putting a tower down on `cell` damages everything around it with the blast of its kind. `moved`
+// is a tower being relocated there, which is not hurt by its own landing.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn apply_placement_damage(
+    cell: CellCoordinate,
+    moved: Option<Entity>,
+    blast: &BlastProfile,
+    commands: &mut Commands,
+    update_game_state: &mut NextState<GameState>,
+    towers_query: &mut Query<(Entity, &mut TowerStats, &mut Transform, &mut CellCoordinate)>,
+    enemies_query: &mut Query<(Entity, &mut EnemyStats, &Transform), Without<TowerStats>>,
+    base_query: &mut Query<(&mut Base, &Transform), (Without<TowerStats>, Without<EnemyStats>)>,
+    match_stats: &mut MatchStats,
+    simulation_events: &mut EventWriter<SimulationEvent>
+) {
+    let Vec2 { x, y } = cell.to_position();
+    simulation_events.send(SimulationEvent::PlacementBlast { position: Vec2::new(x, y) });
 
-                commands.spawn((TowerBundle::new(x, y), 
-                    SpriteBundle {
-                        texture:  asset_server.load("turret.png"),
-                        transform: Transform::from_xyz(x, y, 3.).with_scale(Vec3::splat(0.06)),
-                        ..default()
-                    }));
+    for (tower_entity, mut tower_stat, tower_transform, _) in towers_query.iter_mut() {
+        if Some(tower_entity) == moved {
+            continue;
+        }
+        let distance = euclidean_distance(x, y, tower_transform.translation.x, tower_transform.translation.y);
+        let damage = blast.damage(distance, BlastTarget::Tower);
+        if withstands(tower_stat.health, damage) {
+            tower_stat.health -= damage;
+        } else {
+            info!("tower despawned");
+            match_stats.towers_lost += 1;
+            simulation_events.send(SimulationEvent::TowerDestroyed { position: tower_transform.translation.truncate() });
+            commands.entity(tower_entity).despawn();
+        }
+    }
 
-                
-            }   
+    for (enemy_entity, mut enemy_stat, enemy_transform) in enemies_query.iter_mut() {
+        let distance = euclidean_distance(x, y, enemy_transform.translation.x, enemy_transform.translation.y);
+        let damage = blast.damage(distance, BlastTarget::Enemy);
+        if withstands(enemy_stat.health, damage) {
+            enemy_stat.health -= damage;
+        } else {
+            info!("enemy despawned");
+            // the enemy is only despawned at the end of the step, it must not reach the base before that
+            enemy_stat.health = 0.;
+            match_stats.kills += 1;
+            simulation_events.send(SimulationEvent::EnemyKilled { position: enemy_transform.translation.truncate() });
+            commands.entity(enemy_entity).despawn();
+        }
     }
+
+    for (mut base, base_transform) in base_query.iter_mut() {
+        let distance = euclidean_distance(x, y, base_transform.translation.x, base_transform.translation.y);
+        let damage = blast.damage(distance, BlastTarget::Base);
+        if withstands(base.health, damage) {
+            base.health -= damage;
+        } else {
+            info!("base destroyed");
+            base.health = 0.;
+            update_game_state.set(GameState::GameLost);
+        }
     }
 }
 
-fn heal_tower_and_base(
-    mut commands: Commands, 
-    mouse_button_input: Res<Input<MouseButton>>, 
-    primary_window_query: Query<&Window, With<PrimaryWindow>>,
+// Whether something with `health` left survives `damage` in one hit, it is destroyed otherwise.
+// Blasts and boss stings both go through this, so that they agree on a hit taking exactly the
+// health left.
+pub fn withstands(health: f32, damage: f32) -> bool {
+    health >= damage
+}
+
+fn tower_at_cell<'a>(cell: CellCoordinate,
+    mut towers: impl Iterator<Item = (Entity, &'a CellCoordinate)>) -> Option<Entity> {
+    towers.find(|(_, &tower_cell)| tower_cell == cell).map(|(tower_entity, _)| tower_entity)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn place_tower(
+    mut commands: Commands,
+    actions: Res<Input<Action>>,
+    pointer: Res<Pointer>,
     current_season: Res<State<Season>>,
     game_state: Res<State<GameState>>,
-    mut towers_query: Query<(&mut TowerStats, &Transform)>,
-    mut base_query: Query<(&mut Base, &Transform)>,
-    mut wave_timer: ResMut<WaveTimer>
+    relocating_query: Query<(Entity, &CellCoordinate), With<Relocating>>,
+    occupied_query: Query<&CellCoordinate, With<TowerStats>>,
+    map_query: Query<&Map>,
+    config: Res<GameConfig>,
+    selected_kind: Res<SelectedTowerKind>,
+    hud_query: Query<&Interaction>,
+    mut pending_commands: ResMut<PendingCommands>
 ) {
 
-    if game_state.0 == GameState::Game && current_season.0 == Season::Heal {
+    let relocating = relocating_query.get_single().ok();
 
-        let Ok(window) = primary_window_query.get_single() else {
-                return;
+    if game_state.0 == GameState::Game && placement_allowed(current_season.0, relocating.is_some(), &config) {
+
+        let Ok(map) = map_query.get_single() else {
+            return;
         };
 
-        if let Some(_position) = window.cursor_position() {
-            if mouse_button_input.just_pressed(MouseButton::Left) {
-                let x = _position.x - window.width() / 2.0;
-                let y = _position.y - window.height() / 2.0;
-
-                                
-                for (mut tower_stat, tower_transform) in towers_query.iter_mut() {
-                    let distance = euclidean_distance(x, y, tower_transform.translation.x, tower_transform.translation.y);
-                    if distance < TOWER_RADIUS * tower_transform.scale.x / 0.06{
-                        tower_stat.health += HEAL_AMOUNT;
-                        wave_timer.force_wave = true;
-                    }
+        if let Some(_position) = pointer.world_position {
+            if actions.just_pressed(Action::Place) && !cursor_over_hud(&hud_query) {
+                let cell = CellCoordinate::from_position(_position);
+                if !can_place_tower(cell, map, occupied_query.iter()) {
+                    return;
                 }
 
-                for (mut base, base_transform) in base_query.iter_mut() {
-                    let distance = euclidean_distance(x, y, base_transform.translation.x, base_transform.translation.y);
-                    if distance < BASE_RADIUS * base_transform.scale.x / 0.06 {
-                        base.health += HEAL_AMOUNT;
-                        wave_timer.force_wave = true;
-                    }
+                if let Some((tower_entity, &from)) = relocating {
+                    commands.entity(tower_entity).remove::<Relocating>();
+                    pending_commands.0.push_back(PlayerCommand::RelocateTower { from, to: cell });
+                } else {
+                    pending_commands.0.push_back(PlayerCommand::PlaceTower { cell, kind: selected_kind.0 });
                 }
-                
-            }   
+            }
+    }
+    }
+}
+
+// The tower type action picks the kind of the next towers, in turn
+fn cycle_tower_kind(actions: Res<Input<Action>>, mut selected_kind: ResMut<SelectedTowerKind>) {
+    if actions.just_pressed(Action::CycleTowerType) {
+        selected_kind.0 = selected_kind.0.next();
+    }
+}
+
+fn tower_kind_text_setup(mut commands: Commands,
+    selected_kind: Res<SelectedTowerKind>,
+    asset_server: Res<AssetServer>) {
+
+    commands.spawn((
+        TextBundle::from_section(
+            format!("Tower: {}", selected_kind.0.label()),
+            TextStyle {
+                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                font_size: 24.0,
+                color: TEXT_COLOR,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(10.0),
+                bottom: Val::Px(45.0),
+                ..default()
+            },
+            ..default()
+        }),
+        TowerKindText,
+    ));
+}
+
+fn update_tower_kind_text(selected_kind: Res<SelectedTowerKind>, mut text_query: Query<&mut Text, With<TowerKindText>>) {
+    if !selected_kind.is_changed() {
+        return;
+    }
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = format!("Tower: {}", selected_kind.0.label());
+    }
+}
+
+fn add_tower_sprite(mut commands: Commands,
+    tower_query: Query<(Entity, &TowerStats), Added<TowerStats>>,
+    asset_server: Res<AssetServer>) {
+
+    for (tower_entity, tower_stat) in tower_query.iter() {
+        commands.entity(tower_entity).insert((
+            asset_server.load::<Image, _>("turret.png"),
+            Sprite {
+                color: tower_stat.kind.color(),
+                ..default()
+            },
+            VisibilityBundle::default()));
+    }
+}
+
+// A picked up tower fades out until it is dropped
+fn show_relocating_towers(mut tower_query: Query<(&TowerStats, &mut Sprite, Option<&Relocating>)>) {
+    for (tower_stat, mut sprite, relocating) in tower_query.iter_mut() {
+        let alpha = if relocating.is_some() { RELOCATING_ALPHA } else { 1. };
+        let color = *tower_stat.kind.color().set_a(alpha);
+        if sprite.color != color {
+            sprite.color = color;
+        }
+    }
+}
+
+// New towers go down in the build season, picked up towers can also be dropped in any season that allows relocation
+fn placement_allowed(season: Season, relocating: bool, config: &GameConfig) -> bool {
+    season == Season::Build || (relocating && relocation_allowed(season, config))
+}
+
+// Towers can only be picked up in the seasons listed in the game config
+pub fn relocation_allowed(season: Season, config: &GameConfig) -> bool {
+    config.tower.relocation_seasons.contains(&season)
+}
+
+// Coins given back for a tower, scaled down by the health it has lost
+pub fn sell_value(tower_stat: &TowerStats, config: &GameConfig) -> u32 {
+    let health_fraction = (tower_stat.health / tower_stat.max_health).clamp(0., 1.);
+    (config.tower.value as f32 * config.tower.sell_refund * health_fraction).round() as u32
+}
+
+// Tower under a world position
+pub fn tower_under_cursor<'a>(position: Vec2,
+    mut towers: impl Iterator<Item = (Entity, &'a TowerStats, &'a Transform)>) -> Option<(Entity, &'a TowerStats)> {
+    towers
+        .find(|(_, tower_stat, tower_transform)| {
+            euclidean_distance(position.x, position.y, tower_transform.translation.x, tower_transform.translation.y)
+                < TOWER_RADIUS * health_size(tower_stat.health, tower_stat.max_health)
+        })
+        .map(|(tower_entity, tower_stat, _)| (tower_entity, tower_stat))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn sell_or_relocate_tower(
+    mut commands: Commands,
+    actions: Res<Input<Action>>,
+    pointer: Res<Pointer>,
+    current_season: Res<State<Season>>,
+    towers_query: Query<(Entity, &TowerStats, &Transform), Without<Relocating>>,
+    cells_query: Query<&CellCoordinate>,
+    relocating_query: Query<Entity, With<Relocating>>,
+    config: Res<GameConfig>,
+    mut pending_commands: ResMut<PendingCommands>
+) {
+    // relocating again puts a picked up tower back where it was
+    if actions.just_pressed(Action::Relocate) {
+        if let Ok(tower_entity) = relocating_query.get_single() {
+            commands.entity(tower_entity).remove::<Relocating>();
+            return;
+        }
+    }
+
+    let Some(position) = pointer.world_position else {
+        return;
+    };
+
+    let Some((tower_entity, _)) = tower_under_cursor(position, towers_query.iter()) else {
+        return;
+    };
+
+    if actions.just_pressed(Action::Sell) {
+        if let Ok(&cell) = cells_query.get(tower_entity) {
+            pending_commands.0.push_back(PlayerCommand::SellTower { cell });
+        }
+    } else if actions.just_pressed(Action::Relocate) && relocation_allowed(current_season.0, &config) {
+        commands.entity(tower_entity).insert(Relocating);
+    }
+}
+
+// Drop a picked up tower back on its old cell when the season no longer allows relocation
+fn cancel_relocation(
+    mut commands: Commands,
+    current_season: Res<State<Season>>,
+    config: Res<GameConfig>,
+    relocating_query: Query<Entity, With<Relocating>>
+) {
+    if relocation_allowed(current_season.0, &config) {
+        return;
+    }
+
+    for tower_entity in relocating_query.iter() {
+        commands.entity(tower_entity).remove::<Relocating>();
+    }
+}
+
+// Clicks on the HUD should not also reach the map underneath it. Its buttons and bars track the
+// pointer with an `Interaction`.
+fn cursor_over_hud(hud_query: &Query<&Interaction>) -> bool {
+    hud_query.iter().any(|interaction| *interaction != Interaction::None)
+}
+
+// A tower can only go on an empty, wall-free cell of the map that is not under the base
+pub fn can_place_tower<'a>(cell: CellCoordinate, map: &Map,
+    mut occupied: impl Iterator<Item = &'a CellCoordinate>) -> bool {
+    let under_base = cell.x.abs() <= BASE_FOOTPRINT_CELLS && cell.y.abs() <= BASE_FOOTPRINT_CELLS;
+
+    map.in_map(cell) && !map.has_wall(&cell) && !under_base
+        && !occupied.any(|&tower_cell| tower_cell == cell)
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn preview_placement(
+    mut commands: Commands,
+    pointer: Res<Pointer>,
+    current_season: Res<State<Season>>,
+    game_state: Res<State<GameState>>,
+    mut preview_query: Query<(Entity, &mut Transform, &mut Sprite), With<PlacementPreview>>,
+    label_query: Query<Entity, With<DamagePreviewLabel>>,
+    towers_query: Query<(&TowerStats, &Transform), (Without<PlacementPreview>, Without<Relocating>)>,
+    base_query: Query<(&Base, &Transform), Without<PlacementPreview>>,
+    occupied_query: Query<&CellCoordinate, With<TowerStats>>,
+    relocating_query: Query<&TowerStats, With<Relocating>>,
+    map_query: Query<&Map>,
+    config: Res<GameConfig>,
+    selected_kind: Res<SelectedTowerKind>,
+    asset_server: Res<AssetServer>
+) {
+    // labels are rebuilt every frame since the cursor and the healths keep changing
+    for label_entity in label_query.iter() {
+        commands.entity(label_entity).despawn();
+    }
+
+    // a picked up tower is previewed with its own kind
+    let relocated_kind = relocating_query.get_single().ok().map(|tower_stat| tower_stat.kind);
+    let relocating = relocated_kind.is_some();
+    let kind = relocated_kind.unwrap_or(selected_kind.0);
+    let Some(position) = pointer.world_position.filter(|_| game_state.0 == GameState::Game && placement_allowed(current_season.0, relocating, &config)) else {
+        for (preview_entity, _, _) in preview_query.iter() {
+            commands.entity(preview_entity).despawn();
         }
+        return;
+    };
+
+    let Ok(map) = map_query.get_single() else {
+        return;
+    };
+
+    let cell = CellCoordinate::from_position(position);
+    let valid = can_place_tower(cell, map, occupied_query.iter());
+    let Vec2 { x, y } = cell.to_position();
+    let color = if valid { *kind.color().set_a(PREVIEW_ALPHA) } else { PREVIEW_INVALID_COLOR };
+
+    if let Ok((_, mut preview_transform, mut preview_sprite)) = preview_query.get_single_mut() {
+        preview_transform.translation.x = x;
+        preview_transform.translation.y = y;
+        preview_sprite.color = color;
+    } else {
+        commands.spawn((PlacementPreview,
+            SpriteBundle {
+                texture: asset_server.load("turret.png"),
+                sprite: Sprite {
+                    color,
+                    ..default()
+                },
+                transform: Transform::from_xyz(x, y, 3.5).with_scale(Vec3::splat(0.06)),
+                ..default()
+            }));
+    }
+
+    // nothing would be damaged by a click on an invalid cell
+    if !valid {
+        return;
+    }
+
+    let blast = config.blasts.profile(kind);
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let mut spawn_label = |health: f32, damage: f32, translation: Vec3| {
+        // out of reach of the blast
+        if damage <= 0. {
+            return;
+        }
+        let (text, color) = if health >= damage {
+            (format!("-{:.0}", damage), PREVIEW_DAMAGE_COLOR)
+        } else {
+            ("destroyed".to_string(), PREVIEW_DESTROYED_COLOR)
+        };
+        commands.spawn((DamagePreviewLabel,
+            Text2dBundle {
+                text: Text::from_section(text, TextStyle {
+                    font: font.clone(),
+                    font_size: 16.0,
+                    color
+                }).with_alignment(TextAlignment::Center),
+                transform: Transform::from_xyz(translation.x, translation.y + TOWER_RADIUS, 8.),
+                ..default()
+            }));
+    };
+
+    for (tower_stat, tower_transform) in towers_query.iter() {
+        let distance = euclidean_distance(x, y, tower_transform.translation.x, tower_transform.translation.y);
+        spawn_label(tower_stat.health, blast.damage(distance, BlastTarget::Tower), tower_transform.translation);
+    }
+
+    for (base, base_transform) in base_query.iter() {
+        let distance = euclidean_distance(x, y, base_transform.translation.x, base_transform.translation.y);
+        spawn_label(base.health, blast.damage(distance, BlastTarget::Base), base_transform.translation);
     }
 }
 
+fn heal_tower_and_base(
+    actions: Res<Input<Action>>,
+    pointer: Res<Pointer>,
+    current_season: Res<State<Season>>,
+    game_state: Res<State<GameState>>,
+    hud_query: Query<&Interaction>,
+    mut pending_commands: ResMut<PendingCommands>
+) {
 
+    if game_state.0 == GameState::Game && current_season.0 == Season::Heal {
 
-fn compute_scale(health: f32) -> f32 {
-    (health / TOWER_INITIAL_HEALTH).max(0.25) * 0.06
+        if let Some(_position) = pointer.world_position {
+            if actions.just_pressed(Action::Heal) && !cursor_over_hud(&hud_query) {
+                pending_commands.0.push_back(PlayerCommand::Heal { x: _position.x, y: _position.y });
+            }
+        }
+    }
 }
 
-fn sync_size(mut tower_query: Query<(&TowerStats, &mut Transform)>) {
+
+
+// Towers shrink as they lose health
+pub fn sync_size(mut tower_query: Query<(&TowerStats, &mut Transform)>) {
     for (tower_stat, mut tower_transform) in tower_query.iter_mut() {
-        tower_transform.scale = Vec3::splat(compute_scale(tower_stat.health));
+        tower_transform.scale = Vec3::splat(compute_scale(tower_stat.health, tower_stat.max_health));
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn check_sell_value_follows_the_health_left() {
+        use super::{TowerBundle, TowerKind, sell_value};
+        use crate::config::GameConfig;
+        use crate::difficulty::Difficulty;
+
+        let mut config = GameConfig::default();
+        config.tower.value = 50;
+        config.tower.sell_refund = 0.5;
+        config.tower.health = 100.;
+        config.heal.max_health = 1.5;
+        let mut tower_stat = TowerBundle::new(0., 0., TowerKind::Turret, Difficulty::Nightmare, &config).stats;
+
+        // the health is counted against the most the tower can be healed up to
+        assert_eq!(sell_value(&tower_stat, &config), 17);
+        tower_stat.health = tower_stat.max_health;
+        assert_eq!(sell_value(&tower_stat, &config), 25);
+        tower_stat.health = -10.;
+        assert_eq!(sell_value(&tower_stat, &config), 0);
+    }
+
+    #[test]
+    fn check_a_hit_taking_exactly_the_health_left_is_withstood() {
+        use super::withstands;
+
+        assert!(withstands(60., 60.));
+        assert!(withstands(60., 59.));
+        assert!(!withstands(60., 61.));
+    }
+
+    #[test]
+    fn check_relocation_follows_the_configured_seasons() {
+        use super::{placement_allowed, relocation_allowed};
+        use crate::config::GameConfig;
+        use crate::season::Season;
+
+        let mut config = GameConfig::default();
+        assert!(relocation_allowed(Season::Build, &config));
+        assert!(!relocation_allowed(Season::Heal, &config));
+        assert!(!placement_allowed(Season::Heal, true, &config));
+
+        config.tower.relocation_seasons = vec![Season::Heal];
+        assert!(!relocation_allowed(Season::Build, &config));
+        assert!(relocation_allowed(Season::Heal, &config));
+        // a picked up tower can be dropped in the heal season, new towers still cannot go down
+        assert!(placement_allowed(Season::Heal, true, &config));
+        assert!(!placement_allowed(Season::Heal, false, &config));
+    }
+}